@@ -12,6 +12,11 @@ pub trait ResourceId:
     fn from_bytes(bytes: &[u8]) -> Self {
         borsh::from_slice(bytes).expect("failed to deserialize ResourceId")
     }
+
+    /// Decodes a resource ID from untrusted bytes, returning `None` if they are malformed.
+    fn try_from_bytes(bytes: &[u8]) -> Option<Self> {
+        borsh::from_slice(bytes).ok()
+    }
 }
 impl<T> ResourceId for T where
     T: BorshSerialize
@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use vprogs_core_types::{AccessMetadata, AccessType};
 use vprogs_state_space::StateSpace;
-use vprogs_state_version::StateVersion;
+use vprogs_state_typed::{Codec, TypedResource, TypedState, TypedStateResult};
+use vprogs_state_version::{CorruptionError, StateVersion};
 use vprogs_storage_types::Store;
 
 use crate::{ResourceAccess, vm_interface::VmInterface};
@@ -10,6 +11,7 @@ use crate::{ResourceAccess, vm_interface::VmInterface};
 pub struct AccessHandle<'a, S: Store<StateSpace = StateSpace>, V: VmInterface> {
     state_version: Arc<StateVersion<V::ResourceId>>,
     access: &'a ResourceAccess<S, V>,
+    store: &'a S,
 }
 
 impl<'a, S: Store<StateSpace = StateSpace>, V: VmInterface> AccessHandle<'a, S, V> {
@@ -22,28 +24,128 @@ impl<'a, S: Store<StateSpace = StateSpace>, V: VmInterface> AccessHandle<'a, S,
         self.state_version.version()
     }
 
+    /// Returns the data of the accessed version, loading all chunks of a large value.
+    ///
+    /// # Panics
+    /// Panics if a chunk fails verification (see [`Self::try_data`]).
     #[inline]
     pub fn data(&self) -> &Vec<u8> {
-        self.state_version.data()
+        self.try_data().unwrap_or_else(|err| panic!("{err}"))
     }
 
+    /// Returns the data of the accessed version, loading all chunks of a large value.
+    ///
+    /// Returns an error if a chunk does not match the hash recorded for it.
+    pub fn try_data(&self) -> Result<&Vec<u8>, CorruptionError> {
+        self.state_version.load(self.store)?;
+        Ok(self.state_version.data())
+    }
+
+    /// Returns the length of the data without loading it.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.state_version.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.state_version.is_empty()
+    }
+
+    /// Returns the number of chunks the data consists of.
+    #[inline]
+    pub fn chunk_count(&self) -> usize {
+        self.state_version.chunk_count()
+    }
+
+    /// Returns a single chunk of the data, loading only that chunk.
+    #[inline]
+    pub fn chunk(&self, index: usize) -> Result<&[u8], CorruptionError> {
+        self.state_version.chunk(self.store, index)
+    }
+
+    /// Returns a single chunk of the data for modification, copying only that chunk.
+    #[inline]
+    pub fn chunk_mut(&mut self, index: usize) -> Result<&mut [u8], CorruptionError> {
+        self.state_version.chunk_mut(self.store, index)
+    }
+
+    /// Returns the error if the stored data of the accessed version failed verification.
+    #[inline]
+    pub fn corruption(&self) -> Option<&CorruptionError> {
+        self.state_version.corruption()
+    }
+
+    /// Returns the data of a new version, loading all chunks of a large value.
+    ///
+    /// # Panics
+    /// Panics if a chunk fails verification.
     #[inline]
     pub fn data_mut(&mut self) -> &mut Vec<u8> {
+        self.data();
         self.state_version.data_mut()
     }
 
+    /// Returns the decoded value of the accessed version, or `None` if the resource does not
+    /// exist.
+    ///
+    /// Returns an error if the stored data failed verification or can not be decoded.
+    pub fn value<T>(&self) -> TypedStateResult<Option<T::Value>>
+    where
+        T: TypedResource<ResourceId = V::ResourceId>,
+    {
+        if let Some(err) = self.corruption() {
+            return Err(err.clone().into());
+        }
+        self.try_data()?;
+        TypedState::<T>::value(&self.state_version)
+    }
+
+    /// Replaces the data of a new version with the encoded value, re-creating the resource if it
+    /// does not exist.
+    pub fn set_value<T>(&mut self, value: &T::Value)
+    where
+        T: TypedResource<ResourceId = V::ResourceId>,
+    {
+        self.state_version.set_data(T::Codec::encode(value))
+    }
+
+    /// Deletes the resource, which leaves a tombstone version without data.
+    ///
+    /// Deleting a resource that does not exist has no effect. Modifying the data afterwards
+    /// re-creates the resource.
+    #[inline]
+    pub fn delete(&mut self) {
+        self.state_version.mark_deleted()
+    }
+
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        self.state_version.is_deleted()
+    }
+
+    /// Returns whether the resource does not exist, either because it was never created or
+    /// because it was deleted.
     #[inline]
     pub fn is_new(&self) -> bool {
-        self.state_version.version() == 0
+        self.state_version.version() == 0 || self.state_version.is_deleted()
     }
 
-    pub(crate) fn new(access: &'a ResourceAccess<S, V>) -> Self {
-        Self { state_version: access.read_state(), access }
+    pub(crate) fn new(access: &'a ResourceAccess<S, V>, store: &'a S) -> Self {
+        Self { state_version: access.read_state(), access, store }
     }
 
+    /// Publishes the state left by the transaction.
+    ///
+    /// Writes that left the state unchanged keep the version that was read, so that they neither
+    /// create a new version nor persist anything.
     pub(crate) fn commit_changes(self) {
         if self.access.access_type() == AccessType::Write {
-            self.access.set_written_state(self.state_version.clone());
+            let read_state = self.access.read_state();
+            match self.state_version.has_same_state(&read_state) {
+                true => self.access.set_written_state(read_state),
+                false => self.access.set_written_state(self.state_version.clone()),
+            }
         }
     }
 
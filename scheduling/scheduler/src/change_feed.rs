@@ -0,0 +1,106 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+use vprogs_core_macros::smart_pointer;
+use vprogs_core_types::ResourceId;
+use vprogs_state_changeset::{Changeset, ChangesetResult};
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_types::ReadStore;
+
+/// An event of the change feed (see [`Scheduler::subscribe`](crate::Scheduler::subscribe)).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChangeEvent<R: ResourceId> {
+    /// A batch was committed. Every change carries the data the resource was changed to.
+    Commit(Changeset<R>),
+    /// A committed batch was reverted by a rollback. Carries the changes of the batch without
+    /// data, and is emitted for the newest batch first.
+    Retract(Changeset<R>),
+}
+
+/// Delivers the changes of committed and reverted batches to subscribers.
+///
+/// Events are emitted by the write worker once the commit or rollback has been written to the
+/// store, so they arrive in the order in which the store changed.
+#[smart_pointer]
+pub(crate) struct ChangeFeed<R: ResourceId> {
+    subscribers: Mutex<Vec<Subscriber<R>>>,
+}
+
+struct Subscriber<R: ResourceId> {
+    sender: UnboundedSender<ChangeEvent<R>>,
+    /// The index of the first batch the subscriber asked for.
+    from_index: u64,
+    /// The index of the next batch the subscriber expects to be committed.
+    next_index: u64,
+}
+
+impl<R: ResourceId> ChangeFeed<R> {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(ChangeFeedData { subscribers: Mutex::new(Vec::new()) }))
+    }
+
+    /// Returns whether anyone is subscribed, so that events are only built when needed.
+    pub(crate) fn has_subscribers(&self) -> bool {
+        !self.subscribers.lock().unwrap().is_empty()
+    }
+
+    /// Subscribes to the changes of all batches from `from_index` on.
+    ///
+    /// Batches that are already committed are replayed from the store first. The replay holds the
+    /// subscriber list, so no event is missed or delivered twice while it runs.
+    pub(crate) fn subscribe<S>(
+        &self,
+        store: &S,
+        from_index: u64,
+    ) -> ChangesetResult<UnboundedReceiver<ChangeEvent<R>>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let (sender, receiver) = unbounded_channel();
+
+        let from_index = from_index.max(1);
+        let last_committed_index = StateMetadata::last_committed_index(store);
+        for changeset in Changeset::iter(store, from_index..=last_committed_index, true) {
+            // The receiver is still held here, so sending can not fail.
+            let _ = sender.send(ChangeEvent::Commit(changeset?));
+        }
+
+        let next_index = from_index.max(last_committed_index + 1);
+        subscribers.push(Subscriber { sender, from_index, next_index });
+        Ok(receiver)
+    }
+
+    /// Emits the changes of a committed batch.
+    pub(crate) fn commit(&self, changeset: Changeset<R>) {
+        let batch_index = changeset.batch_index;
+        self.emit(|subscriber| {
+            (batch_index == subscriber.next_index).then(|| {
+                subscriber.next_index = batch_index + 1;
+                ChangeEvent::Commit(changeset.clone())
+            })
+        });
+    }
+
+    /// Emits the retraction of a reverted batch.
+    pub(crate) fn retract(&self, changeset: Changeset<R>) {
+        let batch_index = changeset.batch_index;
+        self.emit(|subscriber| {
+            (subscriber.from_index..subscriber.next_index).contains(&batch_index).then(|| {
+                subscriber.next_index = batch_index;
+                ChangeEvent::Retract(changeset.clone())
+            })
+        });
+    }
+
+    /// Sends the event built for every subscriber and drops the subscribers that went away.
+    ///
+    /// Subscribers that already know about the batch from their replay get no event.
+    fn emit(&self, mut event: impl FnMut(&mut Subscriber<R>) -> Option<ChangeEvent<R>>) {
+        self.subscribers.lock().unwrap().retain_mut(|subscriber| match event(subscriber) {
+            Some(event) => subscriber.sender.send(event).is_ok(),
+            None => !subscriber.sender.is_closed(),
+        });
+    }
+}
@@ -1,9 +1,14 @@
+use vprogs_state_index::SecondaryIndexes;
+use vprogs_state_version::VersionEncoding;
+
 use crate::VmInterface;
 
 #[derive(Clone, Debug)]
 pub struct ExecutionConfig<V: VmInterface> {
     pub(crate) worker_count: usize,
     pub(crate) vm: Option<V>,
+    pub(crate) version_encoding: VersionEncoding,
+    pub(crate) indexes: SecondaryIndexes<V::ResourceId>,
 }
 
 impl<V: VmInterface> ExecutionConfig<V> {
@@ -21,13 +26,35 @@ impl<V: VmInterface> ExecutionConfig<V> {
         self
     }
 
-    pub fn unpack(mut self) -> (usize, V) {
-        (self.worker_count, self.vm.take().expect("unpack requires vm to be set"))
+    /// Sets how the data of new versions is stored.
+    pub fn with_version_encoding(mut self, version_encoding: VersionEncoding) -> Self {
+        self.version_encoding = version_encoding;
+        self
+    }
+
+    /// Sets the secondary indexes that are maintained on commit.
+    pub fn with_indexes(mut self, indexes: SecondaryIndexes<V::ResourceId>) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn unpack(mut self) -> (usize, V, VersionEncoding, SecondaryIndexes<V::ResourceId>) {
+        (
+            self.worker_count,
+            self.vm.take().expect("unpack requires vm to be set"),
+            self.version_encoding,
+            self.indexes,
+        )
     }
 }
 
 impl<V: VmInterface> Default for ExecutionConfig<V> {
     fn default() -> Self {
-        Self { worker_count: num_cpus::get_physical(), vm: None }
+        Self {
+            worker_count: num_cpus::get_physical(),
+            vm: None,
+            version_encoding: VersionEncoding::default(),
+            indexes: SecondaryIndexes::default(),
+        }
     }
 }
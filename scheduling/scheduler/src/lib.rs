@@ -1,6 +1,9 @@
 mod access_handle;
+mod change_feed;
 mod config;
 mod cpu_task;
+mod prune;
+mod pruner;
 mod resource;
 mod resource_access;
 mod rollback;
@@ -14,7 +17,12 @@ mod vm_interface;
 mod worker_loop;
 
 pub use access_handle::AccessHandle;
+pub use change_feed::ChangeEvent;
+pub(crate) use change_feed::ChangeFeed;
 pub use config::ExecutionConfig;
+pub(crate) use prune::Prune;
+pub(crate) use pruner::Pruner;
+pub use pruner::PruningState;
 pub(crate) use resource::Resource;
 pub(crate) use resource_access::ResourceAccess;
 pub(crate) use rollback::Rollback;
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use vprogs_core_atomics::AtomicAsyncLatch;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
+use vprogs_state_ptr_rollback::StatePtrRollback;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::StateTree;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::{Store, WriteBatch};
+
+use crate::VmInterface;
+
+/// Represents the pruning of a single finalized batch.
+///
+/// The versions to delete are determined by the pruner ahead of time, so that executing the
+/// command only appends deletions to the current write batch and never blocks the write worker
+/// with reads. Only the content the stale versions referenced is checked for remaining references
+/// on the write worker, as references in uncommitted writes are not visible to the pruner.
+pub struct Prune<V: VmInterface> {
+    /// Index of the finalized batch whose rollback pointers are removed.
+    index: u64,
+    /// Versions that were replaced by the batch and are no longer referenced.
+    stale_versions: Vec<(V::ResourceId, u64)>,
+    /// History entries `(resource_id, batch_index)` of the writes that the batch replaced.
+    stale_history: Vec<(V::ResourceId, u64)>,
+    /// Chunks `(resource_id, owner, index)` that only the stale versions referenced.
+    stale_chunks: Vec<(V::ResourceId, u64, usize)>,
+    /// References `(resource_id, version, hash)` of the stale versions to the content they are
+    /// stored as.
+    stale_content: Vec<(V::ResourceId, u64, [u8; 32])>,
+    /// Versions `(resource_id, version, data)` stored as deltas against stale versions, which are
+    /// stored in full before their base is deleted.
+    materialized_versions: Vec<(V::ResourceId, u64, Vec<u8>)>,
+    /// Signal that resolves when the pruning has been committed.
+    done_signal: Arc<AtomicAsyncLatch>,
+}
+
+impl<V: VmInterface> Prune<V> {
+    /// Creates a new prune operation for the given batch.
+    pub fn new(
+        index: u64,
+        stale_versions: Vec<(V::ResourceId, u64)>,
+        stale_history: Vec<(V::ResourceId, u64)>,
+        stale_chunks: Vec<(V::ResourceId, u64, usize)>,
+        stale_content: Vec<(V::ResourceId, u64, [u8; 32])>,
+        materialized_versions: Vec<(V::ResourceId, u64, Vec<u8>)>,
+        done_signal: &Arc<AtomicAsyncLatch>,
+    ) -> Self {
+        Prune {
+            index,
+            stale_versions,
+            stale_history,
+            stale_chunks,
+            stale_content,
+            materialized_versions,
+            done_signal: done_signal.clone(),
+        }
+    }
+
+    /// Executes the prune operation on `store`.
+    ///
+    /// The deletions are appended to `write_batch`, which is committed first so that the remaining
+    /// references to tree nodes and content can be counted. The state root of the previous batch,
+    /// which is no longer needed, and the content that is no longer referenced are then deleted
+    /// and committed, and a fresh write batch is returned for further writes.
+    pub fn execute<S: Store<StateSpace = StateSpace>>(
+        &self,
+        store: &S,
+        mut write_batch: S::WriteBatch,
+    ) -> S::WriteBatch {
+        self.write(&mut write_batch);
+        store.commit(write_batch);
+
+        let mut write_batch = store.write_batch();
+        StateTree::delete_roots(store, &mut write_batch, [self.index - 1]);
+        StateVersion::<V::ResourceId>::update_content_refs(
+            store,
+            &mut write_batch,
+            self.stale_content.iter().map(|(_, _, hash)| (*hash, -1)),
+        );
+        store.commit(write_batch);
+
+        store.write_batch()
+    }
+
+    /// Appends the deletions to `write_batch` and records the batch as pruned.
+    fn write<W: WriteBatch<StateSpace = StateSpace>>(&self, write_batch: &mut W) {
+        for (resource_id, version, data) in &self.materialized_versions {
+            StateVersion::put(write_batch, *version, resource_id, data);
+        }
+        for (resource_id, version) in &self.stale_versions {
+            StateVersion::delete(write_batch, *version, resource_id);
+        }
+        for (resource_id, owner, index) in &self.stale_chunks {
+            StateVersion::delete_chunk(write_batch, *owner, resource_id, *index);
+        }
+        for (resource_id, version, hash) in &self.stale_content {
+            StateVersion::delete_content_ref(write_batch, hash, *version, resource_id);
+        }
+        for (resource_id, batch_index) in &self.stale_history {
+            StatePtrHistory::delete(write_batch, resource_id, *batch_index);
+        }
+        StatePtrRollback::delete_batch(write_batch, self.index);
+        StateMetadata::set_pruned_index(write_batch, self.index);
+    }
+
+    /// Signals that the prune operation has completed.
+    pub fn done(&self) {
+        self.done_signal.open()
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use vprogs_core_macros::smart_pointer;
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_ptr_rollback::StatePtrRollback;
+use vprogs_state_space::StateSpace;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_manager::StorageManager;
+use vprogs_storage_types::Store;
+
+use crate::{Read, VmInterface, Write, prune::Prune};
+
+/// How long the pruner sleeps before checking for newly committed batches when it is idle.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Tracks how far the background pruner has progressed.
+///
+/// Batches are pruned once they are both finalized and committed, so the pruner never touches
+/// rollback pointers that are still being written.
+#[smart_pointer]
+pub struct PruningState {
+    /// Highest batch index that can no longer be rolled back.
+    finalized_index: AtomicU64,
+    /// Highest batch index whose changes have been committed.
+    committed_index: AtomicU64,
+    /// Highest batch index that has been pruned.
+    pruned_index: AtomicU64,
+}
+
+impl PruningState {
+    /// Creates the state for a store that has been pruned up to `pruned_index`, finalized up to
+    /// `finalized_index` and committed up to `committed_index`.
+    pub(crate) fn new(pruned_index: u64, finalized_index: u64, committed_index: u64) -> Self {
+        Self(Arc::new(PruningStateData {
+            finalized_index: AtomicU64::new(finalized_index.max(pruned_index)),
+            committed_index: AtomicU64::new(committed_index),
+            pruned_index: AtomicU64::new(pruned_index),
+        }))
+    }
+
+    /// Returns the highest batch index that can no longer be rolled back.
+    pub fn finalized_index(&self) -> u64 {
+        self.finalized_index.load(Ordering::Acquire)
+    }
+
+    /// Returns the highest batch index that has been pruned.
+    pub fn pruned_index(&self) -> u64 {
+        self.pruned_index.load(Ordering::Acquire)
+    }
+
+    /// Raises the finalized index. Finality never moves backwards.
+    pub(crate) fn finalize(&self, index: u64) {
+        self.finalized_index.fetch_max(index, Ordering::AcqRel);
+    }
+
+    /// Records that the batch with the given index has been committed.
+    pub(crate) fn commit(&self, index: u64) {
+        self.committed_index.store(index, Ordering::Release);
+    }
+
+    /// Forgets about committed batches that were reverted by a rollback to `index`.
+    pub(crate) fn rollback(&self, index: u64) {
+        self.committed_index.fetch_min(index, Ordering::AcqRel);
+    }
+
+    /// Returns the highest batch index that may be pruned.
+    fn target_index(&self) -> u64 {
+        self.finalized_index().min(self.committed_index.load(Ordering::Acquire))
+    }
+}
+
+/// Background worker that prunes finalized batches one at a time.
+///
+/// For each batch it reads the rollback pointers and determines the versions they reference that
+/// are no longer latest. The deletions are then handed to the write worker as a [`Prune`]
+/// command, so that all writes stay serialized without the write worker having to read.
+pub(crate) struct Pruner<S: Store<StateSpace = StateSpace>, V: VmInterface> {
+    state: PruningState,
+    is_shutdown: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+    _marker: PhantomData<(S, V)>,
+}
+
+impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Pruner<S, V> {
+    pub(crate) fn new(storage: &StorageManager<S, Read<S, V>, Write<S, V>>) -> Self {
+        let store = storage.store();
+        let state = PruningState::new(
+            StateMetadata::pruned_index(store),
+            StateMetadata::finalized_index(store),
+            StateMetadata::last_committed_index(store),
+        );
+        let is_shutdown = Arc::new(AtomicBool::new(false));
+        let handle = Self::start(storage.clone(), state.clone(), is_shutdown.clone());
+
+        Self { state, is_shutdown, handle, _marker: PhantomData }
+    }
+
+    pub(crate) fn state(&self) -> &PruningState {
+        &self.state
+    }
+
+    /// Marks all batches up to `index` as finalized and wakes the pruner.
+    pub(crate) fn prune_to(&self, index: u64) {
+        self.state.finalize(index);
+        self.handle.thread().unpark();
+    }
+
+    pub(crate) fn shutdown(self) {
+        self.is_shutdown.store(true, Ordering::Release);
+        self.handle.thread().unpark();
+        self.handle.join().expect("pruner panicked");
+    }
+
+    fn start(
+        storage: StorageManager<S, Read<S, V>, Write<S, V>>,
+        state: PruningState,
+        is_shutdown: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while !is_shutdown.load(Ordering::Acquire) {
+                let next_index = state.pruned_index() + 1;
+                if next_index <= state.target_index() {
+                    Self::prune_batch(&storage, next_index);
+                    state.pruned_index.store(next_index, Ordering::Release);
+                } else {
+                    thread::park_timeout(IDLE_TIMEOUT);
+                }
+            }
+        })
+    }
+
+    /// Prunes a single batch and blocks until the deletions have been committed.
+    ///
+    /// Besides the replaced versions, this removes the history entries of the writes that created
+    /// them, so that the last write at or before the pruned batch remains the oldest entry of
+    /// every resource, and the chunks of the replaced versions that the batch's versions do not
+    /// share. Versions the batch stored as deltas against the replaced versions are stored in full
+    /// first, and the content the replaced versions referenced is deleted if no other version
+    /// references it.
+    fn prune_batch(storage: &StorageManager<S, Read<S, V>, Write<S, V>>, index: u64) {
+        let store = storage.store();
+        let mut stale_versions = Vec::new();
+        let mut stale_history = Vec::new();
+        let mut stale_chunks = Vec::new();
+        let mut stale_content = Vec::new();
+        let mut materialized_versions = Vec::new();
+        for (resource_id_bytes, old_version) in StatePtrRollback::iter_batch(store, index) {
+            let resource_id = V::ResourceId::from_bytes(&resource_id_bytes);
+
+            // Version 0 means that the resource did not exist before the batch.
+            if old_version == 0 || StatePtrLatest::get(store, &resource_id) == Some(old_version) {
+                continue;
+            }
+
+            // The version written by the batch may be a delta against the replaced version. If it
+            // can not be loaded, the replaced version is kept rather than deleting its last base.
+            let written_version = StatePtrHistory::get(store, &resource_id, index);
+            if let Some(written_version) = written_version.filter(|&written_version| {
+                StateVersion::delta_base(store, written_version, &resource_id) == Some(old_version)
+            }) {
+                let Ok(Some(data)) = StateVersion::get(store, written_version, &resource_id) else {
+                    continue;
+                };
+                materialized_versions.push((resource_id.clone(), written_version, data));
+            }
+
+            // Later versions can only reference the replaced version's chunks through the version
+            // written by the batch, which shares them at the same position.
+            let written_refs = written_version
+                .map(|written_version| {
+                    StateVersion::chunk_refs(store, written_version, &resource_id)
+                })
+                .unwrap_or_default();
+            for (index, chunk_ref) in
+                StateVersion::chunk_refs(store, old_version, &resource_id).into_iter().enumerate()
+            {
+                if written_refs.get(index) != Some(&chunk_ref) {
+                    stale_chunks.push((resource_id.clone(), chunk_ref.owner, index));
+                }
+            }
+
+            if let Some(hash) = StateVersion::content_hash(store, old_version, &resource_id) {
+                stale_content.push((resource_id.clone(), old_version, hash));
+            }
+
+            if let Some((replaced_index, _)) =
+                StatePtrHistory::find_at(store, &resource_id, index - 1)
+            {
+                stale_history.push((resource_id.clone(), replaced_index));
+            }
+            stale_versions.push((resource_id, old_version));
+        }
+
+        let done_signal = Default::default();
+        storage.submit_write(Write::Prune(Prune::new(
+            index,
+            stale_versions,
+            stale_history,
+            stale_chunks,
+            stale_content,
+            materialized_versions,
+            &done_signal,
+        )));
+        done_signal.wait_blocking();
+    }
+}
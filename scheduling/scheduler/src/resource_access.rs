@@ -90,7 +90,12 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> ResourceAccess<S, V> {
     }
 
     pub(crate) fn read_latest_data<R: ReadStore<StateSpace = StateSpace>>(&self, store: &R) {
-        self.set_read_state(Arc::new(StateVersion::from_latest_data(store, self.metadata.id())));
+        // Corrupted data is handed to the VM as a placeholder that reports the corruption. The
+        // chunks of large values are only loaded once the VM accesses them.
+        let id = self.metadata.id();
+        let state = StateVersion::from_latest_data_lazy(store, id.clone())
+            .unwrap_or_else(|err| StateVersion::corrupted(id, err));
+        self.set_read_state(Arc::new(state));
     }
 
     pub(crate) fn tx(&self) -> &RuntimeTxRef<S, V> {
@@ -120,6 +125,12 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> ResourceAccess<S, V> {
     }
 
     pub(crate) fn set_written_state(&self, state: Arc<StateVersion<V::ResourceId>>) {
+        // The last access of a batch hands on the version the batch read if the batch restored
+        // its state, so that later batches continue from a version that exists.
+        let state = match self.is_batch_tail() {
+            true => self.state_diff.restored_state(state),
+            false => state,
+        };
         if self.written_state.publish(state.clone()) {
             if self.is_batch_tail() {
                 self.state_diff.set_written_state(state.clone());
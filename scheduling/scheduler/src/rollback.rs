@@ -1,14 +1,22 @@
-use std::{marker::PhantomData, sync::Arc};
+use std::{
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
 
 use vprogs_core_atomics::AtomicAsyncLatch;
 use vprogs_core_types::ResourceId;
+use vprogs_state_changeset::{Changeset, ResourceChange};
+use vprogs_state_index::SecondaryIndexes;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
 use vprogs_state_ptr_latest::StatePtrLatest;
 use vprogs_state_ptr_rollback::StatePtrRollback;
 use vprogs_state_space::StateSpace;
+use vprogs_state_tree::StateTree;
 use vprogs_state_version::StateVersion;
 use vprogs_storage_types::Store;
 
-use crate::VmInterface;
+use crate::{ChangeFeed, VmInterface};
 
 /// Represents a rollback operation that reverts state changes made within an inclusive range of
 /// batch indices.
@@ -20,6 +28,12 @@ pub struct Rollback<V: VmInterface> {
     lower_bound: u64,
     /// Upper bound of the batch index range to roll back (inclusive).
     upper_bound: u64,
+    /// Secondary indexes whose entries are restored along with the state.
+    indexes: SecondaryIndexes<V::ResourceId>,
+    /// Receives the retractions of the reverted batches that had been committed.
+    change_feed: ChangeFeed<V::ResourceId>,
+    /// The retractions collected while building the rollback, emitted once it is written.
+    retractions: Mutex<Vec<Changeset<V::ResourceId>>>,
     /// Signal that resolves when the rollback operation is complete.
     done_signal: Arc<AtomicAsyncLatch>,
     /// Marker for the VM interface type.
@@ -28,10 +42,19 @@ pub struct Rollback<V: VmInterface> {
 
 impl<V: VmInterface> Rollback<V> {
     /// Creates a new rollback operation for the given inclusive batch range.
-    pub fn new(lower_bound: u64, upper_bound: u64, done_signal: &Arc<AtomicAsyncLatch>) -> Self {
+    pub(crate) fn new(
+        lower_bound: u64,
+        upper_bound: u64,
+        indexes: &SecondaryIndexes<V::ResourceId>,
+        change_feed: &ChangeFeed<V::ResourceId>,
+        done_signal: &Arc<AtomicAsyncLatch>,
+    ) -> Self {
         Rollback {
             lower_bound,
             upper_bound,
+            indexes: indexes.clone(),
+            change_feed: change_feed.clone(),
+            retractions: Mutex::new(Vec::new()),
             done_signal: done_signal.clone(),
             _marker: PhantomData,
         }
@@ -51,7 +74,20 @@ impl<V: VmInterface> Rollback<V> {
         store.commit(write_batch);
 
         // Perform the rollback and commit the resulting changes.
-        store.commit(self.build_rollback_batch(store));
+        let (rollback_batch, released_content) = self.build_rollback_batch(store);
+        store.commit(rollback_batch);
+
+        // Content is only released once the removed references are committed, so that the
+        // remaining ones can be counted.
+        if !released_content.is_empty() {
+            let mut write_batch = store.write_batch();
+            StateVersion::<V::ResourceId>::update_content_refs(
+                store,
+                &mut write_batch,
+                released_content,
+            );
+            store.commit(write_batch);
+        }
 
         // Return a new empty write batch for further operations.
         store.write_batch()
@@ -59,51 +95,138 @@ impl<V: VmInterface> Rollback<V> {
 
     /// Signals that the rollback operation has completed.
     pub fn done(&self) {
+        for changeset in self.retractions.lock().unwrap().drain(..) {
+            self.change_feed.retract(changeset);
+        }
         self.done_signal.open()
     }
 
-    /// Builds a write batch containing all rollback operations.
-    fn build_rollback_batch<S: Store<StateSpace = StateSpace>>(&self, store: &S) -> S::WriteBatch {
+    /// Builds a write batch containing all rollback operations, along with the changes to the
+    /// reference counts of the content that the removed versions referenced.
+    fn build_rollback_batch<S: Store<StateSpace = StateSpace>>(
+        &self,
+        store: &S,
+    ) -> (S::WriteBatch, Vec<([u8; 32], i64)>) {
         let mut write_batch = store.write_batch();
+        let mut released_content = Vec::new();
+        let last_committed_index = StateMetadata::last_committed_index(store);
+        let retract = self.change_feed.has_subscribers();
 
         // Walk batches from newest to oldest.
         for index in (self.lower_bound..=self.upper_bound).rev() {
             // Apply all rollback pointers associated with this batch.
+            let mut changes = Vec::new();
+            let mut batch_content = Vec::new();
             for (resource_id_bytes, old_version) in StatePtrRollback::iter_batch(store, index) {
                 let resource_id = V::ResourceId::from_bytes(&resource_id_bytes);
-                self.apply_rollback_ptr(store, &mut write_batch, index, resource_id, old_version);
+                let new_version = self.apply_rollback_ptr(
+                    store,
+                    &mut write_batch,
+                    &mut batch_content,
+                    index,
+                    resource_id.clone(),
+                    old_version,
+                );
+                if retract {
+                    let deleted = new_version.is_some_and(|new_version| {
+                        StateVersion::is_tombstone(store, new_version, &resource_id)
+                    });
+                    changes.push(ResourceChange {
+                        resource_id,
+                        old_version,
+                        new_version: new_version.unwrap_or_default(),
+                        deleted,
+                        old_data: None,
+                        new_data: None,
+                    });
+                }
             }
+
+            // Only the references of committed batches were counted, while the content stored by
+            // other batches is deleted if nothing else references it.
+            let delta = -i64::from(index <= last_committed_index);
+            released_content.extend(batch_content.into_iter().map(|hash| (hash, delta)));
+
+            // Only committed batches were delivered to subscribers.
+            if retract && index <= last_committed_index {
+                let changeset = Changeset { batch_index: index, changes };
+                self.retractions.lock().unwrap().push(changeset);
+            }
+
+            // Remove the batch's rollback pointers in one go.
+            StatePtrRollback::delete_batch(&mut write_batch, index);
+            StateMetadata::delete_batch_header(&mut write_batch, index);
         }
 
-        write_batch
+        // Forget the state roots of the batches, along with the tree nodes no other root shares.
+        StateTree::delete_roots(store, &mut write_batch, self.lower_bound..=self.upper_bound);
+
+        // Batches of the range that were not committed yet are canceled, so the last committed
+        // index never moves forward.
+        StateMetadata::set_last_committed_index(
+            &mut write_batch,
+            last_committed_index.min(self.lower_bound - 1),
+        );
+
+        (write_batch, released_content)
     }
 
     /// Applies a single rollback pointer to the write batch.
     ///
-    /// This removes the current version of the resource (if any), restores the previous version,
-    /// and deletes the rollback pointer entry.
+    /// This removes the version the batch wrote along with its chunks and history entry and
+    /// restores the previous version and its index entries. The rollback pointer itself is removed
+    /// together with the rest of its batch. Returns the removed version, if it was found, and adds
+    /// the content it referenced to `released_content`.
     fn apply_rollback_ptr<S: Store<StateSpace = StateSpace>>(
         &self,
         store: &S,
         write_batch: &mut S::WriteBatch,
+        released_content: &mut Vec<[u8; 32]>,
         batch_index: u64,
         resource_id: V::ResourceId,
         old_version: u64,
-    ) {
-        // Remove the currently live version, if present.
-        if let Some(current_version) = StatePtrLatest::get(store, &resource_id) {
-            StateVersion::delete(write_batch, current_version, &resource_id);
+    ) -> Option<u64> {
+        // Remove the version written by the batch. The latest pointer is only consulted as a
+        // fallback, as it is stale once a previous batch of the same rollback touched the resource.
+        let written_version = StatePtrHistory::get(store, &resource_id, batch_index)
+            .or_else(|| StatePtrLatest::get(store, &resource_id));
+        if let Some(written_version) = written_version {
+            released_content.extend(StateVersion::content_hash(
+                store,
+                written_version,
+                &resource_id,
+            ));
+            StateVersion::delete_with_chunks(store, write_batch, written_version, &resource_id);
+        }
+
+        // Move the resource back to the index keys of its previous data. This also holds if the
+        // batch was never committed, as only the entries that differ are written.
+        if !self.indexes.is_empty() {
+            // Tombstones are not indexed.
+            let data = |version| {
+                (!StateVersion::is_tombstone(store, version, &resource_id))
+                    .then(|| StateVersion::get(store, version, &resource_id).ok().flatten())
+                    .flatten()
+            };
+            let written_data = written_version.and_then(data);
+            let old_data = (old_version != 0).then(|| data(old_version)).flatten();
+            self.indexes.update(
+                write_batch,
+                &resource_id,
+                written_data.as_deref(),
+                old_data.as_deref(),
+            );
         }
+        StatePtrHistory::delete(write_batch, &resource_id, batch_index);
 
         if old_version == 0 {
             // The resource did not exist before this batch.
             StatePtrLatest::delete(write_batch, &resource_id);
         } else {
-            // Restore the resource to its previous version.
-            StatePtrLatest::put(write_batch, &resource_id, old_version);
+            // Restore the resource to its previous version, which may be a tombstone.
+            StateVersion::restore_latest_ptr(store, write_batch, old_version, &resource_id);
         }
 
-        // Remove the rollback pointer itself.
-        StatePtrRollback::delete(write_batch, batch_index, &resource_id);
+        written_version
     }
 }
@@ -4,15 +4,22 @@ use std::sync::{
 };
 
 use crossbeam_deque::{Injector, Steal, Worker};
-use vprogs_core_atomics::AtomicAsyncLatch;
+use vprogs_core_atomics::{AtomicAsyncLatch, AtomicOptionArc};
 use vprogs_core_macros::smart_pointer;
+use vprogs_core_types::ResourceId;
+use vprogs_state_changeset::{Changeset, ResourceChange};
+use vprogs_state_index::SecondaryIndexes;
+use vprogs_state_metadata::{BatchHeader, StateMetadata};
 use vprogs_state_space::StateSpace;
+use vprogs_state_tree::{Hash, StateTree};
+use vprogs_state_version::StateVersion;
+use vprogs_state_version::VersionEncoding;
 use vprogs_storage_manager::StorageManager;
-use vprogs_storage_types::{Store, WriteBatch};
+use vprogs_storage_types::{ReadStore, Store, WriteBatch};
 
 use crate::{
-    Read, RuntimeContext, RuntimeTx, Scheduler, StateDiff, Write, cpu_task::ManagerTask,
-    vm_interface::VmInterface,
+    ChangeFeed, PruningState, Read, RuntimeContext, RuntimeTx, Scheduler, StateDiff, Write,
+    cpu_task::ManagerTask, vm_interface::VmInterface,
 };
 
 #[smart_pointer]
@@ -20,11 +27,17 @@ pub struct RuntimeBatch<S: Store<StateSpace = StateSpace>, V: VmInterface> {
     runtime_context: RuntimeContext,
     index: u64,
     storage: StorageManager<S, Read<S, V>, Write<S, V>>,
+    pruning: PruningState,
+    version_encoding: VersionEncoding,
+    indexes: SecondaryIndexes<V::ResourceId>,
+    change_feed: ChangeFeed<V::ResourceId>,
+    context: Vec<u8>,
     txs: Vec<RuntimeTx<S, V>>,
     state_diffs: Vec<StateDiff<S, V>>,
     available_txs: Injector<ManagerTask<S, V>>,
     pending_txs: AtomicU64,
     pending_writes: AtomicI64,
+    state_root: AtomicOptionArc<Hash>,
     was_processed: AtomicAsyncLatch,
     was_persisted: AtomicAsyncLatch,
     was_committed: AtomicAsyncLatch,
@@ -43,6 +56,16 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
         &self.state_diffs
     }
 
+    /// Returns the context the batch was scheduled with, which is recorded in its header.
+    pub fn context(&self) -> &[u8] {
+        &self.context
+    }
+
+    /// Returns how the data of the batch's new versions is stored.
+    pub fn version_encoding(&self) -> VersionEncoding {
+        self.version_encoding
+    }
+
     pub fn num_available(&self) -> u64 {
         self.available_txs.len() as u64
     }
@@ -55,6 +78,11 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
         self.index > self.runtime_context.cancel_threshold()
     }
 
+    /// Returns the root of the state tree after this batch, which is known once it was committed.
+    pub fn state_root(&self) -> Option<Hash> {
+        self.state_root.load().map(|root| *root)
+    }
+
     pub fn was_processed(&self) -> bool {
         self.was_processed.is_open()
     }
@@ -106,7 +134,12 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
         self
     }
 
-    pub(crate) fn new(vm: V, manager: &mut Scheduler<S, V>, txs: Vec<V::Transaction>) -> Self {
+    pub(crate) fn new(
+        vm: V,
+        manager: &mut Scheduler<S, V>,
+        txs: Vec<V::Transaction>,
+        context: Vec<u8>,
+    ) -> Self {
         Self(Arc::new_cyclic(|this| {
             let mut state_diffs = Vec::new();
             let runtime_context = manager.context().clone();
@@ -114,8 +147,14 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
             RuntimeBatchData {
                 index: runtime_context.next_batch_index(),
                 storage: manager.storage_manager().clone(),
+                pruning: manager.pruning().clone(),
+                version_encoding: manager.version_encoding(),
+                indexes: manager.indexes().clone(),
+                change_feed: manager.change_feed().clone(),
+                context,
                 pending_txs: AtomicU64::new(txs.len() as u64),
                 pending_writes: AtomicI64::new(0),
+                state_root: AtomicOptionArc::empty(),
                 txs: txs
                     .into_iter()
                     .map(|tx| {
@@ -138,6 +177,10 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
         }))
     }
 
+    pub(crate) fn storage(&self) -> &StorageManager<S, Read<S, V>, Write<S, V>> {
+        &self.storage
+    }
+
     pub(crate) fn connect(&self) {
         for tx in self.txs() {
             for resource in tx.accessed_resources() {
@@ -184,19 +227,110 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeBatch<S, V> {
         }
     }
 
-    pub(crate) fn commit<W>(&self, store: &mut W)
+    pub(crate) fn commit<RS, W>(&self, store: &RS, write_batch: &mut W)
     where
+        RS: ReadStore<StateSpace = StateSpace>,
         W: WriteBatch<StateSpace = StateSpace>,
     {
         if !self.was_canceled() {
-            for state_diff in self.state_diffs() {
-                state_diff.written_state().write_latest_ptr(store);
+            let changes: Vec<_> = self
+                .state_diffs()
+                .iter()
+                .filter(|diff| !diff.is_read_only())
+                .map(|diff| (diff.resource_id(), diff.read_state(), diff.written_state()))
+                .collect();
+
+            for (resource_id, read_state, written_state) in &changes {
+                written_state.write_latest_ptr(write_batch);
+
+                // Move the resource from the index keys of the replaced data to the new ones.
+                if !self.indexes.is_empty() {
+                    if let Err(err) = written_state.load(store) {
+                        panic!("failed to load written state: {err}");
+                    }
+                    if let Err(err) = read_state.load(store) {
+                        panic!("failed to load replaced state: {err}");
+                    }
+                    let old_data = (read_state.version() != 0 && !read_state.is_deleted())
+                        .then(|| read_state.data().as_slice());
+                    let new_data =
+                        (!written_state.is_deleted()).then(|| written_state.data().as_slice());
+                    self.indexes.update(write_batch, *resource_id, old_data, new_data);
+                }
             }
+
+            // Count the references to the content the new versions are stored as.
+            StateVersion::<V::ResourceId>::update_content_refs(
+                store,
+                write_batch,
+                changes.iter().filter_map(|(_, _, state)| Some((state.written_content()?, 1))),
+            );
+
+            // The state root commits to chunked data through the hashes in its manifest, so chunks
+            // that were never accessed are not loaded.
+            let root = StateTree::update(
+                store,
+                write_batch,
+                self.index,
+                changes.iter().map(|(id, _, state)| {
+                    let data_hash = (!state.is_deleted()).then(|| state.data_hash());
+                    (*id, state.version(), data_hash)
+                }),
+            );
+            self.state_root.store(Some(Arc::new(root)));
+
+            StateMetadata::set_batch_header(
+                write_batch,
+                &BatchHeader {
+                    index: self.index,
+                    tx_count: self.txs.len() as u64,
+                    state_diff_count: self.state_diffs.len() as u64,
+                    commitment: Some(root),
+                    context: self.context.clone(),
+                },
+            );
+            StateMetadata::set_last_committed_index(write_batch, self.index);
         }
     }
 
+    /// Returns the changes of the committed batch along with their new data.
+    ///
+    /// The chunks of large values are loaded from the store, which holds them once the batch is
+    /// committed.
+    fn changeset(&self) -> Changeset<V::ResourceId> {
+        let mut changes: Vec<_> = self
+            .state_diffs()
+            .iter()
+            .filter(|diff| !diff.is_read_only())
+            .inspect(|diff| {
+                if let Err(err) = diff.written_state().load(self.storage.store()) {
+                    panic!("failed to load written state: {err}");
+                }
+            })
+            .map(|diff| ResourceChange {
+                resource_id: diff.resource_id().clone(),
+                old_version: diff.read_state().version(),
+                new_version: diff.written_state().version(),
+                deleted: diff.written_state().is_deleted(),
+                old_data: None,
+                new_data: (!diff.written_state().is_deleted())
+                    .then(|| diff.written_state().data().clone()),
+            })
+            .collect();
+        // Match the order of changesets that are loaded from the store.
+        changes.sort_by_cached_key(|change| change.resource_id.to_bytes());
+
+        Changeset { batch_index: self.index, changes }
+    }
+
     pub(crate) fn commit_done(self) {
         // TODO: EVICT STUFF FROM STORAGE MANAGER
+        if !self.was_canceled() {
+            self.pruning.commit(self.index);
+            if self.change_feed.has_subscribers() {
+                self.change_feed.commit(self.changeset());
+            }
+        }
         self.was_committed.open();
     }
 }
@@ -63,7 +63,8 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> RuntimeTx<S, V> {
     pub(crate) fn execute(&self) {
         if let Some(batch) = self.batch.upgrade() {
             // Create access handles for all accessed resources.
-            let handles = self.resources.iter().map(AccessHandle::new);
+            let store = batch.storage().store();
+            let handles = self.resources.iter().map(|access| AccessHandle::new(access, store));
 
             // If the batch was canceled, roll back all changes and exit early.
             if batch.was_canceled() {
@@ -1,16 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use tap::Tap;
+use tokio::sync::mpsc::UnboundedReceiver;
+use vprogs_core_atomics::AtomicAsyncLatch;
 use vprogs_core_types::{AccessMetadata, Transaction};
 use vprogs_scheduling_execution_workers::ExecutionWorkers;
+use vprogs_state_changeset::ChangesetResult;
+use vprogs_state_index::SecondaryIndexes;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_rollback::StatePtrRollback;
 use vprogs_state_space::StateSpace;
+use vprogs_state_version::VersionEncoding;
 use vprogs_storage_manager::{StorageConfig, StorageManager};
 use vprogs_storage_types::Store;
 
 use crate::{
-    ExecutionConfig, Read, Resource, ResourceAccess, Rollback, RuntimeBatch, RuntimeBatchRef,
-    RuntimeContext, RuntimeTxRef, StateDiff, WorkerLoop, Write, cpu_task::ManagerTask,
-    vm_interface::VmInterface,
+    ChangeEvent, ChangeFeed, ExecutionConfig, Pruner, PruningState, Read, Resource, ResourceAccess,
+    Rollback, RuntimeBatch, RuntimeBatchRef, RuntimeContext, RuntimeTxRef, StateDiff, WorkerLoop,
+    Write, cpu_task::ManagerTask, vm_interface::VmInterface,
 };
 
 /// Orchestrates transaction execution, state management, and storage coordination.
@@ -31,19 +38,39 @@ pub struct Scheduler<S: Store<StateSpace = StateSpace>, V: VmInterface> {
     worker_loop: WorkerLoop<S, V>,
     /// Thread pool for parallel transaction execution.
     execution_workers: ExecutionWorkers<ManagerTask<S, V>, RuntimeBatch<S, V>>,
+    /// Background worker that removes history of finalized batches.
+    pruner: Pruner<S, V>,
+    /// How the data of new versions is stored.
+    version_encoding: VersionEncoding,
+    /// The secondary indexes maintained on commit.
+    indexes: SecondaryIndexes<V::ResourceId>,
+    /// Delivers the changes of committed and reverted batches to subscribers.
+    change_feed: ChangeFeed<V::ResourceId>,
 }
 
 impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Scheduler<S, V> {
     /// Creates a new scheduler with the given execution and storage configurations.
+    ///
+    /// The scheduler resumes after the last batch committed to the store, so that new batches
+    /// continue its index sequence. Writes of batches that were persisted but not committed before
+    /// a crash are removed first (see [`Self::recover`]).
     pub fn new(execution_config: ExecutionConfig<V>, storage_config: StorageConfig<S>) -> Self {
-        let (worker_count, vm) = execution_config.unpack();
+        let (worker_count, vm, version_encoding, indexes) = execution_config.unpack();
+        let storage_manager = StorageManager::new(storage_config);
+        let last_committed_index = StateMetadata::last_committed_index(storage_manager.store());
+        let change_feed = ChangeFeed::new();
+        Self::recover(&storage_manager, &indexes, &change_feed, last_committed_index);
         Self {
-            context: RuntimeContext::new(0),
+            context: RuntimeContext::new(last_committed_index),
             worker_loop: WorkerLoop::new(vm.clone()),
-            storage_manager: StorageManager::new(storage_config),
+            pruner: Pruner::new(&storage_manager),
+            storage_manager,
             resources: HashMap::new(),
             execution_workers: ExecutionWorkers::new(worker_count),
             vm,
+            version_encoding,
+            indexes,
+            change_feed,
         }
     }
 
@@ -53,7 +80,18 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Scheduler<S, V> {
     /// pushes it to the worker loop for lifecycle management, and submits it to execution workers
     /// for parallel processing.
     pub fn schedule(&mut self, txs: Vec<V::Transaction>) -> RuntimeBatch<S, V> {
-        RuntimeBatch::new(self.vm.clone(), self, txs)
+        self.schedule_with_context(txs, Vec::new())
+    }
+
+    /// Schedules a batch of transactions like [`Self::schedule`], recording `context` in the
+    /// batch's header once it is committed (see [`StateMetadata::batch_header`]), e.g. to remember
+    /// the L1 block the batch was derived from.
+    pub fn schedule_with_context(
+        &mut self,
+        txs: Vec<V::Transaction>,
+        context: Vec<u8>,
+    ) -> RuntimeBatch<S, V> {
+        RuntimeBatch::new(self.vm.clone(), self, txs, context)
             // Connect transactions to resource dependency chains.
             .tap(RuntimeBatch::connect)
             .tap(|batch| {
@@ -69,7 +107,16 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Scheduler<S, V> {
     /// This updates the runtime context to reflect the rollback and submits a rollback command to
     /// the storage manager. The call blocks until the rollback completes, after which all in-memory
     /// resource pointers are cleared, as their state may have changed.
+    ///
+    /// # Panics
+    /// Panics if `target_index` is below the finalized batch index (see [`Self::prune_to`]).
     pub fn rollback_to(&mut self, target_index: u64) {
+        assert!(
+            target_index >= self.pruner.state().finalized_index(),
+            "can not roll back to batch {target_index} below the finalized batch {}",
+            self.pruner.state().finalized_index()
+        );
+
         // Determine the range of batches to roll back.
         let lower_bound = target_index + 1;
         let upper_bound = self.context.last_batch_index();
@@ -84,20 +131,76 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Scheduler<S, V> {
             self.storage_manager.submit_write(Write::Rollback(Rollback::new(
                 lower_bound,
                 upper_bound,
+                &self.indexes,
+                &self.change_feed,
                 &done_signal,
             )));
             done_signal.wait_blocking();
 
             // Clear in-memory resource pointers, as their state may no longer be valid.
             self.resources.clear();
+
+            // Reverted batches must not be pruned.
+            self.pruner.state().rollback(target_index);
         }
     }
 
+    /// Marks all batches up to `finalized_index` as final and prunes their history in the
+    /// background.
+    ///
+    /// Pruning removes the rollback pointers of finalized batches together with the versions they
+    /// reference that are no longer latest. Batches are only pruned after they were committed, and
+    /// finalized batches can no longer be rolled back. The call blocks until the finalized index
+    /// is persisted, so that finality is kept across restarts.
+    pub fn prune_to(&self, finalized_index: u64) {
+        self.pruner.prune_to(finalized_index);
+
+        let done_signal = Arc::new(AtomicAsyncLatch::default());
+        let pruning = self.pruner.state().clone();
+        self.storage_manager.submit_write(Write::Finalize(pruning, done_signal.clone()));
+        done_signal.wait_blocking();
+    }
+
+    /// Returns the progress of pruning.
+    pub fn pruning(&self) -> &PruningState {
+        self.pruner.state()
+    }
+
+    /// Returns how the data of new versions is stored.
+    pub fn version_encoding(&self) -> VersionEncoding {
+        self.version_encoding
+    }
+
+    /// Returns the secondary indexes maintained on commit.
+    pub fn indexes(&self) -> &SecondaryIndexes<V::ResourceId> {
+        &self.indexes
+    }
+
+    /// Subscribes to the changes of all batches from `from_index` on, e.g. to resume an indexer
+    /// after the last batch it processed.
+    ///
+    /// Committed batches are replayed from the store first, followed by a [`ChangeEvent::Commit`]
+    /// for every batch that is committed from then on and a [`ChangeEvent::Retract`] for every
+    /// delivered batch that is reverted by a rollback. Events are buffered until they are received,
+    /// and the subscription ends when the receiver is dropped.
+    ///
+    /// Returns an error if batches from `from_index` on have been pruned.
+    pub fn subscribe(
+        &self,
+        from_index: u64,
+    ) -> ChangesetResult<UnboundedReceiver<ChangeEvent<V::ResourceId>>> {
+        self.change_feed.subscribe(self.storage_manager.store(), from_index)
+    }
+
     /// Returns a reference to the runtime context.
     pub fn context(&self) -> &RuntimeContext {
         &self.context
     }
 
+    pub(crate) fn change_feed(&self) -> &ChangeFeed<V::ResourceId> {
+        &self.change_feed
+    }
+
     /// Returns a reference to the storage manager.
     pub fn storage_manager(&self) -> &StorageManager<S, Read<S, V>, Write<S, V>> {
         &self.storage_manager
@@ -105,13 +208,42 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> Scheduler<S, V> {
 
     /// Shuts down the scheduler and all its components.
     ///
-    /// This stops the worker loop, execution workers, and storage manager in order.
+    /// This stops the worker loop, execution workers, pruner, and storage manager in order.
     pub fn shutdown(self) {
         self.worker_loop.shutdown();
         self.execution_workers.shutdown();
+        self.pruner.shutdown();
         self.storage_manager.shutdown();
     }
 
+    /// Removes the writes of batches after `last_committed_index`.
+    ///
+    /// State diffs are persisted before their batch is committed, so a crash can leave versions,
+    /// history entries and rollback pointers of batches whose latest pointers were never published.
+    /// These batches are detected by their rollback pointers and reverted like a regular rollback,
+    /// which leaves the store exactly as it was after the last committed batch.
+    fn recover(
+        storage_manager: &StorageManager<S, Read<S, V>, Write<S, V>>,
+        indexes: &SecondaryIndexes<V::ResourceId>,
+        change_feed: &ChangeFeed<V::ResourceId>,
+        last_committed_index: u64,
+    ) {
+        let store = storage_manager.store();
+        if let Some(last_written_index) =
+            StatePtrRollback::last_batch_index_after(store, last_committed_index)
+        {
+            let done_signal = Default::default();
+            storage_manager.submit_write(Write::Rollback(Rollback::new(
+                last_committed_index + 1,
+                last_written_index,
+                indexes,
+                change_feed,
+                &done_signal,
+            )));
+            done_signal.wait_blocking();
+        }
+    }
+
     /// Builds resource accesses for a transaction by linking it into dependency chains.
     ///
     /// For each resource the transaction accesses, this either creates a new dependency chain or
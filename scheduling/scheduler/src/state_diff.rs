@@ -38,6 +38,28 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> StateDiff<S, V> {
         self.written_state.load().expect("written state unknown")
     }
 
+    /// Returns whether the batch left the resource at the version it read.
+    pub fn is_read_only(&self) -> bool {
+        self.read_state().version() == self.written_state().version()
+    }
+
+    /// Returns the state the batch read if `state`, which the batch's last access left, holds the
+    /// same state, e.g. because one transaction changed the resource and a later one changed it
+    /// back. Returns `state` otherwise.
+    pub(crate) fn restored_state(
+        &self,
+        state: Arc<StateVersion<V::ResourceId>>,
+    ) -> Arc<StateVersion<V::ResourceId>> {
+        match self.read_state.load() {
+            Some(read_state)
+                if read_state.version() != state.version() && state.has_same_state(&read_state) =>
+            {
+                read_state
+            }
+            _ => state,
+        }
+    }
+
     pub(crate) fn set_read_state(&self, state: Arc<StateVersion<V::ResourceId>>) {
         self.read_state.store(Some(state))
     }
@@ -60,12 +82,20 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> StateDiff<S, V> {
             panic!("written_state must be known at write time");
         };
 
-        if !batch.was_canceled() {
-            written_state.write_data(store);
+        // Read-only diffs leave no trace, so that rollback pointers only ever reference versions
+        // that were replaced (and can be pruned once their batch is finalized).
+        if !batch.was_canceled() && written_state.version() != read_state.version() {
+            written_state.write_data(store, &read_state, batch.version_encoding());
+            written_state.write_history_ptr(store, batch.index());
             read_state.write_rollback_ptr(store, batch.index());
         }
     }
 
+    /// Returns the size of the written data, which dominates the bytes the diff writes.
+    pub(crate) fn size_hint(&self) -> usize {
+        self.written_state.load().map_or(0, |written_state| written_state.len())
+    }
+
     pub(crate) fn write_done(self) {
         if let Some(batch) = self.batch.upgrade() {
             batch.decrease_pending_writes();
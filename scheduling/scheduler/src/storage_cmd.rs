@@ -1,9 +1,14 @@
+use std::sync::Arc;
+
+use vprogs_core_atomics::AtomicAsyncLatch;
+use vprogs_state_metadata::StateMetadata;
 use vprogs_state_space::StateSpace;
 use vprogs_storage_manager::{ReadCmd, WriteCmd};
 use vprogs_storage_types::{ReadStore, Store};
 
 use crate::{
-    ResourceAccess, RuntimeBatch, StateDiff, rollback::Rollback, vm_interface::VmInterface,
+    Prune, PruningState, ResourceAccess, RuntimeBatch, StateDiff, rollback::Rollback,
+    vm_interface::VmInterface,
 };
 
 pub enum Read<S: Store<StateSpace = StateSpace>, V: VmInterface> {
@@ -22,6 +27,8 @@ pub enum Write<S: Store<StateSpace = StateSpace>, V: VmInterface> {
     StateDiff(StateDiff<S, V>),
     CommitBatch(RuntimeBatch<S, V>),
     Rollback(Rollback<V>),
+    Prune(Prune<V>),
+    Finalize(PruningState, Arc<AtomicAsyncLatch>),
 }
 
 impl<S: Store<StateSpace = StateSpace>, V: VmInterface> WriteCmd<StateSpace> for Write<S, V> {
@@ -32,17 +39,31 @@ impl<S: Store<StateSpace = StateSpace>, V: VmInterface> WriteCmd<StateSpace> for
     ) -> ST::WriteBatch {
         match self {
             Write::StateDiff(state_diff) => state_diff.write(&mut wb),
-            Write::CommitBatch(batch) => batch.commit(&mut wb),
+            Write::CommitBatch(batch) => batch.commit(store, &mut wb),
             Write::Rollback(rollback) => return rollback.execute(store, wb),
+            Write::Prune(prune) => return prune.execute(store, wb),
+            // Keep finality across restarts, as batches are only pruned some time after it.
+            Write::Finalize(pruning, _) => {
+                StateMetadata::set_finalized_index(&mut wb, pruning.finalized_index())
+            }
         }
         wb
     }
 
+    fn size_hint(&self) -> usize {
+        match self {
+            Write::StateDiff(state_diff) => state_diff.size_hint(),
+            _ => 0,
+        }
+    }
+
     fn done(self) {
         match self {
             Write::StateDiff(state_diff) => state_diff.write_done(),
             Write::CommitBatch(batch) => batch.commit_done(),
             Write::Rollback(rollback) => rollback.done(),
+            Write::Prune(prune) => prune.done(),
+            Write::Finalize(_, done_signal) => done_signal.open(),
         }
     }
 }
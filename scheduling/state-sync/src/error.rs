@@ -0,0 +1,34 @@
+use std::fmt;
+
+/// Errors that can interrupt a state sync.
+///
+/// Syncing can be resumed after any of them, as all verified chunks are persisted.
+#[derive(Debug, Eq, PartialEq)]
+pub enum SyncError {
+    /// The connection to the peer was lost.
+    Disconnected,
+    /// The peer can not serve the state of the target batch, e.g. because it was pruned.
+    Unavailable,
+    /// The peer sent a chunk that does not match the trusted state root.
+    InvalidChunk,
+    /// State sync can only start on an empty store.
+    StoreNotEmpty,
+    /// The store holds the progress of a sync to a different target.
+    TargetMismatch,
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::Disconnected => write!(f, "the connection to the peer was lost"),
+            SyncError::Unavailable => write!(f, "the peer can not serve the requested state"),
+            SyncError::InvalidChunk => write!(f, "the peer sent an invalid chunk"),
+            SyncError::StoreNotEmpty => write!(f, "state sync requires an empty store"),
+            SyncError::TargetMismatch => write!(f, "the store is syncing to a different target"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+pub type SyncResult<T> = Result<T, SyncError>;
@@ -0,0 +1,12 @@
+mod error;
+mod message;
+mod progress;
+mod server;
+mod state_sync;
+mod transport;
+
+pub use error::{SyncError, SyncResult};
+pub use message::{ChunkRequest, ChunkResponse, SyncEntry};
+pub use server::SyncServer;
+pub use state_sync::{StateSync, SyncTarget};
+pub use transport::{ChannelTransport, SyncTransport};
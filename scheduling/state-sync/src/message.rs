@@ -0,0 +1,30 @@
+use vprogs_state_tree::Hash;
+
+/// Requests the resources of a subtree of the state tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChunkRequest {
+    /// The batch whose state is being synced.
+    pub batch_index: u64,
+    /// The hash of the subtree.
+    pub subtree: Hash,
+}
+
+/// Answers a [`ChunkRequest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChunkResponse {
+    /// All resources of the subtree, ordered by their key in the tree.
+    Entries(Vec<SyncEntry>),
+    /// The subtree holds too many resources for a single chunk, so its children have to be
+    /// requested separately.
+    Split { left: Hash, right: Hash },
+    /// The peer can not serve the requested state.
+    Unavailable,
+}
+
+/// A resource at its version as of the synced batch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyncEntry {
+    pub resource_id: Vec<u8>,
+    pub version: u64,
+    pub data: Vec<u8>,
+}
@@ -0,0 +1,53 @@
+use vprogs_state_tree::Hash;
+
+use crate::SyncTarget;
+
+/// A subtree that still has to be synced, located at `depth` on the path `path`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Subtree {
+    pub(crate) depth: usize,
+    pub(crate) path: Hash,
+    pub(crate) hash: Hash,
+}
+
+impl Subtree {
+    /// Returns the child on the given side.
+    pub(crate) fn child(&self, right: bool, hash: Hash) -> Self {
+        let mut path = self.path;
+        if right {
+            path[self.depth / 8] |= 1 << (7 - self.depth % 8);
+        }
+        Self { depth: self.depth + 1, path, hash }
+    }
+}
+
+/// Encodes the progress of a sync as `batch_index u64 || state_root || (depth u16 || path ||
+/// hash)*` for the Metadata column family.
+pub(crate) fn encode(target: &SyncTarget, pending: &[Subtree]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(40 + pending.len() * 66);
+    bytes.extend_from_slice(&target.batch_index.to_be_bytes());
+    bytes.extend_from_slice(&target.state_root);
+    for subtree in pending {
+        bytes.extend_from_slice(&(subtree.depth as u16).to_be_bytes());
+        bytes.extend_from_slice(&subtree.path);
+        bytes.extend_from_slice(&subtree.hash);
+    }
+    bytes
+}
+
+/// Decodes progress written by [`encode`].
+pub(crate) fn decode(bytes: &[u8]) -> (SyncTarget, Vec<Subtree>) {
+    let target = SyncTarget {
+        batch_index: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+        state_root: bytes[8..40].try_into().unwrap(),
+    };
+    let pending = bytes[40..]
+        .chunks_exact(66)
+        .map(|chunk| Subtree {
+            depth: u16::from_be_bytes(chunk[..2].try_into().unwrap()) as usize,
+            path: chunk[2..34].try_into().unwrap(),
+            hash: chunk[34..].try_into().unwrap(),
+        })
+        .collect();
+    (target, pending)
+}
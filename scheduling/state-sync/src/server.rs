@@ -0,0 +1,83 @@
+use std::marker::PhantomData;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::{EMPTY_HASH, Node, StateTree};
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::ReadStore;
+
+use crate::{ChunkRequest, ChunkResponse, SyncEntry};
+
+/// Serves state chunks to syncing peers.
+///
+/// Requests are answered from the state tree of the requested batch, so a node can keep executing
+/// batches while serving, as long as the requested batch is not reverted or pruned.
+pub struct SyncServer<S, R> {
+    store: S,
+    max_entries: usize,
+    _marker: PhantomData<R>,
+}
+
+impl<S: ReadStore<StateSpace = StateSpace>, R: ResourceId> SyncServer<S, R> {
+    pub fn new(store: S) -> Self {
+        Self { store, max_entries: 1024, _marker: PhantomData }
+    }
+
+    /// Sets the maximum number of resources per chunk. Larger subtrees are split.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        assert!(max_entries > 0, "chunks must hold at least one resource");
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn handle(&self, request: &ChunkRequest) -> ChunkResponse {
+        if StateTree::root(&self.store, request.batch_index).is_none() {
+            return ChunkResponse::Unavailable;
+        }
+
+        // Collect the keys of the subtree's leaves from left to right.
+        let mut keys = Vec::new();
+        let mut stack = vec![request.subtree];
+        while let Some(hash) = stack.pop() {
+            if hash == EMPTY_HASH {
+                continue;
+            }
+
+            match StateTree::node(&self.store, &hash) {
+                None => return ChunkResponse::Unavailable,
+                Some(Node::Leaf { key, .. }) => keys.push(key),
+                Some(Node::Internal { left, right }) => stack.extend([right, left]),
+            }
+
+            if keys.len() > self.max_entries {
+                let Some(Node::Internal { left, right }) =
+                    StateTree::node(&self.store, &request.subtree)
+                else {
+                    unreachable!("only internal nodes hold more than one leaf");
+                };
+                return ChunkResponse::Split { left, right };
+            }
+        }
+
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            let Some(resource_id_bytes) = StateTree::resource_id(&self.store, &key) else {
+                panic!("missing resource of tree key {key:?}");
+            };
+            let resource_id = R::from_bytes(&resource_id_bytes);
+            // Corrupted data is not served, as it would fail verification on the receiving node.
+            let Ok(Some(state)) =
+                StateVersion::from_historical_data(&self.store, resource_id, request.batch_index)
+            else {
+                return ChunkResponse::Unavailable;
+            };
+            entries.push(SyncEntry {
+                resource_id: resource_id_bytes,
+                version: state.version(),
+                data: state.data().clone(),
+            });
+        }
+
+        ChunkResponse::Entries(entries)
+    }
+}
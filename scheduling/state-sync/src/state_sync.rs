@@ -0,0 +1,208 @@
+use std::marker::PhantomData;
+
+use vprogs_core_types::ResourceId;
+use vprogs_scheduling_scheduler::{ExecutionConfig, Scheduler, VmInterface};
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::{EMPTY_HASH, Hash, Node, StateTree, key_hash, value_hash};
+use vprogs_state_tree_proof::{goes_right, internal_hash, subtree_hash};
+use vprogs_state_version::{StateVersion, VersionEncoding};
+use vprogs_storage_manager::StorageConfig;
+use vprogs_storage_types::Store;
+
+use crate::{
+    ChunkRequest, ChunkResponse, SyncEntry, SyncError, SyncResult, SyncTransport,
+    progress::{self, Subtree},
+};
+
+/// The trusted state a node syncs to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SyncTarget {
+    pub batch_index: u64,
+    pub state_root: Hash,
+}
+
+/// Syncs an empty store to the state of a trusted batch by fetching it from a peer.
+///
+/// The state tree is downloaded top-down: every requested subtree is answered either with all of
+/// its resources, which form a contiguous key range, or with its two children if it is too large
+/// for a single chunk. Each response is verified against the subtree's hash, which is known from
+/// the trusted root, and committed together with the remaining work. An interrupted sync thus
+/// resumes where it stopped, even across restarts.
+pub struct StateSync<S, R> {
+    store: S,
+    target: SyncTarget,
+    encoding: VersionEncoding,
+    pending: Vec<Subtree>,
+    _marker: PhantomData<R>,
+}
+
+impl<S: Store<StateSpace = StateSpace>, R: ResourceId> StateSync<S, R> {
+    /// Starts syncing `store` to `target`, or resumes an earlier sync to the same target.
+    ///
+    /// The data is stored with the given encoding, which should match the one the scheduler
+    /// created by [`Self::into_scheduler`] is configured with.
+    pub fn new(store: S, target: SyncTarget, encoding: VersionEncoding) -> SyncResult<Self> {
+        let pending = match StateMetadata::sync_progress(&store) {
+            Some(bytes) => {
+                let (stored_target, pending) = progress::decode(&bytes);
+                if stored_target != target {
+                    return Err(SyncError::TargetMismatch);
+                }
+                pending
+            }
+            None if Self::is_synced(&store, &target) => Vec::new(),
+            None if Self::is_empty(&store) => {
+                vec![Subtree { depth: 0, path: EMPTY_HASH, hash: target.state_root }]
+            }
+            None => return Err(SyncError::StoreNotEmpty),
+        };
+
+        Ok(Self { store, target, encoding, pending, _marker: PhantomData })
+    }
+
+    pub fn target(&self) -> &SyncTarget {
+        &self.target
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Fetches chunks from the peer until the state is complete.
+    ///
+    /// On error, all chunks received so far are kept and calling `run` again resumes the sync.
+    pub fn run<T: SyncTransport>(&mut self, transport: &T) -> SyncResult<()> {
+        while let Some(subtree) = self.pending.last().copied() {
+            self.sync_subtree(transport, subtree)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a scheduler on the synced store, which continues after the synced batch.
+    ///
+    /// # Panics
+    /// Panics if the sync is not complete.
+    pub fn into_scheduler<V>(
+        self,
+        execution_config: ExecutionConfig<V>,
+        storage_config: StorageConfig<S>,
+    ) -> Scheduler<S, V>
+    where
+        V: VmInterface<ResourceId = R>,
+    {
+        assert!(self.is_done(), "state sync is not complete");
+        Scheduler::new(execution_config, storage_config.with_store(self.store))
+    }
+
+    /// Fetches, verifies and commits a single subtree.
+    fn sync_subtree<T: SyncTransport>(
+        &mut self,
+        transport: &T,
+        subtree: Subtree,
+    ) -> SyncResult<()> {
+        let response = match subtree.hash {
+            EMPTY_HASH => ChunkResponse::Entries(Vec::new()),
+            hash => transport
+                .request(ChunkRequest { batch_index: self.target.batch_index, subtree: hash })?,
+        };
+
+        let mut write_batch = self.store.write_batch();
+        self.pending.pop();
+        match response {
+            ChunkResponse::Unavailable => {
+                self.pending.push(subtree);
+                return Err(SyncError::Unavailable);
+            }
+            ChunkResponse::Split { left, right } => {
+                if subtree.depth >= 256 || internal_hash(&left, &right) != subtree.hash {
+                    self.pending.push(subtree);
+                    return Err(SyncError::InvalidChunk);
+                }
+
+                StateTree::put_node(&mut write_batch, &Node::Internal { left, right });
+                // Pushed in reverse, so that the left child is synced first.
+                for (is_right, hash) in [(true, right), (false, left)] {
+                    if hash != EMPTY_HASH {
+                        self.pending.push(subtree.child(is_right, hash));
+                    }
+                }
+            }
+            ChunkResponse::Entries(entries) => {
+                if !Self::verify_entries(&subtree, &entries) {
+                    self.pending.push(subtree);
+                    return Err(SyncError::InvalidChunk);
+                }
+
+                // The entries match the trusted root, so their resource ids are well-formed.
+                let resource_ids: Vec<R> =
+                    entries.iter().map(|entry| R::from_bytes(&entry.resource_id)).collect();
+                let states: Vec<_> = (resource_ids.iter().zip(entries))
+                    .map(|(id, entry)| StateVersion::new(id.clone(), entry.version, entry.data))
+                    .collect();
+
+                for (resource_id, state) in resource_ids.iter().zip(&states) {
+                    let base = StateVersion::empty(resource_id.clone());
+                    state.write_data(&mut write_batch, &base, self.encoding);
+                    state.write_latest_ptr(&mut write_batch);
+                    state.write_history_ptr(&mut write_batch, self.target.batch_index);
+                }
+                StateVersion::<R>::update_content_refs(
+                    &self.store,
+                    &mut write_batch,
+                    states.iter().filter_map(|state| Some((state.written_content()?, 1))),
+                );
+                StateTree::build(
+                    &self.store,
+                    &mut write_batch,
+                    subtree.depth,
+                    (resource_ids.iter().zip(&states))
+                        .map(|(id, state)| (id, state.version(), state.data().as_slice())),
+                );
+            }
+        }
+
+        if self.pending.is_empty() {
+            // Publish the synced state, so that a scheduler continues after the synced batch.
+            StateTree::put_root(&mut write_batch, self.target.batch_index, &self.target.state_root);
+            StateMetadata::set_pruned_index(&mut write_batch, self.target.batch_index);
+            StateMetadata::set_last_committed_index(&mut write_batch, self.target.batch_index);
+            StateMetadata::delete_sync_progress(&mut write_batch);
+        } else {
+            StateMetadata::set_sync_progress(
+                &mut write_batch,
+                &progress::encode(&self.target, &self.pending),
+            );
+        }
+        self.store.commit(write_batch);
+
+        Ok(())
+    }
+
+    /// Checks that the entries are exactly the leaves of the subtree.
+    fn verify_entries(subtree: &Subtree, entries: &[SyncEntry]) -> bool {
+        let leaves: Vec<(Hash, Hash)> = entries
+            .iter()
+            .map(|entry| (key_hash(&entry.resource_id), value_hash(entry.version, &entry.data)))
+            .collect();
+
+        let is_sorted = leaves.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        let is_in_subtree = leaves.iter().all(|(key, _)| {
+            (0..subtree.depth)
+                .all(|depth| goes_right(key, depth) == goes_right(&subtree.path, depth))
+        });
+
+        is_sorted && is_in_subtree && subtree_hash(subtree.depth, &leaves) == subtree.hash
+    }
+
+    fn is_synced(store: &S, target: &SyncTarget) -> bool {
+        StateMetadata::last_committed_index(store) == target.batch_index
+            && StateTree::root(store, target.batch_index) == Some(target.state_root)
+    }
+
+    fn is_empty(store: &S) -> bool {
+        StateMetadata::last_committed_index(store) == 0
+            && StatePtrLatest::iter(store).next().is_none()
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+};
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_types::ReadStore;
+
+use crate::{ChunkRequest, ChunkResponse, SyncError, SyncResult, SyncServer};
+
+/// Carries chunk requests to a peer and returns its responses.
+pub trait SyncTransport {
+    /// Sends a request to the peer and waits for its response.
+    fn request(&self, request: ChunkRequest) -> SyncResult<ChunkResponse>;
+}
+
+/// In-process transport that talks to a [`SyncServer`] running on its own thread.
+///
+/// The server thread exits once all clones of the transport are dropped.
+#[derive(Clone)]
+pub struct ChannelTransport {
+    requests: Sender<(ChunkRequest, Sender<ChunkResponse>)>,
+}
+
+impl ChannelTransport {
+    /// Starts a thread serving requests from `server` and returns a transport connected to it.
+    pub fn serve<S, R>(server: SyncServer<S, R>) -> (Self, JoinHandle<()>)
+    where
+        S: ReadStore<StateSpace = StateSpace> + Send + 'static,
+        R: ResourceId,
+    {
+        let (requests, incoming) = mpsc::channel::<(ChunkRequest, Sender<ChunkResponse>)>();
+        let handle = thread::spawn(move || {
+            for (request, reply) in incoming {
+                // The requester may have given up waiting, which is not an error of the server.
+                let _ = reply.send(server.handle(&request));
+            }
+        });
+        (Self { requests }, handle)
+    }
+}
+
+impl SyncTransport for ChannelTransport {
+    fn request(&self, request: ChunkRequest) -> SyncResult<ChunkResponse> {
+        let (reply, response) = mpsc::channel();
+        self.requests.send((request, reply)).map_err(|_| SyncError::Disconnected)?;
+        response.recv().map_err(|_| SyncError::Disconnected)
+    }
+}
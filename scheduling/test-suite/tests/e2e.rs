@@ -1,11 +1,36 @@
 extern crate core;
 
+use std::{cell::Cell, collections::BTreeSet, sync::Arc, thread, time::Duration};
+
 use tempfile::TempDir;
-use vprogs_scheduling_scheduler::{ExecutionConfig, Scheduler};
-use vprogs_storage_manager::StorageConfig;
+use tokio::sync::mpsc::UnboundedReceiver;
+use vprogs_core_types::ResourceId;
+use vprogs_scheduling_scheduler::{ChangeEvent, ExecutionConfig, Scheduler};
+use vprogs_scheduling_state_sync::{
+    ChannelTransport, ChunkRequest, ChunkResponse, StateSync, SyncError, SyncResult, SyncServer,
+    SyncTarget, SyncTransport,
+};
+use vprogs_state_changeset::{Changeset, ChangesetError};
+use vprogs_state_fsck::{Fsck, FsckIssue};
+use vprogs_state_index::{SecondaryIndexes, StateIndex};
+use vprogs_state_metadata::{BatchHeader, StateMetadata};
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_snapshot::{self as snapshot, SnapshotError};
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::{EMPTY_HASH, Node, Proof, StateTree, key_hash, value_hash};
+use vprogs_state_tree_proof::{chunked_data_hash, data_hash, versioned_value_hash};
+use vprogs_state_typed::{TypedState, TypedStateError};
+use vprogs_state_version::{CHUNK_SIZE, CorruptionError, StateVersion, VersionEncoding};
+use vprogs_storage_encrypted_store::{EncryptedStore, EncryptionConfig, Keyring};
+use vprogs_storage_manager::{StorageConfig, WriteConfig};
 use vprogs_storage_rocksdb_store::RocksDbStore;
+use vprogs_storage_types::{Store, WriteBatch};
 
-use crate::test_framework::{Access, AssertResourceDeleted, AssertWrittenState, TestVM, Tx};
+use crate::test_framework::{
+    Access, AssertBatchRolledBack, AssertHistoricalState, AssertResourceDeleted,
+    AssertVersionDeleted, AssertWrittenState, Counter, PersistUncommittedWrite, TestVM, Tx,
+    WriterLog,
+};
 
 #[test]
 pub fn test_runtime() {
@@ -530,85 +555,1797 @@ pub fn test_rollback_interleaved_multi_resource() {
     }
 }
 
-mod test_framework {
-    use vprogs_core_types::{AccessMetadata, AccessType, Transaction};
-    use vprogs_scheduling_scheduler::{AccessHandle, RuntimeBatch, VmInterface};
-    use vprogs_state_space::StateSpace;
-    use vprogs_state_version::StateVersion;
-    use vprogs_storage_types::{ReadStore, Store};
+/// Tests that a rollback removes the rollback pointers of all reverted batches.
+#[test]
+pub fn test_rollback_removes_rollback_ptrs() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
 
-    #[derive(Clone)]
-    pub struct TestVM;
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1)]), Tx(3, vec![Access::Write(2)])]);
+        let batch3 =
+            runtime.schedule(vec![Tx(4, vec![Access::Write(1)]), Tx(5, vec![Access::Write(3)])]);
+        batch3.wait_committed_blocking();
 
-    impl VmInterface for TestVM {
-        type Transaction = Tx;
-        type TransactionEffects = ();
-        type ResourceId = usize;
-        type AccessMetadata = Access;
-        type Error = ();
+        runtime.rollback_to(1);
 
-        fn process_transaction<S: Store<StateSpace = StateSpace>>(
-            &self,
-            tx: &Self::Transaction,
-            resources: &mut [AccessHandle<S, Self>],
-        ) -> Result<(), Self::Error> {
-            for resource in resources {
-                if resource.access_metadata().access_type() == AccessType::Write {
-                    resource.data_mut().extend_from_slice(&tx.0.to_be_bytes());
-                }
-            }
-            Ok::<(), ()>(())
+        AssertBatchRolledBack(2).assert(runtime.storage_manager().store());
+        AssertBatchRolledBack(3).assert(runtime.storage_manager().store());
+        AssertWrittenState(1, vec![1]).assert(runtime.storage_manager().store());
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that pruning removes the history of finalized batches while later batches can still be
+/// rolled back.
+#[test]
+pub fn test_pruning() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1), Access::Read(2)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(2)])]);
+        let batch4 = runtime.schedule(vec![Tx(4, vec![Access::Write(1)])]);
+        batch4.wait_committed_blocking();
+
+        runtime.prune_to(3);
+        while runtime.pruning().pruned_index() < 3 {
+            thread::sleep(Duration::from_millis(1));
         }
 
-        fn notarize_batch<S: Store<StateSpace = StateSpace>>(&self, batch: &RuntimeBatch<S, Self>) {
-            eprintln!(
-                ">> Processed batch with {} transactions and {} state changes",
-                batch.txs().len(),
-                batch.state_diffs().len()
-            );
+        for index in 1..=3 {
+            AssertBatchRolledBack(index).assert(runtime.storage_manager().store());
         }
+        // Version 1 of resource 1 was replaced in batch 2, version 2 is still needed by batch 4.
+        AssertVersionDeleted(1, 1).assert(runtime.storage_manager().store());
+
+        runtime.rollback_to(3);
+
+        AssertWrittenState(1, vec![1, 2]).assert(runtime.storage_manager().store());
+        AssertWrittenState(2, vec![3]).assert(runtime.storage_manager().store());
+
+        runtime.shutdown();
     }
+}
 
-    pub struct Tx(pub usize, pub Vec<Access>);
+/// Tests that resources can be read as of earlier batches, also after rollbacks and pruning.
+#[test]
+pub fn test_historical_state() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
 
-    impl Transaction<usize, Access> for Tx {
-        fn accessed_resources(&self) -> &[<TestVM as VmInterface>::AccessMetadata] {
-            &self.1
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(2)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(2)])]);
+        runtime.schedule(vec![Tx(4, vec![Access::Write(1)])]);
+        let batch5 = runtime.schedule(vec![Tx(5, vec![Access::Write(1), Access::Read(2)])]);
+        batch5.wait_committed_blocking();
+
+        for assertion in [
+            AssertHistoricalState(1, 0, vec![]),
+            AssertHistoricalState(1, 1, vec![1]),
+            AssertHistoricalState(1, 3, vec![1, 2]),
+            AssertHistoricalState(1, 5, vec![1, 2, 4, 5]),
+            AssertHistoricalState(2, 1, vec![]),
+            AssertHistoricalState(2, 2, vec![2]),
+            AssertHistoricalState(2, 5, vec![2, 3]),
+        ] {
+            assertion.assert(runtime.storage_manager().store());
+        }
+
+        // Batches 4 and 5 both wrote resource 1, so both of their versions must be removed.
+        runtime.rollback_to(2);
+        AssertVersionDeleted(1, 3).assert(runtime.storage_manager().store());
+        AssertVersionDeleted(1, 4).assert(runtime.storage_manager().store());
+
+        let batch3 = runtime.schedule(vec![Tx(6, vec![Access::Write(2)])]);
+        batch3.wait_committed_blocking();
+
+        for assertion in
+            [AssertHistoricalState(1, 3, vec![1, 2]), AssertHistoricalState(2, 3, vec![2, 6])]
+        {
+            assertion.assert(runtime.storage_manager().store());
+        }
+
+        // The rolled back batches 4 and 5 are no longer committed.
+        for batch_index in [4, 5] {
+            assert!(
+                StateVersion::from_historical_data(
+                    runtime.storage_manager().store(),
+                    1usize,
+                    batch_index
+                )
+                .unwrap()
+                .is_none()
+            );
+        }
+
+        runtime.prune_to(2);
+        while runtime.pruning().pruned_index() < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(
+            StateVersion::from_historical_data(runtime.storage_manager().store(), 1usize, 1)
+                .unwrap()
+                .is_none()
+        );
+        for assertion in [
+            AssertHistoricalState(1, 2, vec![1, 2]),
+            AssertHistoricalState(2, 2, vec![2]),
+            AssertHistoricalState(2, 3, vec![2, 6]),
+        ] {
+            assertion.assert(runtime.storage_manager().store());
         }
+
+        runtime.shutdown();
     }
+}
 
-    #[derive(Clone)]
-    pub enum Access {
-        Read(usize),
-        Write(usize),
+/// Tests that state roots only depend on the written state and that rollback restores them.
+#[test]
+pub fn test_state_root() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let other_temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        let batch1 = runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        let batch2 = runtime.schedule(vec![Tx(2, vec![Access::Write(2), Access::Write(3)])]);
+        let batch3 = runtime.schedule(vec![Tx(3, vec![Access::Read(1)])]);
+        let batch4 = runtime.schedule(vec![Tx(4, vec![Access::Write(1)])]);
+        batch4.wait_committed_blocking();
+
+        let roots: Vec<_> = [&batch1, &batch2, &batch3, &batch4]
+            .iter()
+            .map(|batch| batch.state_root().expect("committed batch must have a state root"))
+            .collect();
+        assert_ne!(roots[0], roots[1]);
+        assert_eq!(roots[1], roots[2], "read-only batches must not change the state root");
+        assert_ne!(roots[2], roots[3]);
+        for (index, root) in (1..).zip(&roots) {
+            assert_eq!(StateTree::root(runtime.storage_manager().store(), index), Some(*root));
+        }
+
+        // The same writes in a different grouping lead to the same root.
+        let other_storage: RocksDbStore = RocksDbStore::open(other_temp_dir.path());
+        let mut other_runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(other_storage),
+        );
+        other_runtime.schedule(vec![Tx(2, vec![Access::Write(3)])]);
+        let other_batch = other_runtime
+            .schedule(vec![Tx(1, vec![Access::Write(1)]), Tx(2, vec![Access::Write(2)])]);
+        other_batch.wait_committed_blocking();
+        assert_eq!(other_batch.state_root(), Some(roots[1]));
+        other_runtime.shutdown();
+
+        runtime.rollback_to(1);
+        assert_eq!(StateTree::root(runtime.storage_manager().store(), 1), Some(roots[0]));
+        assert_eq!(StateTree::root(runtime.storage_manager().store(), 2), None);
+        assert_eq!(StateTree::root(runtime.storage_manager().store(), 4), None);
+
+        // Re-applying the reverted writes leads back to the same root.
+        let batch2 = runtime.schedule(vec![Tx(2, vec![Access::Write(2), Access::Write(3)])]);
+        batch2.wait_committed_blocking();
+        assert_eq!(batch2.state_root(), Some(roots[1]));
+
+        runtime.shutdown();
     }
+}
 
-    impl AccessMetadata<usize> for Access {
-        fn id(&self) -> usize {
-            match self {
-                Access::Read(id) => *id,
-                Access::Write(id) => *id,
+/// Tests that the tree nodes of reverted and pruned batches are deleted once no remaining root
+/// references them.
+#[test]
+pub fn test_state_tree_garbage_collection() {
+    /// Asserts that the stored tree nodes are exactly the ones reachable from the stored roots.
+    fn assert_reachable_nodes(store: &RocksDbStore) {
+        let mut reachable = BTreeSet::new();
+        let mut pending: Vec<_> = store
+            .iter_from(StateSpace::StateTreeRoot, &[])
+            .map(|(_, root)| <[u8; 32]>::try_from(&root[..]).unwrap())
+            .collect();
+        while let Some(hash) = pending.pop() {
+            if hash == EMPTY_HASH || !reachable.insert(hash) {
+                continue;
+            }
+            let node = StateTree::node(store, &hash).expect("reachable tree node must be stored");
+            if let Node::Internal { left, right } = node {
+                pending.extend([left, right]);
             }
         }
 
-        fn access_type(&self) -> AccessType {
-            match self {
-                Access::Read(_) => AccessType::Read,
-                Access::Write(_) => AccessType::Write,
-            }
+        let stored: BTreeSet<_> = store
+            .iter_from(StateSpace::StateTreeNode, &[])
+            .map(|(key, _)| <[u8; 32]>::try_from(&key[..]).unwrap())
+            .collect();
+        assert_eq!(stored, reachable);
+    }
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(3), Access::Write(4)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]);
+        let batch4 = runtime.schedule(vec![Tx(4, vec![Access::Write(2), Access::Write(5)])]);
+        batch4.wait_committed_blocking();
+        assert_reachable_nodes(runtime.storage_manager().store());
+
+        // Reverting batches deletes the nodes only their roots referenced.
+        runtime.rollback_to(2);
+        assert_reachable_nodes(runtime.storage_manager().store());
+
+        let batch3 = runtime.schedule(vec![Tx(5, vec![Access::Write(3)])]);
+        let batch4 = runtime.schedule(vec![Tx(6, vec![Access::Write(1), Access::Write(6)])]);
+        batch4.wait_committed_blocking();
+        assert_reachable_nodes(runtime.storage_manager().store());
+
+        // Pruning deletes the roots of pruned batches and the nodes the later roots don't share.
+        runtime.prune_to(3);
+        while runtime.pruning().pruned_index() < 3 {
+            thread::sleep(Duration::from_millis(1));
         }
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateTree::root(store, 1), None);
+        assert_eq!(StateTree::root(store, 2), None);
+        assert_eq!(StateTree::root(store, 3), batch3.state_root());
+        assert_reachable_nodes(store);
+
+        // The remaining roots still prove their resources.
+        let root = batch4.state_root().expect("committed batch must have a state root");
+        let proof = StateTree::prove(store, 4, &6usize).expect("committed batch must be provable");
+        let value = value_hash(1, &6usize.to_be_bytes());
+        assert!(proof.verify(&root, &key_hash(&6usize.to_bytes()), Some(&value)));
+
+        runtime.shutdown();
     }
+}
 
-    pub struct AssertWrittenState(pub usize, pub Vec<usize>);
+/// Tests inclusion and non-inclusion proofs against the state roots of committed batches.
+#[test]
+pub fn test_state_proofs() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
 
-    impl AssertWrittenState {
-        pub fn assert<S: ReadStore<StateSpace = StateSpace>>(&self, store: &S) {
-            let writer_count = self.1.len();
-            let writer_log: Vec<u8> = self.1.iter().flat_map(|id| id.to_be_bytes()).collect();
+        let batch1 = runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+        let batch2 = runtime.schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(3)])]);
+        batch2.wait_committed_blocking();
+
+        let store = runtime.storage_manager().store();
+        let key = |id: usize| key_hash(&id.to_bytes());
+        let root1 = batch1.state_root().unwrap();
+        let root2 = batch2.state_root().unwrap();
+
+        // Membership of the latest version as of each batch.
+        let proof = StateTree::prove(store, 2, &1usize).unwrap();
+        let value = value_hash(2, &[1usize.to_be_bytes(), 2usize.to_be_bytes()].concat());
+        assert!(proof.verify(&root2, &key(1), Some(&value)));
+        assert!(!proof.verify(&root1, &key(1), Some(&value)));
+        assert!(!proof.verify(&root2, &key(1), Some(&value_hash(1, &1usize.to_be_bytes()))));
+        assert!(!proof.verify(&root2, &key(1), None));
+
+        let proof = StateTree::prove(store, 1, &1usize).unwrap();
+        assert!(proof.verify(&root1, &key(1), Some(&value_hash(1, &1usize.to_be_bytes()))));
+
+        // Non-membership of a resource that was only written later, and of one that never was.
+        let proof = StateTree::prove(store, 1, &3usize).unwrap();
+        assert!(proof.verify(&root1, &key(3), None));
+        assert!(!proof.verify(&root2, &key(3), None));
+        let proof = StateTree::prove(store, 2, &4usize).unwrap();
+        assert!(proof.verify(&root2, &key(4), None));
+
+        // Proofs survive serialization.
+        let proof = StateTree::prove(store, 2, &3usize).unwrap();
+        let decoded = Proof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+        assert!(decoded.verify(&root2, &key(3), Some(&value_hash(1, &2usize.to_be_bytes()))));
+        assert!(Proof::from_bytes(&proof.to_bytes()[1..]).is_none());
+
+        assert!(StateTree::prove(store, 3, &1usize).is_none());
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that a scheduler resumes after the last committed batch when reopening its store.
+#[test]
+pub fn test_resume_after_restart() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(2)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(2)])]);
+        runtime.schedule(vec![Tx(4, vec![Access::Write(3)])]).wait_committed_blocking();
+
+        // Reverted batches are not resumed from.
+        runtime.rollback_to(3);
+        runtime.shutdown();
+    }
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        assert_eq!(runtime.context().last_batch_index(), 3);
+
+        let batch = runtime.schedule(vec![Tx(5, vec![Access::Write(1)])]);
+        assert_eq!(batch.index(), 4);
+        batch.wait_committed_blocking();
+        drop(batch);
+
+        for assertion in [AssertWrittenState(1, vec![1, 2, 5]), AssertWrittenState(2, vec![2, 3])] {
+            assertion.assert(runtime.storage_manager().store());
+        }
+        AssertResourceDeleted(3).assert(runtime.storage_manager().store());
+        runtime.shutdown();
+    }
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        assert_eq!(runtime.context().last_batch_index(), 4);
+
+        // Batches committed before the restart can still be rolled back.
+        runtime.rollback_to(1);
+        AssertWrittenState(1, vec![1]).assert(runtime.storage_manager().store());
+        AssertResourceDeleted(2).assert(runtime.storage_manager().store());
+
+        let batch = runtime.schedule(vec![Tx(6, vec![Access::Write(2)])]);
+        assert_eq!(batch.index(), 2);
+        batch.wait_committed_blocking();
+        AssertWrittenState(2, vec![6]).assert(runtime.storage_manager().store());
+        drop(batch);
+        runtime.shutdown();
+    }
+}
+
+/// Tests that writes of batches that were persisted but not committed are removed on startup.
+#[test]
+pub fn test_crash_recovery() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime
+            .schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(2)])])
+            .wait_committed_blocking();
+        runtime.shutdown();
+    }
+    {
+        // Batches 3 and 5 were partially persisted, batch 4 only read.
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        PersistUncommittedWrite(3, 1, 3).apply(&storage);
+        PersistUncommittedWrite(3, 3, 3).apply(&storage);
+        PersistUncommittedWrite(5, 1, 5).apply(&storage);
+
+        // The history of uncommitted batches is not visible.
+        AssertHistoricalState(1, 2, vec![1, 2]).assert(&storage);
+        assert!(StateVersion::from_historical_data(&storage, 1usize, 5).unwrap().is_none());
+    }
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        assert_eq!(runtime.context().last_batch_index(), 2);
+
+        let store = runtime.storage_manager().store();
+        AssertBatchRolledBack(3).assert(store);
+        AssertBatchRolledBack(5).assert(store);
+        AssertVersionDeleted(1, 3).assert(store);
+        AssertVersionDeleted(1, 4).assert(store);
+        AssertVersionDeleted(3, 1).assert(store);
+        AssertResourceDeleted(3).assert(store);
+        AssertWrittenState(1, vec![1, 2]).assert(store);
+        AssertHistoricalState(1, 2, vec![1, 2]).assert(store);
+
+        let batch = runtime.schedule(vec![Tx(6, vec![Access::Write(1), Access::Write(3)])]);
+        assert_eq!(batch.index(), 3);
+        batch.wait_committed_blocking();
+        AssertWrittenState(1, vec![1, 2, 6]).assert(runtime.storage_manager().store());
+        AssertWrittenState(3, vec![6]).assert(runtime.storage_manager().store());
+        drop(batch);
+        runtime.shutdown();
+    }
+}
 
-            let versioned_state = StateVersion::<usize>::from_latest_data(store, self.0);
-            assert_eq!(versioned_state.version(), writer_count as u64);
-            assert_eq!(*versioned_state.data(), writer_log);
+/// Tests that the consistency checker reports and repairs inconsistencies.
+#[test]
+pub fn test_fsck() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime
+            .schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(2)])])
+            .wait_committed_blocking();
+        runtime.rollback_to(1);
+        runtime.shutdown();
+    }
+
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let fsck = Fsck::<usize>::new().with_data_check(|_, data| match data.len() % 8 {
+        0 => Ok(()),
+        _ => Err("not a writer log".to_string()),
+    });
+
+    let report = fsck.check(&storage);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    assert_eq!(report.last_committed_index, 1);
+    assert_eq!(report.resources, 1);
+    assert_eq!(report.versions, 1);
+
+    // Batch 2 was persisted but not committed, and resource 2 has an unreferenced version.
+    PersistUncommittedWrite(2, 1, 3).apply(&storage);
+    let mut write_batch = storage.write_batch();
+    StateVersion::put(&mut write_batch, 7, &2usize, &[0]);
+    storage.commit(write_batch);
+
+    let report = fsck.check(&storage);
+    assert_eq!(
+        report.issues,
+        vec![
+            FsckIssue::UncommittedRollbackPtr {
+                batch_index: 2,
+                resource_id: 1usize.to_bytes(),
+                old_version: 1,
+            },
+            FsckIssue::InvalidData {
+                resource_id: 2usize.to_bytes(),
+                version: 7,
+                reason: "not a writer log".to_string(),
+            },
+            FsckIssue::OrphanVersion { resource_id: 2usize.to_bytes(), version: 7 },
+        ]
+    );
+    assert!(!report.issues[1].is_repairable());
+
+    assert_eq!(fsck.repair(&storage, &report), 2);
+    let report = fsck.check(&storage);
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    AssertWrittenState(1, vec![1]).assert(&storage);
+    AssertBatchRolledBack(2).assert(&storage);
+    AssertVersionDeleted(1, 2).assert(&storage);
+}
+
+/// Tests that delta-encoded versions load correctly across rollback and pruning.
+#[test]
+pub fn test_delta_encoding() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default()
+                .with_vm(TestVM)
+                .with_version_encoding(VersionEncoding::Delta { full_interval: 4 }),
+            StorageConfig::default().with_store(storage),
+        );
+        for id in 1..=10 {
+            runtime.schedule(vec![Tx(id, vec![Access::Write(1)])]).wait_committed_blocking();
+        }
+
+        // Versions starting an interval are stored in full, later ones once the delta is smaller.
+        let store = runtime.storage_manager().store();
+        let delta_bases: Vec<_> =
+            (1..=10).map(|version| StateVersion::delta_base(store, version, &1usize)).collect();
+        assert_eq!(
+            delta_bases,
+            vec![None, None, None, None, Some(4), Some(5), Some(6), None, Some(8), Some(9)]
+        );
+        AssertWrittenState(1, (1..=10).collect()).assert(store);
+        AssertHistoricalState(1, 6, (1..=6).collect()).assert(store);
+
+        runtime.rollback_to(9);
+        AssertVersionDeleted(1, 10).assert(runtime.storage_manager().store());
+        AssertWrittenState(1, (1..=9).collect()).assert(runtime.storage_manager().store());
+
+        // Pruning stores the remaining version in full before deleting its base.
+        runtime.prune_to(9);
+        while runtime.pruning().pruned_index() < 9 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateVersion::delta_base(store, 9, &1usize), None);
+        AssertVersionDeleted(1, 8).assert(store);
+        AssertWrittenState(1, (1..=9).collect()).assert(store);
+        assert!(Fsck::<usize>::new().check(store).is_consistent());
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that content-addressed versions store equal data once and release it when the last
+/// version referencing it is rolled back or pruned.
+#[test]
+pub fn test_content_addressed_versions() {
+    let stored_content = |store: &RocksDbStore| -> usize {
+        StateVersion::<usize>::iter_content(store)
+            .filter(|(_, reference)| reference.is_none())
+            .count()
+    };
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default()
+                .with_vm(TestVM)
+                .with_version_encoding(VersionEncoding::ContentAddressed),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime
+            .schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2), Access::Write(3)])])
+            .wait_committed_blocking();
+
+        // All three resources hold the same writer log, which is stored once.
+        let store = runtime.storage_manager().store();
+        let hash = StateVersion::content_hash(store, 1, &1usize).expect("content hash");
+        assert_eq!(StateVersion::content_hash(store, 1, &2usize), Some(hash));
+        assert_eq!(StateVersion::<usize>::content_refs(store, &hash), 3);
+        assert_eq!(stored_content(store), 1);
+        AssertWrittenState(3, vec![1]).assert(store);
+
+        // Rolling back the only versions referencing content deletes it.
+        runtime
+            .schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(2)])])
+            .wait_committed_blocking();
+        assert_eq!(stored_content(runtime.storage_manager().store()), 2);
+        runtime.rollback_to(1);
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateVersion::<usize>::content_refs(store, &hash), 3);
+        assert_eq!(stored_content(store), 1);
+        AssertWrittenState(1, vec![1]).assert(store);
+
+        // Pruning keeps content as long as a remaining version references it.
+        runtime
+            .schedule(vec![Tx(4, vec![Access::Write(1), Access::Write(2)])])
+            .wait_committed_blocking();
+        runtime.prune_to(2);
+        while runtime.pruning().pruned_index() < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateVersion::<usize>::content_refs(store, &hash), 1);
+        assert_eq!(stored_content(store), 2);
+
+        runtime.schedule(vec![Tx(5, vec![Access::Write(3)])]).wait_committed_blocking();
+        runtime.prune_to(3);
+        while runtime.pruning().pruned_index() < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateVersion::<usize>::content_refs(store, &hash), 0);
+        assert_eq!(stored_content(store), 2);
+        assert!(Fsck::<usize>::new().check(store).is_consistent());
+
+        runtime.shutdown();
+    }
+
+    // Content survives a restart, and fsck deletes content that nothing references.
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    AssertWrittenState(1, vec![1, 4]).assert(&storage);
+    AssertWrittenState(2, vec![1, 4]).assert(&storage);
+    AssertWrittenState(3, vec![1, 5]).assert(&storage);
+
+    let orphan = [7; 32];
+    let mut write_batch = storage.write_batch();
+    write_batch.put(StateSpace::StateContent, &orphan, b"orphan");
+    storage.commit(write_batch);
+
+    let fsck = Fsck::<usize>::new();
+    let report = fsck.check(&storage);
+    assert_eq!(report.content, 3);
+    assert_eq!(report.issues, vec![FsckIssue::OrphanContent { hash: orphan }]);
+    assert_eq!(fsck.repair(&storage, &report), 1);
+    assert!(fsck.check(&storage).is_consistent());
+    assert_eq!(stored_content(&storage), 2);
+
+    // Writes of a batch that was not committed before a crash add no counted references, so
+    // recovering from it keeps the counts and deletes the content only it stored.
+    let shared_hash = StateVersion::content_hash(&storage, 2, &1usize).expect("content hash");
+    let mut write_batch = storage.write_batch();
+    for (resource_id, data) in [(3usize, vec![1usize, 4]), (1, vec![1, 4, 9])] {
+        let read_state =
+            Arc::new(StateVersion::from_latest_data(&storage, resource_id).expect("corrupted"));
+        let mut written_state = read_state.clone();
+        *written_state.data_mut() = data.iter().flat_map(|id| id.to_be_bytes()).collect();
+        written_state.write_data(&mut write_batch, &read_state, VersionEncoding::ContentAddressed);
+        written_state.write_history_ptr(&mut write_batch, 4);
+        read_state.write_rollback_ptr(&mut write_batch, 4);
+    }
+    storage.commit(write_batch);
+    assert_eq!(stored_content(&storage), 3);
+
+    let runtime = Scheduler::new(
+        ExecutionConfig::default()
+            .with_vm(TestVM)
+            .with_version_encoding(VersionEncoding::ContentAddressed),
+        StorageConfig::default().with_store(storage),
+    );
+    let store = runtime.storage_manager().store();
+    assert_eq!(StateVersion::<usize>::content_refs(store, &shared_hash), 2);
+    assert_eq!(stored_content(store), 2);
+    AssertWrittenState(3, vec![1, 5]).assert(store);
+    assert!(Fsck::<usize>::new().check(store).is_consistent());
+    runtime.shutdown();
+}
+
+/// Tests that corrupted version data is detected on read instead of being executed on.
+#[test]
+pub fn test_corrupted_state() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime
+            .schedule(vec![Tx(1, vec![Access::Write(1)]), Tx(2, vec![Access::Write(2)])])
+            .wait_committed_blocking();
+        runtime.shutdown();
+    }
+    {
+        // Flip a bit in the stored data of resource 1.
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let key = [1u64.to_be_bytes().to_vec(), 1usize.to_bytes()].concat();
+        let mut value = storage.get(StateSpace::StateVersion, &key).unwrap();
+        *value.last_mut().unwrap() ^= 1;
+        let mut write_batch = storage.write_batch();
+        write_batch.put(StateSpace::StateVersion, &key, &value);
+        storage.commit(write_batch);
+
+        let corruption = CorruptionError { resource_id: 1usize.to_bytes(), version: 1 };
+        assert_eq!(StateVersion::from_latest_data(&storage, 1usize), Err(corruption.clone()));
+
+        let report = Fsck::<usize>::new().check(&storage);
+        assert_eq!(
+            report.issues,
+            vec![FsckIssue::CorruptedVersion { resource_id: corruption.resource_id, version: 1 }]
+        );
+    }
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        // The transaction reading the corrupted data fails, so nothing is written.
+        runtime
+            .schedule(vec![Tx(3, vec![Access::Write(1)]), Tx(4, vec![Access::Write(2)])])
+            .wait_committed_blocking();
+        AssertVersionDeleted(1, 2).assert(runtime.storage_manager().store());
+        AssertWrittenState(2, vec![2, 4]).assert(runtime.storage_manager().store());
+        runtime.shutdown();
+    }
+}
+
+/// Tests that large values are stored as chunks that are shared until they are modified.
+#[test]
+pub fn test_chunked_state() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let data: Vec<u8> = (0..3 * CHUNK_SIZE + 10).map(|i| i as u8).collect();
+    let expected = |txs: &[usize]| {
+        let mut expected = data.clone();
+        txs.iter().for_each(|tx| expected.extend_from_slice(&tx.to_be_bytes()));
+        expected
+    };
+    let chunks = |store: &RocksDbStore| {
+        let mut chunks: Vec<_> = StateVersion::<usize>::iter_chunks(store)
+            .map(|(owner, _, index)| (owner, index))
+            .collect();
+        chunks.sort();
+        chunks
+    };
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut write_batch = storage.write_batch();
+        StateVersion::put(&mut write_batch, 1, &1usize, &data);
+        StatePtrLatest::put(&mut write_batch, &1usize, 1);
+        storage.commit(write_batch);
+
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        for id in 1..=3 {
+            runtime.schedule(vec![Tx(id, vec![Access::Write(1)])]).wait_committed_blocking();
+        }
+
+        // Every version only stores the last chunk, which the appends modified.
+        let store = runtime.storage_manager().store();
+        for version in 2..=4 {
+            let owners: Vec<_> = StateVersion::chunk_refs(store, version, &1usize)
+                .iter()
+                .map(|chunk_ref| chunk_ref.owner)
+                .collect();
+            assert_eq!(owners, vec![1, 1, 1, version]);
+        }
+        let txs: Vec<usize> = (1..=3).collect();
+        assert_eq!(StateVersion::get(store, 4, &1usize), Ok(Some(expected(&txs))));
+
+        runtime.rollback_to(2);
+        assert_eq!(chunks(runtime.storage_manager().store()).len(), 6);
+
+        // Pruning deletes the chunks that the replacing versions do not share.
+        runtime.prune_to(2);
+        while runtime.pruning().pruned_index() < 2 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let store = runtime.storage_manager().store();
+        assert_eq!(chunks(store), vec![(1, 0), (1, 1), (1, 2), (3, 3)]);
+        assert_eq!(
+            StateVersion::from_latest_data(store, 1usize).map(|state| state.data().clone()),
+            Ok(expected(&[1, 2]))
+        );
+        assert!(Fsck::<usize>::new().check(store).is_consistent());
+
+        // Committing a patched chunk neither loads nor verifies the others, and the state root
+        // still commits to the full data through the hashes in the manifest.
+        let mut write_batch = store.write_batch();
+        let first_chunk_key =
+            [1u64.to_be_bytes().to_vec(), 1usize.to_bytes(), 0u32.to_be_bytes().to_vec()].concat();
+        write_batch.put(StateSpace::StateVersionChunk, &first_chunk_key, b"corrupted");
+        store.commit(write_batch);
+        let batch = runtime.schedule(vec![Tx(4, vec![Access::PatchLastChunk(1)])]);
+        batch.wait_committed_blocking();
+
+        let store = runtime.storage_manager().store();
+        let version = StatePtrLatest::get(store, &1usize).expect("latest version");
+        let mut patched = expected(&[1, 2]);
+        *patched.last_mut().unwrap() ^= 0xff;
+        let proof = StateTree::prove(store, batch.index(), &1usize).expect("proof");
+        let key = key_hash(&1usize.to_bytes());
+        assert!(proof.verify(
+            &batch.state_root().unwrap(),
+            &key,
+            Some(&value_hash(version, &patched))
+        ));
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that proofs of large values verify against the data hash over their chunk manifest, also
+/// for a verifier that only holds the hashes of the chunks.
+#[test]
+pub fn test_state_proofs_of_chunked_values() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let mut data: Vec<u8> = (0..2 * CHUNK_SIZE + 10).map(|i| i as u8).collect();
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut write_batch = storage.write_batch();
+        StateVersion::put(&mut write_batch, 1, &1usize, &data);
+        StatePtrLatest::put(&mut write_batch, &1usize, 1);
+        storage.commit(write_batch);
+
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        let batch = runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        batch.wait_committed_blocking();
+        data.extend_from_slice(&1usize.to_be_bytes());
+
+        let store = runtime.storage_manager().store();
+        let root = batch.state_root().unwrap();
+        let key = key_hash(&1usize.to_bytes());
+        let proof = StateTree::prove(store, batch.index(), &1usize).expect("proof");
+        let proof = Proof::from_bytes(&proof.to_bytes()).expect("valid proof encoding");
+        assert!(proof.verify(&root, &key, Some(&value_hash(2, &data))));
+
+        // A verifier holding the chunk manifest computes the same leaf value without the data.
+        let chunk_hashes: Vec<_> =
+            StateVersion::chunk_refs(store, 2, &1usize).iter().map(|chunk| chunk.hash).collect();
+        assert_eq!(chunk_hashes.len(), 3);
+        let manifest_hash = chunked_data_hash(data.len(), chunk_hashes.iter().copied());
+        assert_eq!(manifest_hash, data_hash(&data));
+        assert!(proof.verify(&root, &key, Some(&versioned_value_hash(2, &manifest_hash))));
+
+        // Changing a single chunk or the length changes the commitment.
+        let mut tampered = chunk_hashes.clone();
+        tampered[1][0] ^= 1;
+        let tampered_hash = chunked_data_hash(data.len(), tampered);
+        assert!(!proof.verify(&root, &key, Some(&versioned_value_hash(2, &tampered_hash))));
+        let truncated_hash = chunked_data_hash(data.len() - 1, chunk_hashes);
+        assert!(!proof.verify(&root, &key, Some(&versioned_value_hash(2, &truncated_hash))));
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that secondary indexes follow commits and rollbacks and can be rebuilt.
+#[test]
+pub fn test_secondary_indexes() {
+    const LAST_WRITER: u16 = 1;
+    const WRITERS: u16 = 2;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let indexes = SecondaryIndexes::new()
+        .with_index(LAST_WRITER, |_: &usize, data: &[u8]| {
+            data.rchunks(8).take(1).map(<[u8]>::to_vec).collect()
+        })
+        .with_index(WRITERS, |_: &usize, data: &[u8]| data.chunks(8).map(<[u8]>::to_vec).collect());
+    let lookup = |store: &RocksDbStore, index_id: u16, tx: usize| -> Vec<usize> {
+        StateIndex::lookup(store, index_id, &tx.to_be_bytes()).collect()
+    };
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM).with_indexes(indexes.clone()),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(2)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]).wait_committed_blocking();
+
+        let store = runtime.storage_manager().store();
+        assert_eq!(lookup(store, LAST_WRITER, 1), Vec::<usize>::new());
+        assert_eq!(lookup(store, LAST_WRITER, 2), vec![2]);
+        assert_eq!(lookup(store, LAST_WRITER, 3), vec![1]);
+        assert_eq!(lookup(store, WRITERS, 1), vec![1, 2]);
+
+        // Rolling back restores the entries of the previous versions.
+        runtime.rollback_to(1);
+        let store = runtime.storage_manager().store();
+        assert_eq!(lookup(store, LAST_WRITER, 1), vec![1, 2]);
+        assert_eq!(lookup(store, LAST_WRITER, 2), Vec::<usize>::new());
+        assert_eq!(lookup(store, LAST_WRITER, 3), Vec::<usize>::new());
+        assert_eq!(lookup(store, WRITERS, 1), vec![1, 2]);
+        assert_eq!(StateIndex::iter(store, WRITERS).count(), 2);
+
+        runtime.shutdown();
+    }
+    {
+        // Rebuilding recreates the same entries from the latest state.
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut write_batch = storage.write_batch();
+        StateIndex::delete_index(&mut write_batch, LAST_WRITER);
+        storage.commit(write_batch);
+        assert_eq!(lookup(&storage, LAST_WRITER, 1), Vec::<usize>::new());
+
+        indexes.rebuild(&storage).expect("corrupted state");
+        assert_eq!(lookup(&storage, LAST_WRITER, 1), vec![1, 2]);
+        assert_eq!(StateIndex::iter(&storage, WRITERS).count(), 2);
+    }
+}
+
+/// Tests querying the resources modified by committed batches.
+#[test]
+pub fn test_changesets() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage),
+    );
+    runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+    runtime.schedule(vec![Tx(2, vec![Access::Read(1), Access::Write(2)])]);
+    runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]).wait_committed_blocking();
+
+    let store = runtime.storage_manager().store();
+    let changesets: Vec<Changeset<usize>> =
+        Changeset::iter(store, 1..=3, true).collect::<Result<_, _>>().expect("changesets");
+
+    // Reads are not changes, and created resources have no old version.
+    let ids = |changeset: &Changeset<usize>| -> Vec<usize> {
+        changeset.changes.iter().map(|change| change.resource_id).collect()
+    };
+    assert_eq!(changesets.iter().map(ids).collect::<Vec<_>>(), vec![vec![1, 2], vec![2], vec![1]]);
+    assert!(changesets[0].changes.iter().all(|c| c.old_version == 0 && c.old_data.is_none()));
+
+    // Every change starts from the version that the previous change of the resource wrote.
+    let change = &changesets[2].changes[0];
+    assert_eq!(change.old_version, changesets[0].changes[0].new_version);
+    assert_eq!(change.old_data, changesets[0].changes[0].new_data);
+    assert_eq!(change.new_data, Some([1usize.to_be_bytes(), 3usize.to_be_bytes()].concat()));
+    assert_eq!(
+        Changeset::<usize>::load(store, 2, false).expect("changeset").changes[0].new_data,
+        None
+    );
+    assert_eq!(Changeset::<usize>::load(store, 4, true), Err(ChangesetError::NotCommitted(4)));
+
+    runtime.prune_to(1);
+    while runtime.pruning().pruned_index() < 1 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let store = runtime.storage_manager().store();
+    assert_eq!(Changeset::<usize>::load(store, 1, true), Err(ChangesetError::Pruned(1)));
+    assert_eq!(Changeset::load(store, 2, true), Ok(changesets[1].clone()));
+
+    // Rolled back batches are no longer committed.
+    runtime.rollback_to(2);
+    let store = runtime.storage_manager().store();
+    assert_eq!(Changeset::<usize>::load(store, 3, true), Err(ChangesetError::NotCommitted(3)));
+
+    runtime.shutdown();
+}
+
+/// Tests subscribing to the changes of committed and reverted batches.
+#[test]
+pub fn test_change_feed() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage),
+    );
+    runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+    runtime.schedule(vec![Tx(2, vec![Access::Write(2)])]).wait_committed_blocking();
+
+    // One subscriber resumes from a committed batch, the other waits for a future one.
+    let mut resumed = runtime.subscribe(2).expect("subscribe");
+    let mut live = runtime.subscribe(4).expect("subscribe");
+
+    runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]);
+    runtime.schedule(vec![Tx(4, vec![Access::Write(3)])]).wait_committed_blocking();
+    runtime.rollback_to(2);
+    runtime.schedule(vec![Tx(5, vec![Access::Write(2)])]).wait_committed_blocking();
+
+    let events = |receiver: &mut UnboundedReceiver<ChangeEvent<usize>>| {
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        events
+    };
+    let summary = |events: &[ChangeEvent<usize>]| -> Vec<(bool, u64, Vec<usize>)> {
+        events
+            .iter()
+            .map(|event| match event {
+                ChangeEvent::Commit(changeset) => (true, changeset),
+                ChangeEvent::Retract(changeset) => (false, changeset),
+            })
+            .map(|(is_commit, changeset)| {
+                let ids = changeset.changes.iter().map(|change| change.resource_id).collect();
+                (is_commit, changeset.batch_index, ids)
+            })
+            .collect()
+    };
+
+    let resumed = events(&mut resumed);
+    assert_eq!(
+        summary(&resumed),
+        vec![
+            (true, 2, vec![2]),
+            (true, 3, vec![1]),
+            (true, 4, vec![3]),
+            (false, 4, vec![3]),
+            (false, 3, vec![1]),
+            (true, 3, vec![2]),
+        ]
+    );
+    assert_eq!(summary(&events(&mut live)), vec![(true, 4, vec![3]), (false, 4, vec![3])]);
+
+    // Commits carry the new data, retractions the versions they revert.
+    let (ChangeEvent::Commit(committed), ChangeEvent::Retract(retracted)) =
+        (&resumed[1], &resumed[4])
+    else {
+        panic!("unexpected events");
+    };
+    let change = &committed.changes[0];
+    assert_eq!(change.new_data, Some([1usize.to_be_bytes(), 3usize.to_be_bytes()].concat()));
+    assert_eq!(retracted.changes[0].old_version, change.old_version);
+    assert_eq!(retracted.changes[0].new_version, change.new_version);
+    assert_eq!(retracted.changes[0].new_data, None);
+
+    // Pruned batches can no longer be replayed.
+    runtime.prune_to(1);
+    while runtime.pruning().pruned_index() < 1 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(matches!(runtime.subscribe(1), Err(ChangesetError::Pruned(1))));
+
+    runtime.shutdown();
+}
+
+/// Tests deleting resources, reverting deletions and re-creating deleted resources.
+#[test]
+pub fn test_resource_deletion() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage),
+    );
+    let latest = |store: &RocksDbStore| {
+        StateVersion::<usize>::from_latest_data(store, 1).expect("corrupted state")
+    };
+
+    runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]).wait_committed_blocking();
+
+    // Deleting leaves a tombstone and removes the resource from the state tree.
+    let batch = runtime.schedule(vec![Tx(2, vec![Access::Delete(1), Access::Delete(2)])]);
+    batch.wait_committed_blocking();
+    assert_eq!(batch.state_root(), Some(EMPTY_HASH));
+    let store = runtime.storage_manager().store();
+    let deleted = latest(store);
+    assert!(deleted.is_deleted());
+    assert_eq!((deleted.version(), deleted.data().len()), (2, 0));
+    assert_eq!(StatePtrLatest::iter(store).collect::<Vec<_>>(), vec![(1usize.to_bytes(), 2, true)]);
+
+    // Deleting a resource that does not exist writes nothing.
+    let changeset = Changeset::<usize>::load(store, 2, true).expect("changeset");
+    assert_eq!(changeset.changes.len(), 1);
+    assert!(changeset.changes[0].deleted);
+    assert_eq!(changeset.changes[0].old_data, Some(1usize.to_be_bytes().to_vec()));
+    assert_eq!(changeset.changes[0].new_data, None);
+
+    // Writing re-creates the resource with fresh data, continuing its versions.
+    runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]).wait_committed_blocking();
+    let store = runtime.storage_manager().store();
+    let recreated = latest(store);
+    assert!(!recreated.is_deleted());
+    assert_eq!((recreated.version(), recreated.data()), (3, &3usize.to_be_bytes().to_vec()));
+
+    // Rolling back restores the tombstone, and then the version before the deletion.
+    runtime.rollback_to(2);
+    let store = runtime.storage_manager().store();
+    assert!(latest(store).is_deleted());
+    assert_eq!(StatePtrLatest::iter(store).collect::<Vec<_>>(), vec![(1usize.to_bytes(), 2, true)]);
+
+    runtime.rollback_to(1);
+    let store = runtime.storage_manager().store();
+    let restored = latest(store);
+    assert!(!restored.is_deleted());
+    assert_eq!((restored.version(), restored.data()), (1, &1usize.to_be_bytes().to_vec()));
+
+    let batch = runtime.schedule(vec![Tx(4, vec![Access::Delete(1)])]);
+    batch.wait_committed_blocking();
+    assert_eq!(batch.state_root(), Some(EMPTY_HASH));
+    assert!(Fsck::<usize>::new().check(runtime.storage_manager().store()).is_consistent());
+
+    runtime.shutdown();
+}
+
+/// Tests that writes which leave a resource unchanged create no new version.
+#[test]
+pub fn test_unchanged_writes() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage),
+    );
+
+    let batch = runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+    batch.wait_committed_blocking();
+    let root = batch.state_root();
+
+    // Touching existing and deleting missing resources neither bumps versions nor persists
+    // anything.
+    let batch =
+        runtime.schedule(vec![Tx(2, vec![Access::Touch(1)]), Tx(3, vec![Access::Delete(2)])]);
+    batch.wait_committed_blocking();
+    assert_eq!(batch.state_root(), root);
+    assert!(batch.state_diffs().iter().all(|diff| diff.is_read_only()));
+
+    let store = runtime.storage_manager().store();
+    AssertWrittenState(1, vec![1]).assert(store);
+    assert_eq!(StatePtrLatest::get(store, &2usize), None);
+    assert_eq!(Changeset::<usize>::load(store, 2, false).expect("changeset").changes, vec![]);
+
+    // Changes that later transactions of the same batch revert are not persisted either.
+    let batch =
+        runtime.schedule(vec![Tx(4, vec![Access::Write(3)]), Tx(5, vec![Access::Delete(3)])]);
+    batch.wait_committed_blocking();
+    assert_eq!(batch.state_root(), root);
+    assert!(batch.state_diffs().iter().all(|diff| diff.is_read_only()));
+    assert_eq!(StatePtrLatest::get(runtime.storage_manager().store(), &3usize), None);
+
+    // Later writes continue from the unchanged versions.
+    runtime
+        .schedule(vec![
+            Tx(6, vec![Access::Touch(1)]),
+            Tx(7, vec![Access::Write(1)]),
+            Tx(8, vec![Access::Write(3)]),
+        ])
+        .wait_committed_blocking();
+    let store = runtime.storage_manager().store();
+    AssertWrittenState(1, vec![1, 7]).assert(store);
+    AssertWrittenState(3, vec![8]).assert(store);
+    assert!(Fsck::<usize>::new().check(store).is_consistent());
+
+    runtime.shutdown();
+}
+
+/// Tests reading and writing typed values of resources.
+#[test]
+pub fn test_typed_state() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage),
+    );
+
+    runtime.schedule(vec![Tx(1, vec![Access::Increment(1)])]);
+    runtime
+        .schedule(vec![
+            Tx(2, vec![Access::Increment(1), Access::Write(2)]),
+            Tx(3, vec![Access::Increment(1), Access::Write(2)]),
+        ])
+        .wait_committed_blocking();
+
+    let store = runtime.storage_manager().store();
+    assert_eq!(TypedState::<Counter>::latest(store, &1).expect("counter"), Some(3));
+    assert_eq!(TypedState::<Counter>::historical(store, &1, 1).expect("counter"), Some(1));
+    assert_eq!(TypedState::<Counter>::get(store, 1, &1).expect("counter"), Some(1));
+    assert_eq!(TypedState::<Counter>::latest(store, &3).expect("counter"), None);
+    assert_eq!(TypedState::<WriterLog>::latest(store, &2).expect("writer log"), Some(vec![2, 3]));
+
+    // Values of another type fail to decode, which fails the transaction incrementing them.
+    assert!(matches!(TypedState::<Counter>::latest(store, &2), Err(TypedStateError::Decode(_))));
+    runtime.schedule(vec![Tx(4, vec![Access::Increment(2)])]).wait_committed_blocking();
+    AssertWrittenState(2, vec![2, 3]).assert(runtime.storage_manager().store());
+
+    // Values written outside of the scheduler are read back by later transactions.
+    let store = runtime.storage_manager().store();
+    let mut write_batch = store.write_batch();
+    TypedState::<Counter>::put(&mut write_batch, 1, &3, &41);
+    StatePtrLatest::put(&mut write_batch, &3usize, 1);
+    store.commit(write_batch);
+    runtime.schedule(vec![Tx(5, vec![Access::Increment(3)])]).wait_committed_blocking();
+    let store = runtime.storage_manager().store();
+    assert_eq!(TypedState::<Counter>::latest(store, &3).expect("counter"), Some(42));
+
+    // History of pruned batches is no longer available.
+    runtime.prune_to(2);
+    while runtime.pruning().pruned_index() < 2 {
+        thread::sleep(Duration::from_millis(1));
+    }
+    let store = runtime.storage_manager().store();
+    assert!(matches!(
+        TypedState::<Counter>::historical(store, &1, 1),
+        Err(TypedStateError::Pruned(1))
+    ));
+
+    runtime.shutdown();
+}
+
+/// Tests the headers recorded for committed batches.
+#[test]
+pub fn test_batch_headers() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let header1 = {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        let batch1 = runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        let batch2 = runtime.schedule_with_context(
+            vec![Tx(2, vec![Access::Write(1), Access::Read(2)]), Tx(3, vec![Access::Write(3)])],
+            b"l1-block-2".to_vec(),
+        );
+        batch2.wait_committed_blocking();
+        assert_eq!(batch2.context(), b"l1-block-2");
+
+        let store = runtime.storage_manager().store();
+        let header1 = BatchHeader {
+            index: 1,
+            tx_count: 1,
+            state_diff_count: 1,
+            commitment: batch1.state_root(),
+            context: Vec::new(),
+        };
+        let header2 = BatchHeader {
+            index: 2,
+            tx_count: 2,
+            state_diff_count: 3,
+            commitment: batch2.state_root(),
+            context: b"l1-block-2".to_vec(),
+        };
+        assert_eq!(StateMetadata::batch_header(store, 1), Some(header1.clone()));
+        assert_eq!(StateMetadata::batch_header(store, 2), Some(header2.clone()));
+        assert_eq!(StateMetadata::batch_header(store, 3), None);
+        assert_eq!(
+            StateMetadata::batch_headers(store, 0..=3).collect::<Vec<_>>(),
+            vec![header1.clone(), header2.clone()]
+        );
+        assert_eq!(
+            StateMetadata::batch_headers(store, 2..=u64::MAX).collect::<Vec<_>>(),
+            vec![header2]
+        );
+        assert_eq!(StateMetadata::batch_headers(store, 3..=u64::MAX).count(), 0);
+
+        // Pruning keeps the headers, rollback removes them.
+        runtime.prune_to(1);
+        while runtime.pruning().pruned_index() < 1 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        runtime.rollback_to(1);
+        let store = runtime.storage_manager().store();
+        assert_eq!(
+            StateMetadata::batch_headers(store, 1..=2).collect::<Vec<_>>(),
+            vec![header1.clone()]
+        );
+
+        drop((batch1, batch2));
+        runtime.shutdown();
+        header1
+    };
+    {
+        // Headers survive a restart, and batches scheduled after it are recorded as well.
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        let batch = runtime.schedule(vec![Tx(4, vec![Access::Write(2)])]);
+        batch.wait_committed_blocking();
+
+        let store = runtime.storage_manager().store();
+        assert_eq!(StateMetadata::batch_header(store, 1), Some(header1));
+        let header2 = StateMetadata::batch_header(store, 2).expect("header of batch 2");
+        assert_eq!((header2.tx_count, header2.commitment), (1, batch.state_root()));
+
+        drop(batch);
+        runtime.shutdown();
+    }
+}
+
+/// Tests exporting a snapshot and continuing from it on a fresh store.
+#[test]
+pub fn test_snapshot() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let corrupted_temp_dir = TempDir::new().expect("failed to create temp dir");
+    let imported_temp_dir = TempDir::new().expect("failed to create temp dir");
+
+    let mut snapshot = Vec::new();
+    let expected_root = {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1), Access::Write(2)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1), Access::Write(3)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(4)])]).wait_committed_blocking();
+
+        // Small chunks make sure the snapshot spans several of them.
+        let header =
+            snapshot::export::<usize, _, _>(runtime.storage_manager().store(), &mut snapshot, 16)
+                .expect("export failed");
+        assert_eq!(header.batch_index, 3);
+
+        let batch = runtime.schedule(vec![Tx(4, vec![Access::Write(2)])]);
+        batch.wait_committed_blocking();
+        let expected_root = batch.state_root();
+        drop(batch);
+
+        // A store whose last committed batch has no state root can not be exported.
+        let store = runtime.storage_manager().store();
+        let mut write_batch = store.write_batch();
+        write_batch.delete(StateSpace::StateTreeRoot, &4u64.to_be_bytes());
+        store.commit(write_batch);
+        assert!(matches!(
+            snapshot::export::<usize, _, _>(store, &mut Vec::new(), 16),
+            Err(SnapshotError::MissingStateRoot(4))
+        ));
+
+        runtime.shutdown();
+        expected_root
+    };
+
+    // Corrupted snapshots are rejected.
+    let storage: RocksDbStore = RocksDbStore::open(corrupted_temp_dir.path());
+    let mut corrupted = snapshot.clone();
+    corrupted[100] ^= 1;
+    assert!(matches!(
+        snapshot::import::<usize, _, _>(&storage, corrupted.as_slice(), VersionEncoding::Full),
+        Err(SnapshotError::ChecksumMismatch(Some(_)))
+    ));
+    drop(storage);
+
+    {
+        // The imported data is stored with the encoding the scheduler is configured with.
+        let encoding = VersionEncoding::ContentAddressed;
+        let storage: RocksDbStore = RocksDbStore::open(imported_temp_dir.path());
+        let header = snapshot::import::<usize, _, _>(&storage, snapshot.as_slice(), encoding)
+            .expect("import failed");
+        assert_eq!(header.batch_index, 3);
+        assert!(matches!(
+            snapshot::import::<usize, _, _>(&storage, snapshot.as_slice(), encoding),
+            Err(SnapshotError::StoreNotEmpty)
+        ));
+        let hash = StateVersion::content_hash(&storage, 2, &1usize).expect("content hash");
+        assert_eq!(StateVersion::<usize>::content_refs(&storage, &hash), 1);
+        assert!(Fsck::<usize>::new().check(&storage).is_consistent());
+
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM).with_version_encoding(encoding),
+            StorageConfig::default().with_store(storage),
+        );
+        assert_eq!(runtime.context().last_batch_index(), 3);
+        for assertion in [
+            AssertWrittenState(1, vec![1, 2]),
+            AssertWrittenState(2, vec![1]),
+            AssertWrittenState(3, vec![2]),
+            AssertWrittenState(4, vec![3]),
+        ] {
+            assertion.assert(runtime.storage_manager().store());
+        }
+        AssertHistoricalState(2, 3, vec![1]).assert(runtime.storage_manager().store());
+
+        // The imported node continues with the same state roots as the original one.
+        let batch = runtime.schedule(vec![Tx(4, vec![Access::Write(2)])]);
+        assert_eq!(batch.index(), 4);
+        batch.wait_committed_blocking();
+        assert_eq!(batch.state_root(), expected_root);
+        AssertWrittenState(2, vec![1, 4]).assert(runtime.storage_manager().store());
+        drop(batch);
+        runtime.shutdown();
+    }
+}
+
+/// Tests syncing state from a peer, including verification failures and resumption.
+#[test]
+pub fn test_state_sync() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let synced_temp_dir = TempDir::new().expect("failed to create temp dir");
+
+    let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let mut runtime = Scheduler::new(
+        ExecutionConfig::default().with_vm(TestVM),
+        StorageConfig::default().with_store(storage.clone()),
+    );
+    runtime.schedule((1..=8).map(|id| Tx(id, vec![Access::Write(id)])).collect());
+    let batch = runtime.schedule(vec![Tx(9, vec![Access::Write(1), Access::Write(2)])]);
+    batch.wait_committed_blocking();
+    let target = SyncTarget { batch_index: batch.index(), state_root: batch.state_root().unwrap() };
+    drop(batch);
+
+    // The source keeps executing while it serves the synced batch.
+    let batch = runtime.schedule(vec![Tx(10, vec![Access::Write(1)])]);
+    batch.wait_committed_blocking();
+    let expected_root = batch.state_root();
+    drop(batch);
+
+    let (transport, server) =
+        ChannelTransport::serve(SyncServer::<_, usize>::new(storage).with_max_entries(2));
+    let synced_storage: RocksDbStore = RocksDbStore::open(synced_temp_dir.path());
+
+    // Tampered chunks are rejected without losing progress.
+    let encoding = VersionEncoding::ContentAddressed;
+    let mut sync = StateSync::<_, usize>::new(synced_storage.clone(), target, encoding).unwrap();
+    assert_eq!(sync.run(&TamperingTransport(&transport)), Err(SyncError::InvalidChunk));
+
+    // An interrupted sync resumes from the progress persisted in the store.
+    assert_eq!(sync.run(&FlakyTransport(&transport, Cell::new(3))), Err(SyncError::Disconnected));
+    assert!(!sync.is_done());
+    drop(sync);
+    let mut sync = StateSync::<_, usize>::new(synced_storage.clone(), target, encoding).unwrap();
+    sync.run(&transport).unwrap();
+    assert!(sync.is_done());
+
+    drop(transport);
+    server.join().unwrap();
+
+    // The synced data is stored with the encoding the scheduler is configured with.
+    let hash = StateVersion::content_hash(&synced_storage, 1, &8usize).expect("content hash");
+    assert_eq!(StateVersion::<usize>::content_refs(&synced_storage, &hash), 1);
+    assert!(Fsck::<usize>::new().check(&synced_storage).is_consistent());
+
+    let mut synced_runtime = sync.into_scheduler(
+        ExecutionConfig::default().with_vm(TestVM).with_version_encoding(encoding),
+        StorageConfig::default(),
+    );
+    assert_eq!(synced_runtime.context().last_batch_index(), 2);
+    for assertion in [
+        AssertWrittenState(1, vec![1, 9]),
+        AssertWrittenState(2, vec![2, 9]),
+        AssertWrittenState(8, vec![8]),
+    ] {
+        assertion.assert(synced_runtime.storage_manager().store());
+    }
+
+    let batch = synced_runtime.schedule(vec![Tx(10, vec![Access::Write(1)])]);
+    batch.wait_committed_blocking();
+    assert_eq!(batch.state_root(), expected_root);
+    drop(batch);
+
+    synced_runtime.shutdown();
+    runtime.shutdown();
+}
+
+/// Tests that batches finalized before a restart stay final after it, even if they were not pruned
+/// yet and no batch was committed after finalizing them.
+#[test]
+pub fn test_finality_after_restart() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]).wait_committed_blocking();
+        runtime.prune_to(2);
+        runtime.shutdown();
+    }
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+        assert_eq!(runtime.pruning().finalized_index(), 2);
+
+        // Batches after the finalized one can still be rolled back.
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]).wait_committed_blocking();
+        runtime.rollback_to(2);
+        AssertWrittenState(1, vec![1, 2]).assert(runtime.storage_manager().store());
+        runtime.shutdown();
+    }
+}
+
+/// Tests execution and rollback on top of an encrypted store with hashed keys.
+#[test]
+pub fn test_encrypted_store() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let storage = EncryptedStore::new(
+            inner,
+            EncryptionConfig::new(Keyring::new(0, [7; 32])).with_key_hashing([9; 32]),
+        );
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage),
+        );
+
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)])]);
+        runtime.schedule(vec![Tx(2, vec![Access::Write(1)]), Tx(3, vec![Access::Write(2)])]);
+        let batch3 = runtime.schedule(vec![Tx(4, vec![Access::Write(1), Access::Read(2)])]);
+        batch3.wait_committed_blocking();
+
+        AssertWrittenState(1, vec![1, 2, 4]).assert(runtime.storage_manager().store());
+        AssertWrittenState(2, vec![3]).assert(runtime.storage_manager().store());
+
+        runtime.rollback_to(1);
+
+        AssertBatchRolledBack(2).assert(runtime.storage_manager().store());
+        AssertBatchRolledBack(3).assert(runtime.storage_manager().store());
+        AssertWrittenState(1, vec![1]).assert(runtime.storage_manager().store());
+        AssertResourceDeleted(2).assert(runtime.storage_manager().store());
+
+        runtime.shutdown();
+    }
+}
+
+/// Tests that byte-size limits and adaptive mode produce correct state and expose the chosen
+/// limits.
+#[test]
+pub fn test_write_batch_limits() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    {
+        let storage: RocksDbStore = RocksDbStore::open(temp_dir.path());
+        let mut runtime = Scheduler::new(
+            ExecutionConfig::default().with_vm(TestVM),
+            StorageConfig::default().with_store(storage).with_write_config(
+                WriteConfig::default()
+                    .with_max_batch_bytes(1)
+                    .with_target_commit_latency(Duration::from_nanos(1)),
+            ),
+        );
+
+        // A byte limit of 1 forces a commit after every single write command.
+        runtime.schedule(vec![Tx(1, vec![Access::Write(1)]), Tx(2, vec![Access::Write(2)])]);
+        let batch2 = runtime.schedule(vec![Tx(3, vec![Access::Write(1)])]);
+        batch2.wait_committed_blocking();
+
+        AssertWrittenState(1, vec![1, 3]).assert(runtime.storage_manager().store());
+        AssertWrittenState(2, vec![2]).assert(runtime.storage_manager().store());
+
+        // No commit can meet a 1ns target, so the adaptive writer must have shrunk its limits.
+        let limits = runtime.storage_manager().write_limits();
+        assert!(limits.last_commit_latency() > Duration::ZERO);
+        assert!(limits.batch_size() < WriteConfig::default().max_batch_size());
+        assert!(limits.batch_duration() < WriteConfig::default().max_batch_duration());
+        assert!(limits.batch_bytes() <= 1);
+
+        runtime.shutdown();
+    }
+}
+
+/// Forwards requests but fails once its budget of requests is used up.
+struct FlakyTransport<'a, T>(&'a T, Cell<usize>);
+
+impl<T: SyncTransport> SyncTransport for FlakyTransport<'_, T> {
+    fn request(&self, request: ChunkRequest) -> SyncResult<ChunkResponse> {
+        match self.1.get() {
+            0 => Err(SyncError::Disconnected),
+            budget => {
+                self.1.set(budget - 1);
+                self.0.request(request)
+            }
+        }
+    }
+}
+
+/// Forwards requests but alters the data of returned entries.
+struct TamperingTransport<'a, T>(&'a T);
+
+impl<T: SyncTransport> SyncTransport for TamperingTransport<'_, T> {
+    fn request(&self, request: ChunkRequest) -> SyncResult<ChunkResponse> {
+        self.0.request(request).map(|response| match response {
+            ChunkResponse::Entries(mut entries) => {
+                entries.iter_mut().for_each(|entry| entry.data.push(0));
+                ChunkResponse::Entries(entries)
+            }
+            response => response,
+        })
+    }
+}
+
+mod test_framework {
+    use std::{io, sync::Arc};
+
+    use vprogs_core_types::{AccessMetadata, AccessType, Transaction};
+    use vprogs_scheduling_scheduler::{AccessHandle, RuntimeBatch, VmInterface};
+    use vprogs_state_space::StateSpace;
+    use vprogs_state_typed::{BorshCodec, Codec, TypedResource, TypedState};
+    use vprogs_state_version::{StateVersion, VersionEncoding};
+    use vprogs_storage_types::{ReadStore, Store};
+
+    #[derive(Clone)]
+    pub struct TestVM;
+
+    impl VmInterface for TestVM {
+        type Transaction = Tx;
+        type TransactionEffects = ();
+        type ResourceId = usize;
+        type AccessMetadata = Access;
+        type Error = ();
+
+        fn process_transaction<S: Store<StateSpace = StateSpace>>(
+            &self,
+            tx: &Self::Transaction,
+            resources: &mut [AccessHandle<S, Self>],
+        ) -> Result<(), Self::Error> {
+            for resource in resources {
+                if resource.corruption().is_some() {
+                    return Err(());
+                }
+                match resource.access_metadata().clone() {
+                    Access::Read(_) => {}
+                    Access::Write(_) => resource.data_mut().extend_from_slice(&tx.0.to_be_bytes()),
+                    Access::Delete(_) => resource.delete(),
+                    Access::Touch(_) => {
+                        resource.data_mut();
+                    }
+                    Access::Increment(_) => {
+                        let counter =
+                            resource.value::<Counter>().map_err(drop)?.unwrap_or_default();
+                        resource.set_value::<Counter>(&(counter + 1));
+                    }
+                    Access::PatchLastChunk(_) => {
+                        let index = resource.chunk_count() - 1;
+                        let chunk = resource.chunk_mut(index).map_err(drop)?;
+                        *chunk.last_mut().unwrap() ^= 0xff;
+                    }
+                }
+            }
+            Ok::<(), ()>(())
+        }
+
+        fn notarize_batch<S: Store<StateSpace = StateSpace>>(&self, batch: &RuntimeBatch<S, Self>) {
+            eprintln!(
+                ">> Processed batch with {} transactions and {} state changes",
+                batch.txs().len(),
+                batch.state_diffs().len()
+            );
+        }
+    }
+
+    pub struct Tx(pub usize, pub Vec<Access>);
+
+    impl Transaction<usize, Access> for Tx {
+        fn accessed_resources(&self) -> &[<TestVM as VmInterface>::AccessMetadata] {
+            &self.1
+        }
+    }
+
+    #[derive(Clone)]
+    pub enum Access {
+        Read(usize),
+        Write(usize),
+        Delete(usize),
+        /// A write that borrows the data mutably but leaves it unchanged.
+        Touch(usize),
+        /// A write that increments a [`Counter`] through the typed accessors.
+        Increment(usize),
+        /// A write that flips the last byte of a large value, loading only its last chunk.
+        PatchLastChunk(usize),
+    }
+
+    impl AccessMetadata<usize> for Access {
+        fn id(&self) -> usize {
+            match self {
+                Access::Read(id) => *id,
+                Access::Write(id) => *id,
+                Access::Delete(id) => *id,
+                Access::Touch(id) => *id,
+                Access::Increment(id) => *id,
+                Access::PatchLastChunk(id) => *id,
+            }
+        }
+
+        fn access_type(&self) -> AccessType {
+            match self {
+                Access::Read(_) => AccessType::Read,
+                Access::Write(_)
+                | Access::Delete(_)
+                | Access::Touch(_)
+                | Access::Increment(_)
+                | Access::PatchLastChunk(_) => AccessType::Write,
+            }
+        }
+    }
+
+    /// The IDs of the transactions that wrote to a resource, as appended by [`Access::Write`].
+    pub struct WriterLog;
+
+    impl TypedResource for WriterLog {
+        type ResourceId = usize;
+        type Value = Vec<usize>;
+        type Codec = WriterLogCodec;
+    }
+
+    pub struct WriterLogCodec;
+
+    impl Codec<Vec<usize>> for WriterLogCodec {
+        fn encode(value: &Vec<usize>) -> Vec<u8> {
+            value.iter().flat_map(|id| id.to_be_bytes()).collect()
+        }
+
+        fn decode(bytes: &[u8]) -> io::Result<Vec<usize>> {
+            let ids = bytes.chunks_exact(size_of::<usize>());
+            if !ids.remainder().is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated writer log"));
+            }
+            Ok(ids.map(|id| usize::from_be_bytes(id.try_into().unwrap())).collect())
+        }
+    }
+
+    /// A counter maintained by [`Access::Increment`].
+    pub struct Counter;
+
+    impl TypedResource for Counter {
+        type ResourceId = usize;
+        type Value = u64;
+        type Codec = BorshCodec;
+    }
+
+    pub struct AssertWrittenState(pub usize, pub Vec<usize>);
+
+    impl AssertWrittenState {
+        pub fn assert<S: ReadStore<StateSpace = StateSpace>>(&self, store: &S) {
+            let versioned_state =
+                StateVersion::<usize>::from_latest_data(store, self.0).expect("corrupted state");
+            assert_eq!(versioned_state.version(), self.1.len() as u64);
+            let writer_log =
+                TypedState::<WriterLog>::value(&versioned_state).expect("invalid writer log");
+            assert_eq!(writer_log.unwrap_or_default(), self.1);
+        }
+    }
+
+    pub struct AssertBatchRolledBack(pub u64);
+
+    impl AssertBatchRolledBack {
+        pub fn assert<S: Store<StateSpace = StateSpace>>(&self, store: &S) {
+            assert!(
+                store
+                    .prefix_iter(StateSpace::StatePtrRollback, &self.0.to_be_bytes())
+                    .next()
+                    .is_none(),
+                "Batch {} should have no rollback pointers left",
+                self.0
+            );
+        }
+    }
+
+    /// Persists a write of a transaction to a resource as part of a batch, without committing
+    /// the batch, as if the node crashed before the batch was committed.
+    pub struct PersistUncommittedWrite(pub u64, pub usize, pub usize);
+
+    impl PersistUncommittedWrite {
+        pub fn apply<S: Store<StateSpace = StateSpace>>(&self, store: &S) {
+            let read_state = Arc::new(
+                StateVersion::<usize>::from_latest_data(store, self.1).expect("corrupted state"),
+            );
+            let mut written_state = read_state.clone();
+            written_state.data_mut().extend_from_slice(&WriterLogCodec::encode(&vec![self.2]));
+
+            let mut write_batch = store.write_batch();
+            written_state.write_data(&mut write_batch, &read_state, VersionEncoding::Full);
+            written_state.write_history_ptr(&mut write_batch, self.0);
+            read_state.write_rollback_ptr(&mut write_batch, self.0);
+            store.commit(write_batch);
+        }
+    }
+
+    pub struct AssertHistoricalState(pub usize, pub u64, pub Vec<usize>);
+
+    impl AssertHistoricalState {
+        pub fn assert<S: ReadStore<StateSpace = StateSpace>>(&self, store: &S) {
+            let Ok(Some(versioned_state)) =
+                StateVersion::from_historical_data(store, self.0, self.1)
+            else {
+                panic!("State of resource {} at batch {} should be available", self.0, self.1);
+            };
+            assert_eq!(versioned_state.version(), self.2.len() as u64);
+            let writer_log =
+                TypedState::<WriterLog>::value(&versioned_state).expect("invalid writer log");
+            assert_eq!(writer_log.unwrap_or_default(), self.2);
+        }
+    }
+
+    pub struct AssertVersionDeleted(pub usize, pub u64);
+
+    impl AssertVersionDeleted {
+        pub fn assert<S: ReadStore<StateSpace = StateSpace>>(&self, store: &S) {
+            assert!(
+                matches!(StateVersion::get(store, self.1, &self.0), Ok(None)),
+                "Version {} of resource {} should have been deleted",
+                self.1,
+                self.0
+            );
         }
     }
 
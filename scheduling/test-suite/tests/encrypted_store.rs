@@ -0,0 +1,252 @@
+use std::panic;
+
+use tempfile::TempDir;
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::{BatchHeader, StateMetadata};
+use vprogs_state_ptr_history::StatePtrHistory;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_encrypted_store::{EncryptedStore, EncryptionConfig, EncryptionError, Keyring};
+use vprogs_storage_rocksdb_store::RocksDbStore;
+use vprogs_storage_types::{PrefixIterator, Store, WriteBatch};
+
+const KEY_1: [u8; 32] = [1; 32];
+const KEY_2: [u8; 32] = [2; 32];
+const KEY_HASHING_KEY: [u8; 32] = [3; 32];
+
+fn versioned_key(version: u64, resource_id: u64) -> Vec<u8> {
+    [version.to_be_bytes(), resource_id.to_be_bytes()].concat()
+}
+
+/// Tests that values and hashed keys never reach the disk in plaintext, while lookups, prefix
+/// iteration and prefix deletion keep working on the plaintext key prefixes.
+#[test]
+pub fn test_encrypted_store_with_key_hashing() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let store = EncryptedStore::new(
+        inner.clone(),
+        EncryptionConfig::new(Keyring::new(1, KEY_1)).with_key_hashing(KEY_HASHING_KEY),
+    );
+
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StateVersion, &versioned_key(1, 7), b"secret-1");
+    write_batch.put(StateSpace::StateVersion, &versioned_key(1, 8), b"secret-2");
+    write_batch.put(StateSpace::StateVersion, &versioned_key(2, 7), b"secret-3");
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-7", &2u64.to_be_bytes());
+    write_batch.put(StateSpace::StatePtrRollback, &versioned_key(5, 7), &1u64.to_be_bytes());
+    store.commit(write_batch);
+
+    // Reads through the wrapper see the original keys and values.
+    assert_eq!(
+        store.get(StateSpace::StateVersion, &versioned_key(1, 8)),
+        Some(b"secret-2".to_vec())
+    );
+    assert_eq!(
+        store.get(StateSpace::StatePtrLatest, b"resource-7"),
+        Some(2u64.to_be_bytes().to_vec())
+    );
+    assert_eq!(
+        store.prefix_iter(StateSpace::StateVersion, &1u64.to_be_bytes()).collect::<Vec<_>>(),
+        vec![
+            (versioned_key(1, 7), b"secret-1".to_vec()),
+            (versioned_key(1, 8), b"secret-2".to_vec()),
+        ]
+    );
+    assert_eq!(store.prefix_iter(StateSpace::StateVersion, &versioned_key(2, 7)).count(), 1);
+
+    // The inner store only sees hashed keys behind the plaintext prefix, and no plaintext values.
+    assert_eq!(inner.get(StateSpace::StatePtrLatest, b"resource-7"), None);
+    assert_eq!(inner.get(StateSpace::StateVersion, &versioned_key(1, 7)), None);
+    let raw = inner.prefix_iter(StateSpace::StateVersion, &1u64.to_be_bytes()).collect::<Vec<_>>();
+    assert_eq!(raw.len(), 2);
+    for (key, value) in raw {
+        assert_eq!(&key[..8], &1u64.to_be_bytes());
+        assert!(!value.windows(6).any(|window| window == b"secret"));
+    }
+
+    // Batch-wise deletion of rollback pointers still works on the plaintext batch index.
+    let mut write_batch = store.write_batch();
+    write_batch.delete_prefix(StateSpace::StatePtrRollback, &5u64.to_be_bytes());
+    store.commit(write_batch);
+    assert_eq!(store.prefix_iter(StateSpace::StatePtrRollback, &5u64.to_be_bytes()).count(), 0);
+}
+
+/// Tests that iteration yields keys in lexicographic order although hashed keys are not ordered on
+/// disk.
+#[test]
+pub fn test_encrypted_store_iteration_order_with_key_hashing() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let store = EncryptedStore::new(
+        inner,
+        EncryptionConfig::new(Keyring::new(1, KEY_1)).with_key_hashing(KEY_HASHING_KEY),
+    );
+
+    let mut write_batch = store.write_batch();
+    for resource_id in 0..32 {
+        write_batch.put(StateSpace::StatePtrRollback, &versioned_key(5, resource_id), &[]);
+        write_batch.put(StateSpace::StatePtrRollback, &versioned_key(6, resource_id), &[]);
+        write_batch.put(StateSpace::StatePtrLatest, &resource_id.to_be_bytes(), &[]);
+        StatePtrHistory::put(&mut write_batch, &resource_id, 1, 1);
+        StatePtrHistory::put(&mut write_batch, &resource_id, 2, 2);
+    }
+    store.commit(write_batch);
+
+    let keys = |entries: PrefixIterator<'_>| entries.map(|(key, _)| key).collect::<Vec<_>>();
+    assert_eq!(
+        keys(store.prefix_iter(StateSpace::StatePtrRollback, &6u64.to_be_bytes())),
+        (0..32).map(|resource_id| versioned_key(6, resource_id)).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        keys(store.iter_from(StateSpace::StatePtrRollback, &versioned_key(5, 30))),
+        [versioned_key(5, 30), versioned_key(5, 31)]
+            .into_iter()
+            .chain((0..32).map(|resource_id| versioned_key(6, resource_id)))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        keys(store.iter_from(StateSpace::StatePtrLatest, &[])),
+        (0..32u64).map(|resource_id| resource_id.to_be_bytes().to_vec()).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        StatePtrHistory::iter(&store)
+            .map(|(resource_id, batch_index, _)| (resource_id, batch_index))
+            .collect::<Vec<_>>(),
+        (0..32u64)
+            .flat_map(|resource_id| [2, 1].map(|batch_index| (resource_id.to_bytes(), batch_index)))
+            .collect::<Vec<_>>()
+    );
+}
+
+/// Tests that historical lookups find the last write at or before a batch with key hashing.
+#[test]
+pub fn test_encrypted_store_history_with_key_hashing() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let store = EncryptedStore::new(
+        inner,
+        EncryptionConfig::new(Keyring::new(1, KEY_1)).with_key_hashing(KEY_HASHING_KEY),
+    );
+
+    let mut write_batch = store.write_batch();
+    for batch_index in [1, 3, 4, 6, 9] {
+        StatePtrHistory::put(&mut write_batch, &7u64, batch_index, batch_index * 10);
+        StatePtrHistory::put(&mut write_batch, &8u64, batch_index + 1, batch_index * 100);
+    }
+    store.commit(write_batch);
+
+    assert_eq!(StatePtrHistory::find_at(&store, &7u64, 0), None);
+    assert_eq!(StatePtrHistory::find_at(&store, &7u64, 2), Some((1, 10)));
+    assert_eq!(StatePtrHistory::find_at(&store, &7u64, 5), Some((4, 40)));
+    assert_eq!(StatePtrHistory::find_at(&store, &7u64, 8), Some((6, 60)));
+    assert_eq!(StatePtrHistory::find_at(&store, &7u64, u64::MAX), Some((9, 90)));
+    assert_eq!(StatePtrHistory::find_at(&store, &8u64, 6), Some((5, 400)));
+}
+
+/// Tests that batch headers are iterated in batch order with key hashing.
+#[test]
+pub fn test_encrypted_store_batch_headers_with_key_hashing() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let store = EncryptedStore::new(
+        inner,
+        EncryptionConfig::new(Keyring::new(1, KEY_1)).with_key_hashing(KEY_HASHING_KEY),
+    );
+
+    let headers = (1..=20)
+        .map(|index| BatchHeader {
+            index,
+            tx_count: index,
+            state_diff_count: index,
+            commitment: None,
+            context: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    let mut write_batch = store.write_batch();
+    for header in &headers {
+        StateMetadata::set_batch_header(&mut write_batch, header);
+    }
+    StateMetadata::set_last_committed_index(&mut write_batch, 20);
+    store.commit(write_batch);
+
+    assert_eq!(
+        StateMetadata::batch_headers(&store, 5..=u64::MAX).collect::<Vec<_>>(),
+        headers[4..]
+    );
+    assert_eq!(StateMetadata::batch_headers(&store, 3..=7).collect::<Vec<_>>(), headers[2..7]);
+}
+
+/// Tests that values written before a key rotation stay readable through retired keys and can be
+/// re-encrypted with the new active key.
+#[test]
+pub fn test_encrypted_store_key_rotation() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+
+    let store = EncryptedStore::new(inner.clone(), EncryptionConfig::new(Keyring::new(1, KEY_1)));
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-1", b"value-1");
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-2", b"value-2");
+    store.commit(write_batch);
+
+    // Rotate to a new key, keeping the old one for decryption.
+    let store = EncryptedStore::new(
+        inner.clone(),
+        EncryptionConfig::new(Keyring::new(2, KEY_2).with_retired_key(1, KEY_1)),
+    );
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-2", b"value-2b");
+    store.commit(write_batch);
+
+    assert_eq!(store.get(StateSpace::StatePtrLatest, b"resource-1"), Some(b"value-1".to_vec()));
+    assert_eq!(store.key_id(StateSpace::StatePtrLatest, b"resource-1"), Ok(Some(1)));
+    assert_eq!(store.key_id(StateSpace::StatePtrLatest, b"resource-2"), Ok(Some(2)));
+
+    // Only the value that still uses the retired key is rewritten.
+    assert_eq!(store.reencrypt(StateSpace::StatePtrLatest, b""), Ok(1));
+    assert_eq!(store.key_id(StateSpace::StatePtrLatest, b"resource-1"), Ok(Some(2)));
+
+    // The retired key is no longer needed.
+    let store = EncryptedStore::new(inner, EncryptionConfig::new(Keyring::new(2, KEY_2)));
+    assert_eq!(store.get(StateSpace::StatePtrLatest, b"resource-1"), Some(b"value-1".to_vec()));
+    assert_eq!(store.get(StateSpace::StatePtrLatest, b"resource-2"), Some(b"value-2b".to_vec()));
+}
+
+/// Tests that values which can not be decrypted are reported as errors and never read as missing.
+#[test]
+pub fn test_encrypted_store_undecryptable_values() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let inner: RocksDbStore = RocksDbStore::open(temp_dir.path());
+    let store = EncryptedStore::new(inner.clone(), EncryptionConfig::new(Keyring::new(1, KEY_1)));
+
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-1", b"value-1");
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-2", b"value-2");
+    store.commit(write_batch);
+
+    // Corrupt one value and move the other one to a different key.
+    let envelope = inner.get(StateSpace::StatePtrLatest, b"resource-2").unwrap();
+    let mut write_batch = inner.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-1", &[0, 0, 0, 1, 7]);
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-3", &envelope);
+    inner.commit(write_batch);
+
+    assert_eq!(
+        store.try_get(StateSpace::StatePtrLatest, b"resource-1"),
+        Err(EncryptionError::Truncated)
+    );
+    assert_eq!(
+        store.try_get(StateSpace::StatePtrLatest, b"resource-3"),
+        Err(EncryptionError::Unauthenticated)
+    );
+    assert_eq!(
+        store.try_prefix_iter(StateSpace::StatePtrLatest, b"").filter(Result::is_err).count(),
+        2
+    );
+
+    // The store interface refuses to read them.
+    let get = panic::catch_unwind(|| store.get(StateSpace::StatePtrLatest, b"resource-1"));
+    assert!(get.is_err());
+    let iter = panic::catch_unwind(|| store.prefix_iter(StateSpace::StatePtrLatest, b"").count());
+    assert!(iter.is_err());
+}
@@ -0,0 +1,132 @@
+use tempfile::TempDir;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_rocksdb_store::{
+    DefaultConfig, RocksDbReadOnlyStore, RocksDbStore, StateSpaceExt, rocksdb::Options,
+};
+use vprogs_storage_types::{Store, WriteBatch};
+
+/// An application-defined state space with its own column families.
+enum AppSpace {
+    Index,
+    Settings,
+}
+
+impl StateSpaceExt for AppSpace {
+    fn all() -> Vec<Self> {
+        vec![AppSpace::Index, AppSpace::Settings]
+    }
+
+    fn cf_name(&self) -> &'static str {
+        match self {
+            AppSpace::Index => "app_index",
+            AppSpace::Settings => "app_settings",
+        }
+    }
+
+    fn cf_opts(&self) -> Options {
+        Options::default()
+    }
+}
+
+/// Tests that a store can be opened over custom column families and keeps them separate.
+#[test]
+pub fn test_custom_state_space() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let store: RocksDbStore<DefaultConfig, AppSpace> = RocksDbStore::open(temp_dir.path());
+
+    let mut write_batch = store.write_batch();
+    write_batch.put(AppSpace::Index, b"owner/1", b"a");
+    write_batch.put(AppSpace::Index, b"owner/2", b"b");
+    write_batch.put(AppSpace::Index, b"type/1", b"c");
+    write_batch.put(AppSpace::Settings, b"owner/3", b"d");
+    store.commit(write_batch);
+
+    assert_eq!(store.get(AppSpace::Index, b"owner/1"), Some(b"a".to_vec()));
+    assert_eq!(store.get(AppSpace::Settings, b"owner/1"), None);
+    assert_eq!(
+        store.prefix_iter(AppSpace::Index, b"owner/").map(|(_, v)| v).collect::<Vec<_>>(),
+        vec![b"a".to_vec(), b"b".to_vec()]
+    );
+}
+
+/// Tests that read-only and secondary instances can read a database that is held open by a
+/// primary, and that the secondary follows the primary after catching up.
+#[test]
+pub fn test_read_only_and_secondary_store() {
+    use vprogs_storage_types::ReadStore;
+
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let secondary_dir = TempDir::new().expect("failed to create temp dir");
+    let primary: RocksDbStore = RocksDbStore::open(temp_dir.path());
+
+    let mut write_batch = primary.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource", &1u64.to_be_bytes());
+    primary.commit(write_batch);
+
+    let read_only: RocksDbReadOnlyStore = RocksDbReadOnlyStore::open_read_only(temp_dir.path());
+    let secondary: RocksDbReadOnlyStore =
+        RocksDbReadOnlyStore::open_secondary(temp_dir.path(), secondary_dir.path());
+    assert_eq!(
+        read_only.get(StateSpace::StatePtrLatest, b"resource"),
+        Some(1u64.to_be_bytes().to_vec())
+    );
+    assert_eq!(
+        secondary.get(StateSpace::StatePtrLatest, b"resource"),
+        Some(1u64.to_be_bytes().to_vec())
+    );
+
+    let mut write_batch = primary.write_batch();
+    write_batch.put(StateSpace::StatePtrLatest, b"resource", &2u64.to_be_bytes());
+    primary.commit(write_batch);
+
+    secondary.try_catch_up_with_primary();
+    assert_eq!(
+        secondary.get(StateSpace::StatePtrLatest, b"resource"),
+        Some(2u64.to_be_bytes().to_vec())
+    );
+    assert_eq!(secondary.prefix_iter(StateSpace::StatePtrLatest, b"res").count(), 1);
+}
+
+/// Tests that prefixes without an upper bound are deleted up to the end of the state space.
+#[test]
+pub fn test_delete_unbounded_prefix() {
+    let temp_dir = TempDir::new().expect("failed to create temp dir");
+    let store: RocksDbStore = RocksDbStore::open(temp_dir.path());
+
+    let key =
+        |batch_index: u64, resource_id: &[u8]| [&batch_index.to_be_bytes(), resource_id].concat();
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StatePtrRollback, &key(1, b"a"), b"1");
+    write_batch.put(StateSpace::StatePtrRollback, &key(u64::MAX, b"a"), b"2");
+    write_batch.put(StateSpace::StatePtrRollback, &key(u64::MAX, b"\xff\xff"), b"3");
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-1", b"4");
+    write_batch.put(StateSpace::StatePtrLatest, b"resource-2", b"5");
+    store.commit(write_batch);
+
+    // An all-`0xff` prefix only covers the keys at the end of the state space.
+    let mut write_batch = store.write_batch();
+    write_batch.delete_prefix(StateSpace::StatePtrRollback, &u64::MAX.to_be_bytes());
+    store.commit(write_batch);
+    assert_eq!(
+        store.iter_from(StateSpace::StatePtrRollback, &[]).map(|(k, _)| k).collect::<Vec<_>>(),
+        vec![key(1, b"a")]
+    );
+
+    // An empty prefix covers the whole state space, and only that one.
+    let mut write_batch = store.write_batch();
+    write_batch.delete_prefix(StateSpace::StatePtrLatest, &[]);
+    store.commit(write_batch);
+    assert_eq!(store.iter_from(StateSpace::StatePtrLatest, &[]).count(), 0);
+    assert_eq!(store.iter_from(StateSpace::StatePtrRollback, &[]).count(), 1);
+
+    // Keys put earlier in the same batch are covered as well, also beyond the last stored key.
+    let mut write_batch = store.write_batch();
+    write_batch.put(StateSpace::StatePtrRollback, &key(2, b"a"), b"6");
+    write_batch.put(StateSpace::StatePtrRollback, &key(u64::MAX, b"b"), b"7");
+    write_batch.delete_prefix(StateSpace::StatePtrRollback, &u64::MAX.to_be_bytes());
+    store.commit(write_batch);
+    assert_eq!(
+        store.iter_from(StateSpace::StatePtrRollback, &[]).map(|(k, _)| k).collect::<Vec<_>>(),
+        vec![key(1, b"a"), key(2, b"a")]
+    );
+}
@@ -0,0 +1,132 @@
+use std::ops::RangeInclusive;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
+use vprogs_state_ptr_rollback::StatePtrRollback;
+use vprogs_state_space::StateSpace;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::ReadStore;
+
+use crate::{ChangesetError, ChangesetResult};
+
+/// A resource that was modified by a batch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResourceChange<R: ResourceId> {
+    pub resource_id: R,
+    /// The version before the batch, or 0 if the batch created the resource.
+    pub old_version: u64,
+    /// The version the batch wrote.
+    pub new_version: u64,
+    /// Whether the batch deleted the resource, in which case the new version is a tombstone.
+    pub deleted: bool,
+    /// The data before the batch, if requested and the resource existed.
+    pub old_data: Option<Vec<u8>>,
+    /// The data the batch wrote, if requested and the resource was not deleted.
+    pub new_data: Option<Vec<u8>>,
+}
+
+/// The resources modified by a committed batch.
+///
+/// Changesets are derived from the rollback pointers of a batch, so they are available for every
+/// committed batch that has not been pruned yet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Changeset<R: ResourceId> {
+    pub batch_index: u64,
+    /// The modified resources, ordered by their serialized IDs.
+    pub changes: Vec<ResourceChange<R>>,
+}
+
+impl<R: ResourceId> Changeset<R> {
+    /// Loads the changeset of a committed batch, including the old and new data of every modified
+    /// resource if `with_data` is set.
+    pub fn load<S>(store: &S, batch_index: u64, with_data: bool) -> ChangesetResult<Self>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::check_available(store, batch_index)?;
+
+        let mut changes = Vec::new();
+        for (resource_id, old_version) in StatePtrRollback::iter_batch(store, batch_index) {
+            let resource_id = R::from_bytes(&resource_id);
+            let Some(new_version) = StatePtrHistory::get(store, &resource_id, batch_index) else {
+                return Err(Self::unavailable(store, batch_index));
+            };
+
+            let deleted = StateVersion::is_tombstone(store, new_version, &resource_id);
+            let (mut old_data, mut new_data) = (None, None);
+            if with_data {
+                if old_version != 0 && !StateVersion::is_tombstone(store, old_version, &resource_id)
+                {
+                    old_data = StateVersion::get(store, old_version, &resource_id)?;
+                    if old_data.is_none() {
+                        return Err(Self::unavailable(store, batch_index));
+                    }
+                }
+                if !deleted {
+                    new_data = StateVersion::get(store, new_version, &resource_id)?;
+                    if new_data.is_none() {
+                        return Err(Self::unavailable(store, batch_index));
+                    }
+                }
+            }
+
+            changes.push(ResourceChange {
+                resource_id,
+                old_version,
+                new_version,
+                deleted,
+                old_data,
+                new_data,
+            });
+        }
+
+        // A concurrent prune or rollback may have removed the batch while it was being read.
+        Self::check_available(store, batch_index)?;
+
+        Ok(Self { batch_index, changes })
+    }
+
+    /// Iterates the changesets of a range of committed batches in ascending order.
+    ///
+    /// The iterator yields an error for every batch that is not available, e.g. because it was
+    /// pruned or rolled back while iterating.
+    pub fn iter<S>(
+        store: &S,
+        batch_indices: RangeInclusive<u64>,
+        with_data: bool,
+    ) -> impl Iterator<Item = ChangesetResult<Self>> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        batch_indices.map(move |batch_index| Self::load(store, batch_index, with_data))
+    }
+
+    /// Returns an error if the batch is not committed or has been pruned.
+    fn check_available<S>(store: &S, batch_index: u64) -> ChangesetResult<()>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        if batch_index > StateMetadata::last_committed_index(store) {
+            return Err(ChangesetError::NotCommitted(batch_index));
+        }
+        if batch_index <= StateMetadata::pruned_index(store) {
+            return Err(ChangesetError::Pruned(batch_index));
+        }
+        Ok(())
+    }
+
+    /// Returns the reason why an entry of an available batch was missing.
+    ///
+    /// # Panics
+    /// Panics if the batch is still available, which means that the store is inconsistent.
+    fn unavailable<S>(store: &S, batch_index: u64) -> ChangesetError
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        match Self::check_available(store, batch_index) {
+            Err(err) => err,
+            Ok(()) => panic!("missing state of committed batch {batch_index}"),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+use vprogs_state_version::CorruptionError;
+
+/// Errors that can occur while loading the changeset of a batch.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChangesetError {
+    /// The batch with the given index has not been committed.
+    NotCommitted(u64),
+    /// The batch with the given index has been pruned, which removed the record of its changes.
+    Pruned(u64),
+    /// The stored data of a changed resource does not match its checksum.
+    CorruptedState(CorruptionError),
+}
+
+impl From<CorruptionError> for ChangesetError {
+    fn from(err: CorruptionError) -> Self {
+        ChangesetError::CorruptedState(err)
+    }
+}
+
+impl fmt::Display for ChangesetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangesetError::NotCommitted(i) => write!(f, "batch {i} has not been committed"),
+            ChangesetError::Pruned(i) => write!(f, "batch {i} has been pruned"),
+            ChangesetError::CorruptedState(err) => write!(f, "can not load {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ChangesetError {}
+
+pub type ChangesetResult<T> = Result<T, ChangesetError>;
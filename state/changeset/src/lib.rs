@@ -0,0 +1,5 @@
+mod changeset;
+mod error;
+
+pub use changeset::{Changeset, ResourceChange};
+pub use error::{ChangesetError, ChangesetResult};
@@ -0,0 +1,279 @@
+use std::collections::HashSet;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_ptr_rollback::StatePtrRollback;
+use vprogs_state_space::StateSpace;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::{ReadStore, Store};
+
+use crate::{FsckIssue, FsckReport};
+
+type DataCheck<R> = Box<dyn Fn(&R, &[u8]) -> Result<(), String> + Send + Sync>;
+
+/// Offline consistency checker for the state database.
+///
+/// The checker verifies that
+/// - every latest pointer references an existing version,
+/// - every rollback pointer belongs to a committed batch and references an existing version,
+/// - every version is referenced by a latest pointer, a rollback pointer or a history entry,
+/// - every version's data matches its checksum,
+/// - every chunk is referenced by a version that is referenced itself,
+/// - every content reference belongs to a referenced version stored as that content, and all stored
+///   content is referenced,
+/// - every stored resource ID decodes as `R` and, if a data check is set, every version's data
+///   passes it.
+///
+/// It must run on a store that is not being written to, e.g. a read-only instance of the database
+/// or one that no scheduler has open.
+pub struct Fsck<R: ResourceId> {
+    data_check: Option<DataCheck<R>>,
+}
+
+impl<R: ResourceId> Default for Fsck<R> {
+    fn default() -> Self {
+        Self { data_check: None }
+    }
+}
+
+impl<R: ResourceId> Fsck<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validates the data of every version, e.g. by decoding it as the resource's type.
+    pub fn with_data_check(
+        mut self,
+        data_check: impl Fn(&R, &[u8]) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.data_check = Some(Box::new(data_check));
+        self
+    }
+
+    /// Checks the store and reports all inconsistencies.
+    pub fn check<S: ReadStore<StateSpace = StateSpace>>(&self, store: &S) -> FsckReport {
+        let mut report = FsckReport {
+            last_committed_index: StateMetadata::last_committed_index(store),
+            ..Default::default()
+        };
+
+        // Versions that are still referenced by any pointer.
+        let mut referenced = HashSet::new();
+
+        for (resource_id, version, _) in StatePtrLatest::iter(store) {
+            report.resources += 1;
+            let Some(decoded) = self.decode(&mut report, StateSpace::StatePtrLatest, &resource_id)
+            else {
+                continue;
+            };
+            if matches!(StateVersion::get(store, version, &decoded), Ok(None)) {
+                report.issues.push(FsckIssue::DanglingLatestPtr {
+                    resource_id: resource_id.clone(),
+                    version,
+                });
+            }
+            referenced.insert((resource_id, version));
+        }
+
+        for (batch_index, resource_id, old_version) in StatePtrRollback::iter(store) {
+            report.rollback_ptrs += 1;
+            let Some(decoded) =
+                self.decode(&mut report, StateSpace::StatePtrRollback, &resource_id)
+            else {
+                continue;
+            };
+            if batch_index > report.last_committed_index {
+                report.issues.push(FsckIssue::UncommittedRollbackPtr {
+                    batch_index,
+                    resource_id: resource_id.clone(),
+                    old_version,
+                });
+            }
+            // Version 0 means that the resource did not exist before the batch.
+            if old_version != 0
+                && matches!(StateVersion::get(store, old_version, &decoded), Ok(None))
+            {
+                report.issues.push(FsckIssue::MissingRollbackVersion {
+                    batch_index,
+                    resource_id: resource_id.clone(),
+                    version: old_version,
+                });
+            }
+            referenced.insert((resource_id, old_version));
+        }
+
+        for (resource_id, _, version) in StatePtrHistory::iter(store) {
+            report.history_entries += 1;
+            if self.decode(&mut report, StateSpace::StatePtrHistory, &resource_id).is_some() {
+                referenced.insert((resource_id, version));
+            }
+        }
+
+        // Chunks `(owner, resource_id, index)` of versions that are referenced.
+        let mut referenced_chunks = HashSet::new();
+        // Content references `(hash, version, resource_id)` of versions that are referenced.
+        let mut content_refs = HashSet::new();
+
+        for (version, resource_id, data) in StateVersion::<R>::iter(store) {
+            report.versions += 1;
+            let Some(decoded) = self.decode(&mut report, StateSpace::StateVersion, &resource_id)
+            else {
+                continue;
+            };
+            match data {
+                Err(_) => report.issues.push(FsckIssue::CorruptedVersion {
+                    resource_id: resource_id.clone(),
+                    version,
+                }),
+                // Tombstones have no data to check.
+                Ok(_) if StateVersion::is_tombstone(store, version, &decoded) => {}
+                Ok(data) => {
+                    if let Some(Err(reason)) =
+                        self.data_check.as_ref().map(|check| check(&decoded, &data))
+                    {
+                        report.issues.push(FsckIssue::InvalidData {
+                            resource_id: resource_id.clone(),
+                            version,
+                            reason,
+                        });
+                    }
+                }
+            }
+            if !referenced.contains(&(resource_id.clone(), version)) {
+                report.issues.push(FsckIssue::OrphanVersion { resource_id, version });
+                continue;
+            }
+            for (index, chunk_ref) in
+                StateVersion::chunk_refs(store, version, &decoded).into_iter().enumerate()
+            {
+                referenced_chunks.insert((chunk_ref.owner, resource_id.clone(), index));
+            }
+            if let Some(hash) = StateVersion::content_hash(store, version, &decoded) {
+                content_refs.insert((hash, version, resource_id));
+            }
+        }
+
+        for (owner, resource_id, index) in StateVersion::<R>::iter_chunks(store) {
+            report.chunks += 1;
+            if self.decode(&mut report, StateSpace::StateVersionChunk, &resource_id).is_some()
+                && !referenced_chunks.contains(&(owner, resource_id.clone(), index))
+            {
+                report.issues.push(FsckIssue::OrphanChunk { owner, resource_id, index });
+            }
+        }
+
+        // Content is referenced if any of its references is valid.
+        let referenced_content: HashSet<_> = content_refs.iter().map(|(hash, ..)| *hash).collect();
+        for (hash, reference) in StateVersion::<R>::iter_content(store) {
+            match reference {
+                None => {
+                    report.content += 1;
+                    if !referenced_content.contains(&hash) {
+                        report.issues.push(FsckIssue::OrphanContent { hash });
+                    }
+                }
+                Some((version, resource_id)) => {
+                    if self.decode(&mut report, StateSpace::StateContent, &resource_id).is_some()
+                        && !content_refs.contains(&(hash, version, resource_id.clone()))
+                    {
+                        report.issues.push(FsckIssue::DanglingContentRef {
+                            hash,
+                            resource_id,
+                            version,
+                        });
+                    }
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Repairs the repairable issues of a report in a single write batch and returns how many were
+    /// repaired.
+    ///
+    /// The report must have been created for the current state of the store.
+    pub fn repair<S: Store<StateSpace = StateSpace>>(
+        &self,
+        store: &S,
+        report: &FsckReport,
+    ) -> usize {
+        let mut write_batch = store.write_batch();
+        let mut repaired = 0;
+
+        for issue in &report.issues {
+            match issue {
+                FsckIssue::UncommittedRollbackPtr { batch_index, resource_id, old_version } => {
+                    let resource_id = R::from_bytes(resource_id);
+
+                    // Remove the version the batch wrote and restore the latest pointer if the
+                    // write was published.
+                    if let Some(written_version) =
+                        StatePtrHistory::get(store, &resource_id, *batch_index)
+                    {
+                        StateVersion::delete_with_chunks(
+                            store,
+                            &mut write_batch,
+                            written_version,
+                            &resource_id,
+                        );
+                        if StatePtrLatest::get(store, &resource_id) == Some(written_version) {
+                            match old_version {
+                                0 => StatePtrLatest::delete(&mut write_batch, &resource_id),
+                                _ => StateVersion::restore_latest_ptr(
+                                    store,
+                                    &mut write_batch,
+                                    *old_version,
+                                    &resource_id,
+                                ),
+                            }
+                        }
+                    }
+                    StatePtrHistory::delete(&mut write_batch, &resource_id, *batch_index);
+                    StatePtrRollback::delete(&mut write_batch, *batch_index, &resource_id);
+                }
+                // The chunks and content references of orphan versions are reported as orphan
+                // chunks and dangling content references themselves.
+                FsckIssue::OrphanVersion { resource_id, version } => {
+                    StateVersion::delete(&mut write_batch, *version, &R::from_bytes(resource_id));
+                }
+                FsckIssue::OrphanChunk { owner, resource_id, index } => {
+                    let resource_id = R::from_bytes(resource_id);
+                    StateVersion::delete_chunk(&mut write_batch, *owner, &resource_id, *index);
+                }
+                FsckIssue::DanglingContentRef { hash, resource_id, version } => {
+                    let resource_id = R::from_bytes(resource_id);
+                    StateVersion::delete_content_ref(
+                        &mut write_batch,
+                        hash,
+                        *version,
+                        &resource_id,
+                    );
+                }
+                // Content that only dangling references point to is reported as orphan content, so
+                // deleting it in the same batch as the references is safe.
+                FsckIssue::OrphanContent { hash } => {
+                    StateVersion::<R>::delete_content(&mut write_batch, hash);
+                }
+                _ => continue,
+            }
+            repaired += 1;
+        }
+
+        store.commit(write_batch);
+        repaired
+    }
+
+    /// Decodes a resource ID, recording an issue if it is malformed.
+    fn decode(&self, report: &mut FsckReport, state_space: StateSpace, bytes: &[u8]) -> Option<R> {
+        let decoded = R::try_from_bytes(bytes);
+        if decoded.is_none() {
+            report
+                .issues
+                .push(FsckIssue::InvalidResourceId { state_space, resource_id: bytes.to_vec() });
+        }
+        decoded
+    }
+}
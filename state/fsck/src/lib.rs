@@ -0,0 +1,5 @@
+mod fsck;
+mod report;
+
+pub use fsck::Fsck;
+pub use report::{FsckIssue, FsckReport};
@@ -0,0 +1,75 @@
+use vprogs_state_space::StateSpace;
+
+/// An inconsistency found by [`Fsck`](crate::Fsck).
+///
+/// Resource IDs are kept in their encoded form, so that issues can be reported for resources
+/// whose IDs do not decode.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FsckIssue {
+    /// A latest pointer references a version whose data does not exist. Not repairable.
+    DanglingLatestPtr { resource_id: Vec<u8>, version: u64 },
+    /// A rollback pointer belongs to a batch that was never committed. Repaired by reverting the
+    /// batch's write to the resource.
+    UncommittedRollbackPtr { batch_index: u64, resource_id: Vec<u8>, old_version: u64 },
+    /// A rollback pointer references a version whose data does not exist. Not repairable.
+    MissingRollbackVersion { batch_index: u64, resource_id: Vec<u8>, version: u64 },
+    /// A version is not referenced by any pointer. Repaired by deleting it.
+    OrphanVersion { resource_id: Vec<u8>, version: u64 },
+    /// A chunk is not referenced by any version that is referenced itself. Repaired by deleting
+    /// it.
+    OrphanChunk { owner: u64, resource_id: Vec<u8>, index: usize },
+    /// A reference to content does not belong to a referenced version that is stored as that
+    /// content. Repaired by deleting it.
+    DanglingContentRef { hash: [u8; 32], resource_id: Vec<u8>, version: u64 },
+    /// Content is not referenced by any version that is referenced itself. Repaired by deleting
+    /// it.
+    OrphanContent { hash: [u8; 32] },
+    /// A key holds a resource ID that does not decode. Not repairable.
+    InvalidResourceId { state_space: StateSpace, resource_id: Vec<u8> },
+    /// The data of a version does not match its checksum. Not repairable.
+    CorruptedVersion { resource_id: Vec<u8>, version: u64 },
+    /// The data of a version was rejected by the data check. Not repairable.
+    InvalidData { resource_id: Vec<u8>, version: u64, reason: String },
+}
+
+impl FsckIssue {
+    /// Returns whether [`Fsck::repair`](crate::Fsck::repair) can fix this issue.
+    pub fn is_repairable(&self) -> bool {
+        matches!(
+            self,
+            FsckIssue::UncommittedRollbackPtr { .. }
+                | FsckIssue::OrphanVersion { .. }
+                | FsckIssue::OrphanChunk { .. }
+                | FsckIssue::DanglingContentRef { .. }
+                | FsckIssue::OrphanContent { .. }
+        )
+    }
+}
+
+/// The result of a consistency check.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FsckReport {
+    /// The index of the last committed batch.
+    pub last_committed_index: u64,
+    /// The number of resources with a latest pointer.
+    pub resources: usize,
+    /// The number of stored versions.
+    pub versions: usize,
+    /// The number of stored chunks of large values.
+    pub chunks: usize,
+    /// The number of distinct values stored by content hash.
+    pub content: usize,
+    /// The number of rollback pointers.
+    pub rollback_ptrs: usize,
+    /// The number of history entries.
+    pub history_entries: usize,
+    /// All inconsistencies that were found.
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    /// Returns whether no inconsistencies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
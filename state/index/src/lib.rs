@@ -0,0 +1,5 @@
+mod secondary_indexes;
+mod state_index;
+
+pub use secondary_indexes::SecondaryIndexes;
+pub use state_index::StateIndex;
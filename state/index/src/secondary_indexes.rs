@@ -0,0 +1,112 @@
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_space::StateSpace;
+use vprogs_state_version::{CorruptionError, StateVersion};
+use vprogs_storage_types::{Store, WriteBatch};
+
+use crate::StateIndex;
+
+type IndexKeys<R> = Arc<dyn Fn(&R, &[u8]) -> Vec<Vec<u8>> + Send + Sync>;
+
+/// The secondary indexes maintained for the resources of a store.
+///
+/// Every index is identified by a `u16` and defined by a function that maps the data of a resource
+/// to the keys it is indexed under. The entries are stored in [`StateIndex`] and reflect the latest
+/// committed state of every resource.
+#[derive(Clone)]
+pub struct SecondaryIndexes<R: ResourceId> {
+    indexes: Vec<(u16, IndexKeys<R>)>,
+}
+
+impl<R: ResourceId> Default for SecondaryIndexes<R> {
+    fn default() -> Self {
+        Self { indexes: Vec::new() }
+    }
+}
+
+impl<R: ResourceId> SecondaryIndexes<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an index that maps the data of a resource to the keys it is indexed under.
+    ///
+    /// # Panics
+    /// Panics if an index with the same ID was added before.
+    pub fn with_index(
+        mut self,
+        index_id: u16,
+        index_keys: impl Fn(&R, &[u8]) -> Vec<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        assert!(self.indexes.iter().all(|(id, _)| *id != index_id), "duplicate index {index_id}");
+        self.indexes.push((index_id, Arc::new(index_keys)));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indexes.is_empty()
+    }
+
+    /// Moves a resource from the keys of its old data to the keys of its new data, where `None`
+    /// means that the resource does not exist.
+    ///
+    /// Only the entries that differ are written, so that applying the reverse update restores the
+    /// old entries even if this update was never applied.
+    pub fn update<W>(
+        &self,
+        write_batch: &mut W,
+        resource_id: &R,
+        old_data: Option<&[u8]>,
+        new_data: Option<&[u8]>,
+    ) where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        for (index_id, index_keys) in &self.indexes {
+            let keys = |data: Option<&[u8]>| -> HashSet<Vec<u8>> {
+                data.map(|data| index_keys(resource_id, data)).into_iter().flatten().collect()
+            };
+            let (old_keys, new_keys) = (keys(old_data), keys(new_data));
+
+            for key in old_keys.difference(&new_keys) {
+                StateIndex::delete(write_batch, *index_id, key, resource_id);
+            }
+            for key in new_keys.difference(&old_keys) {
+                StateIndex::put(write_batch, *index_id, key, resource_id);
+            }
+        }
+    }
+
+    /// Rebuilds all indexes from the latest state of every resource, e.g. after adding an index
+    /// to an existing store or importing a snapshot.
+    ///
+    /// Must not run while a scheduler writes to the store. Returns an error, leaving the indexes
+    /// unchanged, if the data of a resource fails verification.
+    pub fn rebuild<S>(&self, store: &S) -> Result<(), CorruptionError>
+    where
+        S: Store<StateSpace = StateSpace>,
+    {
+        let mut write_batch = store.write_batch();
+        for (index_id, _) in &self.indexes {
+            StateIndex::delete_index(&mut write_batch, *index_id);
+        }
+        for (resource_id, version, is_deleted) in StatePtrLatest::iter(store) {
+            if is_deleted {
+                continue;
+            }
+            let resource_id = R::from_bytes(&resource_id);
+            if let Some(data) = StateVersion::get(store, version, &resource_id)? {
+                self.update(&mut write_batch, &resource_id, None, Some(&data));
+            }
+        }
+        store.commit(write_batch);
+        Ok(())
+    }
+}
+
+impl<R: ResourceId> fmt::Debug for SecondaryIndexes<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.indexes.iter().map(|(index_id, _)| index_id)).finish()
+    }
+}
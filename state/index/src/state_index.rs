@@ -0,0 +1,79 @@
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_manager::concat_bytes;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+/// Provides type-safe operations for the Index column family.
+///
+/// StateIndex records the resources indexed under each key of a secondary index, reflecting the
+/// latest committed state of every resource.
+///
+/// Key layout: `index_id.to_be_bytes() || (key.len() as u32).to_be_bytes() || key ||
+/// resource_id.to_bytes()`
+/// Value layout: empty
+pub struct StateIndex;
+
+impl StateIndex {
+    /// Records that a resource is indexed under `key`.
+    pub fn put<W, R>(store: &mut W, index_id: u16, key: &[u8], resource_id: &R)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let entry = concat_bytes!(&Self::key_prefix(index_id, key), &resource_id.to_bytes());
+        store.put(StateSpace::StateIndex, &entry, &[]);
+    }
+
+    /// Removes a resource from `key`.
+    pub fn delete<W, R>(store: &mut W, index_id: u16, key: &[u8], resource_id: &R)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let entry = concat_bytes!(&Self::key_prefix(index_id, key), &resource_id.to_bytes());
+        store.delete(StateSpace::StateIndex, &entry);
+    }
+
+    /// Removes all entries of an index.
+    pub fn delete_index<W>(store: &mut W, index_id: u16)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.delete_prefix(StateSpace::StateIndex, &index_id.to_be_bytes());
+    }
+
+    /// Iterates the resources indexed under `key`.
+    pub fn lookup<'a, S, R>(store: &'a S, index_id: u16, key: &[u8]) -> impl Iterator<Item = R> + 'a
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let prefix = Self::key_prefix(index_id, key);
+        store
+            .prefix_iter(StateSpace::StateIndex, &prefix)
+            .map(move |(entry, _)| R::from_bytes(&entry[prefix.len()..]))
+    }
+
+    /// Iterates all entries of an index.
+    ///
+    /// Returns an iterator yielding `(key, resource_id_bytes)` pairs.
+    /// The caller must decode the resource ID bytes using `ResourceId::from_bytes`.
+    pub fn iter<S>(store: &S, index_id: u16) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.prefix_iter(StateSpace::StateIndex, &index_id.to_be_bytes()).map(|(entry, _)| {
+            let entry = &entry[size_of::<u16>()..];
+            let (key_len, entry) = entry.split_at(size_of::<u32>());
+            let key_len = u32::from_be_bytes(key_len.try_into().unwrap()) as usize;
+            let (key, resource_id) = entry.split_at(key_len);
+            (key.to_vec(), resource_id.to_vec())
+        })
+    }
+
+    /// The key length is part of the prefix, so that a key never matches the entries of a longer
+    /// key that starts with it.
+    fn key_prefix(index_id: u16, key: &[u8]) -> Vec<u8> {
+        concat_bytes!(&index_id.to_be_bytes(), &(key.len() as u32).to_be_bytes(), key)
+    }
+}
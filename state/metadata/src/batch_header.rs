@@ -0,0 +1,48 @@
+/// The record of a committed batch, kept until the batch is rolled back.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BatchHeader {
+    pub index: u64,
+    /// The number of transactions in the batch.
+    pub tx_count: u64,
+    /// The number of resources the batch accessed, including the ones it only read.
+    pub state_diff_count: u64,
+    /// A commitment to the state after the batch, e.g. its state root.
+    pub commitment: Option<[u8; 32]>,
+    /// Context supplied by the caller that scheduled the batch, e.g. the hash of the L1 block it
+    /// was derived from.
+    pub context: Vec<u8>,
+}
+
+impl BatchHeader {
+    /// Value layout: `index || tx_count || state_diff_count || has_commitment(1) || commitment? ||
+    /// context`, with all integers big-endian.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(57 + self.context.len());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.tx_count.to_be_bytes());
+        bytes.extend_from_slice(&self.state_diff_count.to_be_bytes());
+        match &self.commitment {
+            Some(commitment) => {
+                bytes.push(1);
+                bytes.extend_from_slice(commitment);
+            }
+            None => bytes.push(0),
+        }
+        bytes.extend_from_slice(&self.context);
+        bytes
+    }
+
+    /// Decodes a header, returning `None` if the bytes are malformed.
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let u64_at = |offset: usize| {
+            bytes.get(offset..offset + 8).map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+        };
+        let (index, tx_count, state_diff_count) = (u64_at(0)?, u64_at(8)?, u64_at(16)?);
+        let (commitment, context) = match *bytes.get(24)? {
+            0 => (None, &bytes[25..]),
+            1 => (Some(bytes.get(25..57)?.try_into().unwrap()), &bytes[57..]),
+            _ => return None,
+        };
+        Some(Self { index, tx_count, state_diff_count, commitment, context: context.to_vec() })
+    }
+}
@@ -0,0 +1,171 @@
+mod batch_header;
+
+use std::ops::RangeInclusive;
+
+pub use batch_header::BatchHeader;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_manager::concat_bytes;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+/// Provides type-safe operations for the Metadata column family.
+///
+/// Metadata holds single values that describe the database as a whole, each under a fixed key,
+/// and the [`BatchHeader`] of every committed batch.
+/// Key layout: the name of the entry (e.g. `b"pruned_index"`), followed by
+/// `batch_index.to_be_bytes()` for batch headers
+/// Value layout: entry-specific (batch indices are stored as `index.to_be_bytes()`)
+pub struct StateMetadata;
+
+impl StateMetadata {
+    const PRUNED_INDEX: &[u8] = b"pruned_index";
+    const LAST_COMMITTED_INDEX: &[u8] = b"last_committed_index";
+    const FINALIZED_INDEX: &[u8] = b"finalized_index";
+    const SYNC_PROGRESS: &[u8] = b"sync_progress";
+    const BATCH_HEADER: &[u8] = b"batch_header";
+
+    /// Gets the index of the last batch that was pruned, or 0 if nothing was pruned yet.
+    pub fn pruned_index<S>(store: &S) -> u64
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::get_index(store, Self::PRUNED_INDEX)
+    }
+
+    /// Sets the index of the last batch that was pruned.
+    pub fn set_pruned_index<W>(store: &mut W, index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.put(StateSpace::Metadata, Self::PRUNED_INDEX, &index.to_be_bytes());
+    }
+
+    /// Gets the index of the last batch that was committed, or 0 if nothing was committed yet.
+    ///
+    /// Rollbacks lower the index to their target, so it always refers to a batch of the current
+    /// lineage.
+    pub fn last_committed_index<S>(store: &S) -> u64
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::get_index(store, Self::LAST_COMMITTED_INDEX)
+    }
+
+    /// Sets the index of the last batch that was committed.
+    pub fn set_last_committed_index<W>(store: &mut W, index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.put(StateSpace::Metadata, Self::LAST_COMMITTED_INDEX, &index.to_be_bytes());
+    }
+
+    /// Gets the highest finalized batch index that was persisted, or 0 if none was recorded yet.
+    pub fn finalized_index<S>(store: &S) -> u64
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::get_index(store, Self::FINALIZED_INDEX)
+    }
+
+    /// Sets the highest finalized batch index.
+    pub fn set_finalized_index<W>(store: &mut W, index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.put(StateSpace::Metadata, Self::FINALIZED_INDEX, &index.to_be_bytes());
+    }
+
+    /// Gets the encoded progress of an ongoing state sync, if any.
+    pub fn sync_progress<S>(store: &S) -> Option<Vec<u8>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.get(StateSpace::Metadata, Self::SYNC_PROGRESS)
+    }
+
+    /// Sets the encoded progress of an ongoing state sync.
+    pub fn set_sync_progress<W>(store: &mut W, progress: &[u8])
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.put(StateSpace::Metadata, Self::SYNC_PROGRESS, progress);
+    }
+
+    /// Deletes the progress of a completed state sync.
+    pub fn delete_sync_progress<W>(store: &mut W)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.delete(StateSpace::Metadata, Self::SYNC_PROGRESS);
+    }
+
+    /// Gets the header of a committed batch, or `None` if the batch was not committed or has
+    /// been rolled back.
+    ///
+    /// # Panics
+    /// Panics if the stored header is malformed.
+    pub fn batch_header<S>(store: &S, batch_index: u64) -> Option<BatchHeader>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(Self::BATCH_HEADER, &batch_index.to_be_bytes());
+        store.get(StateSpace::Metadata, &key).map(|bytes| Self::decode_batch_header(&bytes))
+    }
+
+    /// Iterates the headers of the committed batches in a range of indices in ascending order,
+    /// skipping batches that have no header.
+    ///
+    /// Only the stored headers are visited, so the range may be unbounded (e.g. `0..=u64::MAX`).
+    ///
+    /// # Panics
+    /// Panics if a stored header is malformed.
+    pub fn batch_headers<S>(
+        store: &S,
+        batch_indices: RangeInclusive<u64>,
+    ) -> impl Iterator<Item = BatchHeader> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let from = concat_bytes!(Self::BATCH_HEADER, &batch_indices.start().to_be_bytes());
+        let end = *batch_indices.end();
+        store.iter_from(StateSpace::Metadata, &from).map_while(move |(key, value)| {
+            let batch_index = key.strip_prefix(Self::BATCH_HEADER)?;
+            let batch_index = u64::from_be_bytes(batch_index.try_into().ok()?);
+            (batch_index <= end).then(|| Self::decode_batch_header(&value))
+        })
+    }
+
+    /// Stores the header of a committed batch.
+    pub fn set_batch_header<W>(store: &mut W, header: &BatchHeader)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(Self::BATCH_HEADER, &header.index.to_be_bytes());
+        store.put(StateSpace::Metadata, &key, &header.encode());
+    }
+
+    /// Deletes the header of a batch that is rolled back.
+    pub fn delete_batch_header<W>(store: &mut W, batch_index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(Self::BATCH_HEADER, &batch_index.to_be_bytes());
+        store.delete(StateSpace::Metadata, &key);
+    }
+
+    fn decode_batch_header(bytes: &[u8]) -> BatchHeader {
+        BatchHeader::decode(bytes).unwrap_or_else(|| panic!("malformed batch header {bytes:?}"))
+    }
+
+    fn get_index<S>(store: &S, key: &[u8]) -> u64
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store
+            .get(StateSpace::Metadata, key)
+            .map(|bytes| match bytes[..].try_into() {
+                Ok(bytes) => u64::from_be_bytes(bytes),
+                Err(_) => panic!("malformed {}: {bytes:?}", String::from_utf8_lossy(key)),
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,89 @@
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_manager::concat_bytes;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+/// Provides type-safe operations for the HistoryPtr column family.
+///
+/// StatePtrHistory records the version each batch wrote for a resource, allowing the state of a
+/// resource to be looked up as of any batch that has not been pruned.
+///
+/// Key layout: `resource_id.to_bytes() || (!batch_index).to_be_bytes()`
+/// Value layout: `version.to_be_bytes()` (u64)
+///
+/// The batch index is inverted so that the entries of a resource are iterated from the newest to
+/// the oldest batch.
+pub struct StatePtrHistory;
+
+impl StatePtrHistory {
+    /// Stores the version a batch wrote for a resource.
+    pub fn put<W, R>(store: &mut W, resource_id: &R, batch_index: u64, version: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let key = concat_bytes!(&resource_id.to_bytes(), &(!batch_index).to_be_bytes());
+        store.put(StateSpace::StatePtrHistory, &key, &version.to_be_bytes());
+    }
+
+    /// Deletes the entry a batch recorded for a resource.
+    pub fn delete<W, R>(store: &mut W, resource_id: &R, batch_index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let key = concat_bytes!(&resource_id.to_bytes(), &(!batch_index).to_be_bytes());
+        store.delete(StateSpace::StatePtrHistory, &key);
+    }
+
+    /// Gets the version that the given batch wrote for a resource, if it wrote one.
+    pub fn get<S, R>(store: &S, resource_id: &R, batch_index: u64) -> Option<u64>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let key = concat_bytes!(&resource_id.to_bytes(), &(!batch_index).to_be_bytes());
+        store
+            .get(StateSpace::StatePtrHistory, &key)
+            .map(|bytes| u64::from_be_bytes(bytes[..8].try_into().unwrap()))
+    }
+
+    /// Finds the last write to a resource at or before the given batch.
+    ///
+    /// Returns `(batch_index, version)` of that write, or `None` if the resource was not written
+    /// up to the given batch. As the entries of a resource are iterated from the newest to the
+    /// oldest batch, this seeks to the given batch and takes the first entry from there on.
+    pub fn find_at<S, R>(store: &S, resource_id: &R, batch_index: u64) -> Option<(u64, u64)>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let resource_id_bytes = resource_id.to_bytes();
+        let key = concat_bytes!(&resource_id_bytes, &(!batch_index).to_be_bytes());
+        store
+            .prefix_iter_from(StateSpace::StatePtrHistory, &resource_id_bytes, &key)
+            // Skip the entries of longer resource IDs that share the prefix.
+            .find(|(key, _)| key.len() == resource_id_bytes.len() + size_of::<u64>())
+            .map(|(key, value)| {
+                let inverted_index = &key[resource_id_bytes.len()..];
+                let entry_index = !u64::from_be_bytes(inverted_index.try_into().unwrap());
+                let version = u64::from_be_bytes(value[..8].try_into().unwrap());
+                (entry_index, version)
+            })
+    }
+
+    /// Iterates the entries of all resources.
+    ///
+    /// Returns an iterator yielding `(resource_id_bytes, batch_index, version)` triples.
+    pub fn iter<S>(store: &S) -> impl Iterator<Item = (Vec<u8>, u64, u64)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StatePtrHistory, &[]).map(|(key, value)| {
+            let (resource_id_bytes, inverted_index) = key.split_at(key.len() - 8);
+            let batch_index = !u64::from_be_bytes(inverted_index.try_into().unwrap());
+            let version = u64::from_be_bytes(value[..8].try_into().unwrap());
+            (resource_id_bytes.to_vec(), batch_index, version)
+        })
+    }
+}
@@ -4,13 +4,18 @@ use vprogs_storage_types::{ReadStore, WriteBatch};
 
 /// Provides type-safe operations for the LatestPtr column family.
 ///
-/// StatePtrLatest maps resource IDs to their current version number.
+/// StatePtrLatest maps resource IDs to their current version number. Deleted resources keep a
+/// tombstone pointer, so that the versions of a re-created resource continue the sequence.
 /// Key layout: `resource_id.to_bytes()`
-/// Value layout: `version.to_be_bytes()` (u64)
+/// Value layout: `version.to_be_bytes()` (u64), followed by `TOMBSTONE` for deleted resources
 pub struct StatePtrLatest;
 
 impl StatePtrLatest {
-    /// Gets the current version for a resource, or `None` if the resource doesn't exist.
+    const TOMBSTONE: u8 = 1;
+
+    /// Gets the current version for a resource, or `None` if the resource never existed.
+    ///
+    /// The version of a deleted resource is its tombstone version.
     pub fn get<S, R>(store: &S, resource_id: &R) -> Option<u64>
     where
         S: ReadStore<StateSpace = StateSpace>,
@@ -30,6 +35,16 @@ impl StatePtrLatest {
         store.put(StateSpace::StatePtrLatest, &resource_id.to_bytes(), &version.to_be_bytes());
     }
 
+    /// Marks a resource as deleted by the given tombstone version.
+    pub fn put_tombstone<W, R>(store: &mut W, resource_id: &R, version: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let value = [&version.to_be_bytes()[..], &[Self::TOMBSTONE]].concat();
+        store.put(StateSpace::StatePtrLatest, &resource_id.to_bytes(), &value);
+    }
+
     /// Deletes the latest pointer for a resource.
     pub fn delete<W, R>(store: &mut W, resource_id: &R)
     where
@@ -38,4 +53,18 @@ impl StatePtrLatest {
     {
         store.delete(StateSpace::StatePtrLatest, &resource_id.to_bytes());
     }
+
+    /// Iterates the latest pointers of all resources, including deleted ones.
+    ///
+    /// Returns an iterator yielding `(resource_id_bytes, version, is_deleted)` triples.
+    /// The caller must decode the resource ID bytes using `ResourceId::from_bytes`.
+    pub fn iter<S>(store: &S) -> impl Iterator<Item = (Vec<u8>, u64, bool)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StatePtrLatest, &[]).map(|(key, value)| {
+            let version = u64::from_be_bytes(value[..8].try_into().unwrap());
+            (key, version, value.get(8) == Some(&Self::TOMBSTONE))
+        })
+    }
 }
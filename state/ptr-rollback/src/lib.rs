@@ -1,7 +1,7 @@
 use vprogs_core_types::ResourceId;
 use vprogs_state_space::StateSpace;
 use vprogs_storage_manager::concat_bytes;
-use vprogs_storage_types::{Store, WriteBatch};
+use vprogs_storage_types::{ReadStore, WriteBatch};
 
 /// Provides type-safe operations for the RollbackPtr column family.
 ///
@@ -33,13 +33,21 @@ impl StatePtrRollback {
         store.delete(StateSpace::StatePtrRollback, &key);
     }
 
+    /// Deletes all rollback pointer entries of a batch with a single range deletion.
+    pub fn delete_batch<W>(store: &mut W, batch_index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.delete_prefix(StateSpace::StatePtrRollback, &batch_index.to_be_bytes());
+    }
+
     /// Iterates all rollback pointers for a given batch index.
     ///
     /// Returns an iterator yielding `(resource_id_bytes, old_version)` pairs.
     /// The caller must decode the resource ID bytes using `ResourceId::from_bytes`.
     pub fn iter_batch<S>(store: &S, batch_index: u64) -> impl Iterator<Item = (Vec<u8>, u64)> + '_
     where
-        S: Store<StateSpace = StateSpace>,
+        S: ReadStore<StateSpace = StateSpace>,
     {
         store.prefix_iter(StateSpace::StatePtrRollback, &batch_index.to_be_bytes()).map(
             |(key, value)| {
@@ -49,4 +57,33 @@ impl StatePtrRollback {
             },
         )
     }
+
+    /// Iterates the rollback pointers of all batches.
+    ///
+    /// Returns an iterator yielding `(batch_index, resource_id_bytes, old_version)` triples.
+    pub fn iter<S>(store: &S) -> impl Iterator<Item = (u64, Vec<u8>, u64)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StatePtrRollback, &[]).map(|(key, value)| {
+            let batch_index = u64::from_be_bytes(key[..8].try_into().unwrap());
+            let old_version = u64::from_be_bytes(value[..8].try_into().unwrap());
+            (batch_index, key[8..].to_vec(), old_version)
+        })
+    }
+
+    /// Returns the highest batch index after `index` that has rollback pointers, if any.
+    ///
+    /// Only the rollback pointers of the batches after `index` are visited, e.g. those of batches
+    /// that were persisted but never committed.
+    pub fn last_batch_index_after<S>(store: &S, index: u64) -> Option<u64>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let from = index.checked_add(1)?.to_be_bytes();
+        store
+            .iter_from(StateSpace::StatePtrRollback, &from)
+            .map(|(key, _)| u64::from_be_bytes(key[..8].try_into().unwrap()))
+            .max()
+    }
 }
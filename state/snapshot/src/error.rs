@@ -0,0 +1,63 @@
+use std::{fmt, io};
+
+use vprogs_state_version::CorruptionError;
+
+/// Errors that can occur while exporting or importing a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading or writing the snapshot failed.
+    Io(io::Error),
+    /// The input does not start with the snapshot magic bytes.
+    InvalidMagic,
+    /// The snapshot was written in a format version this build does not understand.
+    UnsupportedVersion(u16),
+    /// The header or the chunk with the given index does not match its checksum.
+    ChecksumMismatch(Option<u64>),
+    /// A chunk is structurally invalid.
+    MalformedChunk(u64),
+    /// The imported state does not hash to the state root recorded in the header.
+    StateRootMismatch,
+    /// Snapshots can only be imported into an empty store.
+    StoreNotEmpty,
+    /// The store has no state root for the last committed batch, which a snapshot records.
+    MissingStateRoot(u64),
+    /// The stored data of an exported resource does not match its checksum.
+    CorruptedState(CorruptionError),
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<CorruptionError> for SnapshotError {
+    fn from(err: CorruptionError) -> Self {
+        SnapshotError::CorruptedState(err)
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "snapshot i/o failed: {err}"),
+            SnapshotError::InvalidMagic => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {v}"),
+            SnapshotError::ChecksumMismatch(None) => write!(f, "snapshot header is corrupted"),
+            SnapshotError::ChecksumMismatch(Some(i)) => {
+                write!(f, "snapshot chunk {i} is corrupted")
+            }
+            SnapshotError::MalformedChunk(i) => write!(f, "snapshot chunk {i} is malformed"),
+            SnapshotError::StateRootMismatch => write!(f, "snapshot state does not match its root"),
+            SnapshotError::StoreNotEmpty => {
+                write!(f, "snapshots can only be imported into an empty store")
+            }
+            SnapshotError::MissingStateRoot(i) => write!(f, "missing state root of batch {i}"),
+            SnapshotError::CorruptedState(err) => write!(f, "can not export {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+pub type SnapshotResult<T> = Result<T, SnapshotError>;
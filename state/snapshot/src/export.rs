@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::StateTree;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::ReadStore;
+
+use crate::{
+    SnapshotError, SnapshotResult,
+    format::{ChunkWriter, SnapshotHeader},
+};
+
+/// Writes the latest state of `store` to `writer` as a snapshot.
+///
+/// The snapshot contains every resource at its latest version, together with the last committed
+/// batch index and its state root. A new chunk is started once the current one holds at least
+/// `chunk_size` bytes of entries.
+///
+/// The store must not be written to while the snapshot is taken, e.g. by exporting from a
+/// read-only or secondary instance of the database. Returns an error if the last committed batch
+/// has no state root.
+pub fn export<R, S, W>(store: &S, writer: W, chunk_size: usize) -> SnapshotResult<SnapshotHeader>
+where
+    R: ResourceId,
+    S: ReadStore<StateSpace = StateSpace>,
+    W: Write,
+{
+    let batch_index = StateMetadata::last_committed_index(store);
+    let state_root =
+        StateTree::root(store, batch_index).ok_or(SnapshotError::MissingStateRoot(batch_index))?;
+
+    let header = SnapshotHeader { batch_index, state_root };
+    let mut writer = writer;
+    header.write(&mut writer)?;
+
+    let mut chunks = ChunkWriter::new(writer, chunk_size);
+    for (resource_id_bytes, version, is_deleted) in StatePtrLatest::iter(store) {
+        // Deleted resources are not part of the state.
+        if is_deleted {
+            continue;
+        }
+        let resource_id = R::from_bytes(&resource_id_bytes);
+        let Some(data) = StateVersion::get(store, version, &resource_id)? else {
+            panic!("missing data for resource_{:?}@v{:?}", resource_id, version);
+        };
+        chunks.push(&resource_id_bytes, version, &data)?;
+    }
+    chunks.finish()?;
+
+    Ok(header)
+}
@@ -0,0 +1,180 @@
+use std::io::{self, Read, Write};
+
+use vprogs_state_tree::Hash;
+
+use crate::{SnapshotError, SnapshotResult};
+
+/// The version of the snapshot format written by this build.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The payload size after which the exporter starts a new chunk.
+pub const DEFAULT_CHUNK_SIZE: usize = 4 << 20;
+
+const MAGIC: &[u8; 8] = b"VPROGSSN";
+const CHECKSUM_LEN: usize = 32;
+
+/// Describes the state contained in a snapshot.
+///
+/// Layout: `magic || format_version u16 || batch_index u64 || state_root || checksum`, where the
+/// checksum is the blake3 hash of all preceding bytes. All integers are big-endian.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SnapshotHeader {
+    /// The index of the last batch included in the snapshot.
+    pub batch_index: u64,
+    /// The state root after that batch.
+    pub state_root: Hash,
+}
+
+impl SnapshotHeader {
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 2 + 8 + 32 + CHECKSUM_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&self.batch_index.to_be_bytes());
+        bytes.extend_from_slice(&self.state_root);
+        bytes.extend_from_slice(blake3::hash(&bytes).as_bytes());
+        writer.write_all(&bytes)
+    }
+
+    pub(crate) fn read<R: Read>(reader: &mut R) -> SnapshotResult<Self> {
+        let mut bytes = [0; 8 + 2 + 8 + 32 + CHECKSUM_LEN];
+        reader.read_exact(&mut bytes[..MAGIC.len()])?;
+        if bytes[..MAGIC.len()] != *MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        reader.read_exact(&mut bytes[MAGIC.len()..])?;
+        let (fields, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if blake3::hash(fields).as_bytes() != checksum {
+            return Err(SnapshotError::ChecksumMismatch(None));
+        }
+
+        let version = u16::from_be_bytes(fields[8..10].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            batch_index: u64::from_be_bytes(fields[10..18].try_into().unwrap()),
+            state_root: fields[18..50].try_into().unwrap(),
+        })
+    }
+}
+
+/// A resource at its latest version.
+pub(crate) struct Entry {
+    pub(crate) resource_id: Vec<u8>,
+    pub(crate) version: u64,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Groups entries into checksummed chunks.
+///
+/// Chunk layout: `entry_count u32 || payload_len u32 || payload || checksum`, where the payload
+/// is the concatenation of `resource_id_len u32 || resource_id || version u64 || data_len u32 ||
+/// data` for every entry and the checksum is the blake3 hash of all preceding bytes of the chunk.
+/// The last chunk of a snapshot is empty.
+pub(crate) struct ChunkWriter<W: Write> {
+    writer: W,
+    chunk_size: usize,
+    payload: Vec<u8>,
+    entry_count: u32,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    pub(crate) fn new(writer: W, chunk_size: usize) -> Self {
+        Self { writer, chunk_size, payload: Vec::new(), entry_count: 0 }
+    }
+
+    pub(crate) fn push(&mut self, resource_id: &[u8], version: u64, data: &[u8]) -> io::Result<()> {
+        self.payload.extend_from_slice(&(resource_id.len() as u32).to_be_bytes());
+        self.payload.extend_from_slice(resource_id);
+        self.payload.extend_from_slice(&version.to_be_bytes());
+        self.payload.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        self.payload.extend_from_slice(data);
+        self.entry_count += 1;
+
+        if self.payload.len() >= self.chunk_size { self.flush_chunk() } else { Ok(()) }
+    }
+
+    /// Writes the remaining entries followed by the terminating empty chunk.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if self.entry_count > 0 {
+            self.flush_chunk()?;
+        }
+        self.flush_chunk()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        let mut prefix = [0; 8];
+        prefix[..4].copy_from_slice(&self.entry_count.to_be_bytes());
+        prefix[4..].copy_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        let checksum = blake3::Hasher::new().update(&prefix).update(&self.payload).finalize();
+
+        self.writer.write_all(&prefix)?;
+        self.writer.write_all(&self.payload)?;
+        self.writer.write_all(checksum.as_bytes())?;
+
+        self.payload.clear();
+        self.entry_count = 0;
+        Ok(())
+    }
+}
+
+/// Reads the chunk with the given index, returning `None` for the terminating empty chunk.
+pub(crate) fn read_chunk<R: Read>(
+    reader: &mut R,
+    index: u64,
+) -> SnapshotResult<Option<Vec<Entry>>> {
+    let mut prefix = [0; 8];
+    reader.read_exact(&mut prefix)?;
+    let entry_count = u32::from_be_bytes(prefix[..4].try_into().unwrap());
+    let payload_len = u32::from_be_bytes(prefix[4..].try_into().unwrap()) as u64;
+
+    let mut payload = Vec::new();
+    reader.take(payload_len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != payload_len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    let mut checksum = [0; CHECKSUM_LEN];
+    reader.read_exact(&mut checksum)?;
+    if *blake3::Hasher::new().update(&prefix).update(&payload).finalize().as_bytes() != checksum {
+        return Err(SnapshotError::ChecksumMismatch(Some(index)));
+    }
+
+    if entry_count == 0 {
+        return match payload.is_empty() {
+            true => Ok(None),
+            false => Err(SnapshotError::MalformedChunk(index)),
+        };
+    }
+
+    let mut entries = Vec::with_capacity(entry_count as usize);
+    let mut rest = payload.as_slice();
+    for _ in 0..entry_count {
+        let entry = decode_entry(&mut rest).ok_or(SnapshotError::MalformedChunk(index))?;
+        entries.push(entry);
+    }
+    match rest.is_empty() {
+        true => Ok(Some(entries)),
+        false => Err(SnapshotError::MalformedChunk(index)),
+    }
+}
+
+fn decode_entry(bytes: &mut &[u8]) -> Option<Entry> {
+    let resource_id_len = u32::from_be_bytes(*take(bytes, 4)?.first_chunk()?) as usize;
+    let resource_id = take(bytes, resource_id_len)?.to_vec();
+    let version = u64::from_be_bytes(*take(bytes, 8)?.first_chunk()?);
+    let data_len = u32::from_be_bytes(*take(bytes, 4)?.first_chunk()?) as usize;
+    let data = take(bytes, data_len)?.to_vec();
+    Some(Entry { resource_id, version, data })
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let (taken, rest) = bytes.split_at_checked(len)?;
+    *bytes = rest;
+    Some(taken)
+}
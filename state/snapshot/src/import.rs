@@ -0,0 +1,88 @@
+use std::io::Read;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_latest::StatePtrLatest;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree::{EMPTY_HASH, StateTree};
+use vprogs_state_version::{StateVersion, VersionEncoding};
+use vprogs_storage_types::Store;
+
+use crate::{
+    SnapshotError, SnapshotResult,
+    format::{SnapshotHeader, read_chunk},
+};
+
+/// Loads a snapshot produced by [`export`](crate::export) into an empty store.
+///
+/// The data is stored with the given encoding, which should match the one the scheduler on the
+/// store is configured with. Every chunk is committed on its own while the state tree is rebuilt
+/// alongside. Only once the
+/// rebuilt tree matches the state root of the snapshot are the root and the batch index recorded,
+/// after which a `Scheduler` opened on the store continues with the next batch. Batches up to the
+/// snapshot's batch index count as pruned and can not be rolled back.
+///
+/// A failed import leaves the store partially filled, so it has to be discarded.
+pub fn import<R, S, Rd>(
+    store: &S,
+    reader: Rd,
+    encoding: VersionEncoding,
+) -> SnapshotResult<SnapshotHeader>
+where
+    R: ResourceId,
+    S: Store<StateSpace = StateSpace>,
+    Rd: Read,
+{
+    if StateMetadata::last_committed_index(store) != 0
+        || StatePtrLatest::iter(store).next().is_some()
+    {
+        return Err(SnapshotError::StoreNotEmpty);
+    }
+
+    let mut reader = reader;
+    let header = SnapshotHeader::read(&mut reader)?;
+
+    let mut state_root = EMPTY_HASH;
+    let mut chunk_index = 0;
+    while let Some(entries) = read_chunk(&mut reader, chunk_index)? {
+        let resource_ids: Vec<R> =
+            entries.iter().map(|entry| R::from_bytes(&entry.resource_id)).collect();
+        let states: Vec<_> = (resource_ids.iter().zip(entries))
+            .map(|(id, entry)| StateVersion::new(id.clone(), entry.version, entry.data))
+            .collect();
+
+        let mut write_batch = store.write_batch();
+        for (resource_id, state) in resource_ids.iter().zip(&states) {
+            state.write_data(&mut write_batch, &StateVersion::empty(resource_id.clone()), encoding);
+            state.write_latest_ptr(&mut write_batch);
+            state.write_history_ptr(&mut write_batch, header.batch_index);
+        }
+        StateVersion::<R>::update_content_refs(
+            store,
+            &mut write_batch,
+            states.iter().filter_map(|state| Some((state.written_content()?, 1))),
+        );
+        state_root = StateTree::apply(
+            store,
+            &mut write_batch,
+            state_root,
+            (resource_ids.iter().zip(&states))
+                .map(|(id, state)| (id, state.version(), state.data().as_slice())),
+        );
+        store.commit(write_batch);
+
+        chunk_index += 1;
+    }
+
+    if state_root != header.state_root {
+        return Err(SnapshotError::StateRootMismatch);
+    }
+
+    let mut write_batch = store.write_batch();
+    StateTree::put_root(&mut write_batch, header.batch_index, &state_root);
+    StateMetadata::set_pruned_index(&mut write_batch, header.batch_index);
+    StateMetadata::set_last_committed_index(&mut write_batch, header.batch_index);
+    store.commit(write_batch);
+
+    Ok(header)
+}
@@ -0,0 +1,9 @@
+mod error;
+mod export;
+mod format;
+mod import;
+
+pub use error::{SnapshotError, SnapshotResult};
+pub use export::export;
+pub use format::{DEFAULT_CHUNK_SIZE, FORMAT_VERSION, SnapshotHeader};
+pub use import::import;
@@ -1,6 +1,14 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum StateSpace {
     StateVersion,
+    StateVersionChunk,
+    StateContent,
     StatePtrLatest,
     StatePtrRollback,
+    StatePtrHistory,
+    StateTreeNode,
+    StateTreeRoot,
+    StateTreeKey,
+    StateIndex,
     Metadata,
 }
@@ -0,0 +1,86 @@
+/// A 32-byte blake3 hash.
+pub type Hash = [u8; 32];
+
+/// The hash of an empty subtree.
+pub const EMPTY_HASH: Hash = [0; 32];
+
+/// Domain tag of leaf nodes.
+pub const LEAF_TAG: u8 = 0;
+
+/// Domain tag of internal nodes.
+pub const INTERNAL_TAG: u8 = 1;
+
+/// The size of the chunks that leaves commit to the data of a resource through.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns the position of a resource in the tree.
+pub fn key_hash(resource_id_bytes: &[u8]) -> Hash {
+    blake3::hash(resource_id_bytes).into()
+}
+
+/// Returns the leaf value committing to a version of a resource.
+pub fn value_hash(version: u64, data: &[u8]) -> Hash {
+    versioned_value_hash(version, &data_hash(data))
+}
+
+/// Returns the leaf value committing to a version of a resource, given the [`data_hash`] of its
+/// data.
+pub fn versioned_value_hash(version: u64, data_hash: &Hash) -> Hash {
+    blake3::Hasher::new().update(&version.to_be_bytes()).update(data_hash).finalize().into()
+}
+
+/// Returns the hash committing to the data of a resource.
+///
+/// The data is committed through the blake3 hashes of its [`CHUNK_SIZE`] chunks, so that a value
+/// that is stored as chunks can be committed from their hashes without loading them.
+pub fn data_hash(data: &[u8]) -> Hash {
+    chunked_data_hash(data.len(), data.chunks(CHUNK_SIZE).map(|chunk| blake3::hash(chunk).into()))
+}
+
+/// Returns the [`data_hash`] of `len` bytes of data, given the hashes of its chunks.
+pub fn chunked_data_hash(len: usize, chunk_hashes: impl IntoIterator<Item = Hash>) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&(len as u64).to_be_bytes());
+    for chunk_hash in chunk_hashes {
+        hasher.update(&chunk_hash);
+    }
+    hasher.finalize().into()
+}
+
+/// Returns the hash of a leaf node.
+pub fn leaf_hash(key: &Hash, value: &Hash) -> Hash {
+    node_hash(LEAF_TAG, key, value)
+}
+
+/// Returns the hash of an internal node.
+pub fn internal_hash(left: &Hash, right: &Hash) -> Hash {
+    node_hash(INTERNAL_TAG, left, right)
+}
+
+/// Returns the hash of the subtree at `depth` that holds exactly the given `(key, value)` leaves.
+///
+/// The leaves must be sorted by key without duplicates, and share the first `depth` bits of their
+/// keys. This allows verifying a complete subtree, e.g. a chunk received during state sync,
+/// against its hash.
+pub fn subtree_hash(depth: usize, leaves: &[(Hash, Hash)]) -> Hash {
+    match leaves {
+        [] => EMPTY_HASH,
+        [(key, value)] => leaf_hash(key, value),
+        _ => {
+            let split = leaves.partition_point(|(key, _)| !goes_right(key, depth));
+            let left = subtree_hash(depth + 1, &leaves[..split]);
+            let right = subtree_hash(depth + 1, &leaves[split..]);
+            internal_hash(&left, &right)
+        }
+    }
+}
+
+/// Returns whether the path to `key` turns right at the given depth.
+pub fn goes_right(key: &Hash, depth: usize) -> bool {
+    (key[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+/// Hashes the encoding `tag || first || second` shared by all nodes.
+fn node_hash(tag: u8, first: &Hash, second: &Hash) -> Hash {
+    blake3::Hasher::new().update(&[tag]).update(first).update(second).finalize().into()
+}
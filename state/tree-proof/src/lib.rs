@@ -0,0 +1,12 @@
+#![no_std]
+
+extern crate alloc;
+
+mod hash;
+mod proof;
+
+pub use hash::{
+    CHUNK_SIZE, EMPTY_HASH, Hash, INTERNAL_TAG, LEAF_TAG, chunked_data_hash, data_hash, goes_right,
+    internal_hash, key_hash, leaf_hash, subtree_hash, value_hash, versioned_value_hash,
+};
+pub use proof::Proof;
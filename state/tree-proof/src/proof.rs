@@ -0,0 +1,102 @@
+use alloc::vec::Vec;
+
+use crate::{EMPTY_HASH, Hash, goes_right, internal_hash, leaf_hash};
+
+/// Proves the value of a key, or its absence, against a state root.
+///
+/// The proof consists of the sibling hashes along the path to the key, ordered from the root
+/// downwards, and the node the path ends in: either the key's own leaf, a leaf of another key that
+/// shares the path, or an empty subtree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Proof {
+    siblings: Vec<Hash>,
+    leaf: Option<(Hash, Hash)>,
+}
+
+impl Proof {
+    /// Creates a proof from the siblings along the path and the leaf the path ends in.
+    pub fn new(siblings: Vec<Hash>, leaf: Option<(Hash, Hash)>) -> Self {
+        Self { siblings, leaf }
+    }
+
+    /// Returns the sibling hashes along the path, ordered from the root downwards.
+    pub fn siblings(&self) -> &[Hash] {
+        &self.siblings
+    }
+
+    /// Returns the `(key, value)` of the leaf the path ends in, if it does not end in an empty
+    /// subtree.
+    pub fn leaf(&self) -> Option<&(Hash, Hash)> {
+        self.leaf.as_ref()
+    }
+
+    /// Returns the value the proof shows for `key`, or `None` if it proves its absence.
+    pub fn value(&self, key: &Hash) -> Option<&Hash> {
+        self.leaf.as_ref().filter(|(leaf_key, _)| leaf_key == key).map(|(_, value)| value)
+    }
+
+    /// Verifies that `key` maps to `value` under `root`, or that it is absent if `value` is
+    /// `None`.
+    pub fn verify(&self, root: &Hash, key: &Hash, value: Option<&Hash>) -> bool {
+        if self.siblings.len() >= 256 || self.value(key) != value {
+            return false;
+        }
+
+        let mut hash = match &self.leaf {
+            None => EMPTY_HASH,
+            Some((leaf_key, leaf_value)) => {
+                // A leaf of another key can only end the path if it shares the path.
+                let depth = self.siblings.len();
+                if (0..depth).any(|d| goes_right(leaf_key, d) != goes_right(key, d)) {
+                    return false;
+                }
+                leaf_hash(leaf_key, leaf_value)
+            }
+        };
+
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = match goes_right(key, depth) {
+                true => internal_hash(sibling, &hash),
+                false => internal_hash(&hash, sibling),
+            };
+        }
+
+        hash == *root
+    }
+
+    /// Encodes the proof as `sibling_count u16 || siblings || leaf_flag u8 || [key || value]`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(3 + 32 * (self.siblings.len() + 2));
+        bytes.extend_from_slice(&(self.siblings.len() as u16).to_be_bytes());
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling);
+        }
+        match &self.leaf {
+            None => bytes.push(0),
+            Some((key, value)) => {
+                bytes.push(1);
+                bytes.extend_from_slice(key);
+                bytes.extend_from_slice(value);
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a proof produced by [`Proof::to_bytes`], returning `None` for malformed input.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (count, rest) = bytes.split_first_chunk::<2>()?;
+        let count = u16::from_be_bytes(*count) as usize;
+        let (siblings, rest) = rest.split_at_checked(count.checked_mul(32)?)?;
+        let siblings = siblings.chunks_exact(32).map(|s| s.try_into().unwrap()).collect();
+
+        let leaf = match rest {
+            [0] => None,
+            [1, leaf @ ..] if leaf.len() == 64 => {
+                Some((leaf[..32].try_into().unwrap(), leaf[32..].try_into().unwrap()))
+            }
+            _ => return None,
+        };
+
+        Some(Self { siblings, leaf })
+    }
+}
@@ -0,0 +1,10 @@
+mod node;
+mod refs;
+mod tree;
+mod updater;
+
+pub use node::Node;
+pub use tree::StateTree;
+pub use vprogs_state_tree_proof::{
+    EMPTY_HASH, Hash, Proof, data_hash, key_hash, value_hash, versioned_value_hash,
+};
@@ -0,0 +1,51 @@
+use vprogs_state_tree_proof::{Hash, INTERNAL_TAG, LEAF_TAG, internal_hash, leaf_hash};
+
+pub(crate) const NODE_LEN: usize = 1 + 2 * size_of::<Hash>();
+
+/// A node of the sparse Merkle tree.
+///
+/// Subtrees that contain a single leaf are represented by the leaf itself, at whatever depth the
+/// path to it stops being shared with other keys. Empty subtrees hash to
+/// [`EMPTY_HASH`](crate::EMPTY_HASH) and are never stored. Together this makes the tree canonical:
+/// its root only depends on its contents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Node {
+    Leaf { key: Hash, value: Hash },
+    Internal { left: Hash, right: Hash },
+}
+
+impl Node {
+    /// Returns the hash of the node, under which it is stored.
+    pub fn hash(&self) -> Hash {
+        match self {
+            Node::Leaf { key, value } => leaf_hash(key, value),
+            Node::Internal { left, right } => internal_hash(left, right),
+        }
+    }
+
+    /// Encodes the node as `tag || left_or_key || right_or_value`.
+    pub fn to_bytes(&self) -> [u8; NODE_LEN] {
+        let (tag, first, second) = match self {
+            Node::Leaf { key, value } => (LEAF_TAG, key, value),
+            Node::Internal { left, right } => (INTERNAL_TAG, left, right),
+        };
+
+        let mut bytes = [0; NODE_LEN];
+        bytes[0] = tag;
+        bytes[1..33].copy_from_slice(first);
+        bytes[33..].copy_from_slice(second);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(bytes.len(), NODE_LEN, "invalid tree node length");
+        let first = bytes[1..33].try_into().unwrap();
+        let second = bytes[33..].try_into().unwrap();
+
+        match bytes[0] {
+            LEAF_TAG => Node::Leaf { key: first, value: second },
+            INTERNAL_TAG => Node::Internal { left: first, right: second },
+            tag => panic!("invalid tree node tag {tag}"),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use vprogs_state_space::StateSpace;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+use crate::{EMPTY_HASH, Hash, Node, StateTree};
+
+/// A node touched by [`NodeRefs`], with its stored reference count and the pending change to it.
+struct Entry {
+    node: Node,
+    stored: u64,
+    delta: i64,
+}
+
+impl Entry {
+    fn ref_count(&self) -> i64 {
+        self.stored as i64 + self.delta
+    }
+}
+
+/// Collects the nodes created and the references taken and released by a single tree operation,
+/// and writes them to a write batch at once.
+///
+/// Every stored node counts the internal nodes and recorded roots that reference it. Counts are
+/// read from the store, so all changes of a write batch must go through a single instance, and the
+/// changes of earlier operations must have been committed. Nodes that end up without references
+/// are deleted, releasing their children in turn.
+pub(crate) struct NodeRefs<'a, S> {
+    store: &'a S,
+    entries: HashMap<Hash, Entry>,
+}
+
+impl<'a, S> NodeRefs<'a, S>
+where
+    S: ReadStore<StateSpace = StateSpace>,
+{
+    pub(crate) fn new(store: &'a S) -> Self {
+        Self { store, entries: HashMap::new() }
+    }
+
+    /// Gets a node by its hash, including the nodes created by this operation.
+    pub(crate) fn node(&mut self, hash: &Hash) -> Option<Node> {
+        match *hash {
+            EMPTY_HASH => None,
+            hash => Some(self.entry(&hash).node),
+        }
+    }
+
+    /// Creates a node and returns its hash.
+    ///
+    /// A new internal node takes references on its children. Nodes that already exist hold these
+    /// references and are reused.
+    pub(crate) fn create(&mut self, node: Node) -> Hash {
+        let hash = node.hash();
+        if self.entries.contains_key(&hash) {
+            return hash;
+        }
+
+        match StateTree::stored_node(self.store, &hash) {
+            Some((node, stored)) => {
+                self.entries.insert(hash, Entry { node, stored, delta: 0 });
+            }
+            None => {
+                self.entries.insert(hash, Entry { node, stored: 0, delta: 0 });
+                if let Node::Internal { left, right } = node {
+                    self.retain(&left);
+                    self.retain(&right);
+                }
+            }
+        }
+        hash
+    }
+
+    /// Takes a reference on a node.
+    pub(crate) fn retain(&mut self, hash: &Hash) {
+        if *hash != EMPTY_HASH {
+            self.entry(hash).delta += 1;
+        }
+    }
+
+    /// Releases a reference on a node.
+    pub(crate) fn release(&mut self, hash: &Hash) {
+        if *hash != EMPTY_HASH {
+            self.entry(hash).delta -= 1;
+        }
+    }
+
+    /// Writes the changed nodes and deletes the ones without references.
+    ///
+    /// # Panics
+    /// Panics if more references are released than a node has.
+    pub(crate) fn write<W>(mut self, write_batch: &mut W)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        // Unreferenced nodes release their children, which may leave those unreferenced in turn.
+        let mut unreferenced: Vec<Hash> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.ref_count() == 0)
+            .map(|(hash, _)| *hash)
+            .collect();
+        while let Some(hash) = unreferenced.pop() {
+            if let Node::Internal { left, right } = self.entries[&hash].node {
+                for child in [left, right].into_iter().filter(|child| *child != EMPTY_HASH) {
+                    self.release(&child);
+                    if self.entries[&child].ref_count() == 0 {
+                        unreferenced.push(child);
+                    }
+                }
+            }
+        }
+
+        for (hash, entry) in &self.entries {
+            let ref_count = entry.ref_count();
+            assert!(ref_count >= 0, "released an unreferenced tree node {hash:?}");
+            match ref_count {
+                0 if entry.stored != 0 => StateTree::delete_node(write_batch, hash),
+                0 => {}
+                ref_count if entry.delta != 0 => {
+                    StateTree::write_node(write_batch, &entry.node, ref_count as u64);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn entry(&mut self, hash: &Hash) -> &mut Entry {
+        let store = self.store;
+        self.entries.entry(*hash).or_insert_with(|| match StateTree::stored_node(store, hash) {
+            Some((node, stored)) => Entry { node, stored, delta: 0 },
+            None => panic!("missing tree node {hash:?}"),
+        })
+    }
+}
@@ -0,0 +1,296 @@
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree_proof::goes_right;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+use crate::{
+    EMPTY_HASH, Hash, Node, Proof, data_hash, key_hash, node::NODE_LEN, refs::NodeRefs,
+    updater::Updater, versioned_value_hash,
+};
+
+/// Provides type-safe operations for the sparse Merkle tree committing to the latest version of
+/// every resource.
+///
+/// Nodes are content-addressed in the TreeNode column family and shared between batches. The root
+/// after every batch is kept in the TreeRoot column family, and the TreeKey column family maps the
+/// keys of leaves back to their resources.
+///
+/// Every node counts the internal nodes and recorded roots that reference it, and is deleted along
+/// with the references it holds once the count drops to zero. Deleting the roots of reverted and
+/// pruned batches therefore removes the nodes that no remaining root shares.
+///
+/// TreeNode key layout: `node_hash`, value layout: `node.to_bytes() || ref_count.to_be_bytes()`
+/// (see [`Node::to_bytes`])
+/// TreeRoot key layout: `batch_index.to_be_bytes()`, value layout: `root_hash`
+/// TreeKey key layout: `key_hash`, value layout: `resource_id.to_bytes()`
+pub struct StateTree;
+
+impl StateTree {
+    /// Gets the state root after the given batch, or `None` if the batch was not committed.
+    ///
+    /// Batch 0 denotes the empty state before the first batch.
+    pub fn root<S>(store: &S, batch_index: u64) -> Option<Hash>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        if batch_index == 0 {
+            return Some(EMPTY_HASH);
+        }
+
+        store
+            .get(StateSpace::StateTreeRoot, &batch_index.to_be_bytes())
+            .map(|bytes| bytes[..32].try_into().unwrap())
+    }
+
+    /// Deletes the state roots of the given batches and the nodes that only they referenced.
+    ///
+    /// The changes of earlier tree operations must have been committed, as the remaining
+    /// references are counted in `store`.
+    pub fn delete_roots<S, W>(
+        store: &S,
+        write_batch: &mut W,
+        batch_indices: impl IntoIterator<Item = u64>,
+    ) where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let mut refs = NodeRefs::new(store);
+        for batch_index in batch_indices {
+            if let Some(root) = store.get(StateSpace::StateTreeRoot, &batch_index.to_be_bytes()) {
+                write_batch.delete(StateSpace::StateTreeRoot, &batch_index.to_be_bytes());
+                refs.release(&root[..32].try_into().unwrap());
+            }
+        }
+        refs.write(write_batch);
+    }
+
+    /// Gets a node by its hash.
+    pub fn node<S>(store: &S, hash: &Hash) -> Option<Node>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::stored_node(store, hash).map(|(node, _)| node)
+    }
+
+    /// Stores a node that is held by a single reference and returns its hash, without taking
+    /// references on its children.
+    ///
+    /// This allows storing a tree top-down, e.g. when syncing state: every node is held by its
+    /// parent, which is stored first, the subtrees added with [`build`](Self::build) are held the
+    /// same way, and the root is held by the batch it is recorded for with
+    /// [`put_root`](Self::put_root).
+    pub fn put_node<W>(store: &mut W, node: &Node) -> Hash
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        Self::write_node(store, node, 1);
+        node.hash()
+    }
+
+    /// Gets a node and its reference count by the node's hash.
+    pub(crate) fn stored_node<S>(store: &S, hash: &Hash) -> Option<(Node, u64)>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.get(StateSpace::StateTreeNode, hash).map(|bytes| {
+            let Some(ref_count) = bytes.get(NODE_LEN..).and_then(|bytes| bytes.try_into().ok())
+            else {
+                panic!("invalid tree node length {}", bytes.len());
+            };
+            (Node::from_bytes(&bytes[..NODE_LEN]), u64::from_be_bytes(ref_count))
+        })
+    }
+
+    /// Stores a node with the given reference count under its hash.
+    pub(crate) fn write_node<W>(store: &mut W, node: &Node, ref_count: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let value = [&node.to_bytes()[..], &ref_count.to_be_bytes()].concat();
+        store.put(StateSpace::StateTreeNode, &node.hash(), &value);
+    }
+
+    /// Deletes the node with the given hash.
+    pub(crate) fn delete_node<W>(store: &mut W, hash: &Hash)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.delete(StateSpace::StateTreeNode, hash);
+    }
+
+    /// Gets the encoded resource ID of the leaf with the given key.
+    pub fn resource_id<S>(store: &S, key: &Hash) -> Option<Vec<u8>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.get(StateSpace::StateTreeKey, key)
+    }
+
+    /// Creates a proof of the resource's latest version as of the given batch, or of its absence.
+    ///
+    /// Returns `None` if the batch was not committed. The proof is verified against the batch's
+    /// root using the key `key_hash(resource_id.to_bytes())` and the value
+    /// `value_hash(version, data)`.
+    pub fn prove<S, R>(store: &S, batch_index: u64, resource_id: &R) -> Option<Proof>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        R: ResourceId,
+    {
+        let key = key_hash(&resource_id.to_bytes());
+        let mut siblings = Vec::new();
+        let mut hash = Self::root(store, batch_index)?;
+        while hash != EMPTY_HASH {
+            let Some(node) = Self::node(store, &hash) else {
+                panic!("missing tree node {hash:?}");
+            };
+
+            match node {
+                Node::Leaf { key: leaf_key, value } => {
+                    return Some(Proof::new(siblings, Some((leaf_key, value))));
+                }
+                Node::Internal { left, right } => {
+                    let (next, sibling) = match goes_right(&key, siblings.len()) {
+                        true => (right, left),
+                        false => (left, right),
+                    };
+                    siblings.push(sibling);
+                    hash = next;
+                }
+            }
+        }
+
+        Some(Proof::new(siblings, None))
+    }
+
+    /// Applies the changes of a batch to the tree of the previous batch and stores the new nodes
+    /// and the resulting root.
+    ///
+    /// Each change is a `(resource_id, version, data_hash)` triple describing the new latest state
+    /// of a resource by the [`data_hash`](crate::data_hash) of its data, so that large values need
+    /// not be loaded. `None` removes a deleted resource from the tree.
+    ///
+    /// The changes of earlier tree operations must have been committed, as the references to the
+    /// nodes are counted in `store`.
+    ///
+    /// # Panics
+    /// Panics if the root of the previous batch is unknown.
+    pub fn update<'a, S, W, R>(
+        store: &S,
+        write_batch: &mut W,
+        batch_index: u64,
+        changes: impl IntoIterator<Item = (&'a R, u64, Option<Hash>)>,
+    ) -> Hash
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId + 'a,
+    {
+        let Some(previous_root) = Self::root(store, batch_index - 1) else {
+            panic!("missing state root of batch {}", batch_index - 1);
+        };
+
+        let mut refs = NodeRefs::new(store);
+        let root = Self::apply_at(&mut refs, write_batch, previous_root, 0, changes);
+        refs.retain(&root);
+        refs.write(write_batch);
+
+        Self::put_root(write_batch, batch_index, &root);
+        root
+    }
+
+    /// Applies changes to the tree with the given root and stores the new nodes, without recording
+    /// the resulting root for any batch.
+    ///
+    /// This allows building a tree in several steps, e.g. when importing state. The caller holds a
+    /// reference on `root`, which moves to the returned root, and eventually passes it on to
+    /// [`put_root`](Self::put_root). The changes of earlier tree operations must have been
+    /// committed to `store`.
+    pub fn apply<'a, S, W, R>(
+        store: &S,
+        write_batch: &mut W,
+        root: Hash,
+        changes: impl IntoIterator<Item = (&'a R, u64, &'a [u8])>,
+    ) -> Hash
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId + 'a,
+    {
+        let changes =
+            changes.into_iter().map(|(id, version, data)| (id, version, Some(data_hash(data))));
+
+        let mut refs = NodeRefs::new(store);
+        let new_root = Self::apply_at(&mut refs, write_batch, root, 0, changes);
+        refs.retain(&new_root);
+        refs.release(&root);
+        refs.write(write_batch);
+        new_root
+    }
+
+    /// Builds the subtree at `depth` that holds exactly the given resources and stores its nodes.
+    ///
+    /// All resources must share the first `depth` bits of their keys, and no resource may appear
+    /// twice. The root of the subtree is held by a single reference like the nodes stored with
+    /// [`put_node`](Self::put_node).
+    pub fn build<'a, S, W, R>(
+        store: &S,
+        write_batch: &mut W,
+        depth: usize,
+        changes: impl IntoIterator<Item = (&'a R, u64, &'a [u8])>,
+    ) -> Hash
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId + 'a,
+    {
+        let changes =
+            changes.into_iter().map(|(id, version, data)| (id, version, Some(data_hash(data))));
+
+        let mut refs = NodeRefs::new(store);
+        let root = Self::apply_at(&mut refs, write_batch, EMPTY_HASH, depth, changes);
+        refs.retain(&root);
+        refs.write(write_batch);
+        root
+    }
+
+    fn apply_at<'a, S, W, R>(
+        refs: &mut NodeRefs<'_, S>,
+        write_batch: &mut W,
+        root: Hash,
+        depth: usize,
+        changes: impl IntoIterator<Item = (&'a R, u64, Option<Hash>)>,
+    ) -> Hash
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+        R: ResourceId + 'a,
+    {
+        // Removals are passed to the updater as leaves with an empty value.
+        let mut updates: Vec<(Hash, Hash)> = changes
+            .into_iter()
+            .map(|(id, version, data_hash)| {
+                let resource_id_bytes = id.to_bytes();
+                let key = key_hash(&resource_id_bytes);
+                match data_hash {
+                    Some(data_hash) => {
+                        write_batch.put(StateSpace::StateTreeKey, &key, &resource_id_bytes);
+                        (key, versioned_value_hash(version, &data_hash))
+                    }
+                    None => (key, EMPTY_HASH),
+                }
+            })
+            .collect();
+        updates.sort_unstable_by_key(|(key, _)| *key);
+
+        Updater::new(refs).update(root, depth, &updates)
+    }
+
+    /// Records the state root of a batch, which takes over a reference on the root that the caller
+    /// holds (see [`apply`](Self::apply) and [`put_node`](Self::put_node)).
+    pub fn put_root<W>(store: &mut W, batch_index: u64, root: &Hash)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.put(StateSpace::StateTreeRoot, &batch_index.to_be_bytes(), root);
+    }
+}
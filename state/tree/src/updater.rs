@@ -0,0 +1,91 @@
+use vprogs_state_space::StateSpace;
+use vprogs_state_tree_proof::goes_right;
+use vprogs_storage_types::ReadStore;
+
+use crate::{EMPTY_HASH, Hash, Node, refs::NodeRefs};
+
+/// Applies a sorted set of leaf updates to a tree in a single pass.
+///
+/// Updates with an [`EMPTY_HASH`] value remove the leaf with their key.
+///
+/// Nodes created during the update are collected in the [`NodeRefs`] of the operation, as they are
+/// not visible in the store until the batch is committed.
+pub(crate) struct Updater<'a, 'b, S> {
+    refs: &'a mut NodeRefs<'b, S>,
+}
+
+impl<'a, 'b, S> Updater<'a, 'b, S>
+where
+    S: ReadStore<StateSpace = StateSpace>,
+{
+    pub(crate) fn new(refs: &'a mut NodeRefs<'b, S>) -> Self {
+        Self { refs }
+    }
+
+    /// Applies `updates`, which are sorted by key and all share the path to `subtree`, and returns
+    /// the hash of the resulting subtree.
+    pub(crate) fn update(&mut self, subtree: Hash, depth: usize, updates: &[(Hash, Hash)]) -> Hash {
+        if updates.is_empty() {
+            return subtree;
+        }
+
+        match self.node(&subtree) {
+            None => self.build(depth, &Self::without_removals(updates.to_vec())),
+            Some(Node::Leaf { key, value }) => {
+                // Push the existing leaf down along with the updates, unless it is replaced.
+                let mut merged = updates.to_vec();
+                if let Err(pos) = updates.binary_search_by_key(&key, |(key, _)| *key) {
+                    merged.insert(pos, (key, value));
+                }
+                self.build(depth, &Self::without_removals(merged))
+            }
+            Some(Node::Internal { left, right }) => {
+                let split = updates.partition_point(|(key, _)| !goes_right(key, depth));
+                let left = self.update(left, depth + 1, &updates[..split]);
+                let right = self.update(right, depth + 1, &updates[split..]);
+                self.internal(left, right)
+            }
+        }
+    }
+
+    /// Drops the leaves that are removed.
+    fn without_removals(mut leaves: Vec<(Hash, Hash)>) -> Vec<(Hash, Hash)> {
+        leaves.retain(|(_, value)| *value != EMPTY_HASH);
+        leaves
+    }
+
+    /// Builds a new subtree holding exactly the given leaves.
+    fn build(&mut self, depth: usize, leaves: &[(Hash, Hash)]) -> Hash {
+        match leaves {
+            [] => EMPTY_HASH,
+            [(key, value)] => self.put(Node::Leaf { key: *key, value: *value }),
+            _ => {
+                let split = leaves.partition_point(|(key, _)| !goes_right(key, depth));
+                let left = self.build(depth + 1, &leaves[..split]);
+                let right = self.build(depth + 1, &leaves[split..]);
+                self.internal(left, right)
+            }
+        }
+    }
+
+    /// Joins two subtrees, lifting a leaf that has no sibling to keep the tree canonical.
+    fn internal(&mut self, left: Hash, right: Hash) -> Hash {
+        match (left, right) {
+            (EMPTY_HASH, EMPTY_HASH) => EMPTY_HASH,
+            (EMPTY_HASH, single) | (single, EMPTY_HASH) if self.is_leaf(&single) => single,
+            _ => self.put(Node::Internal { left, right }),
+        }
+    }
+
+    fn put(&mut self, node: Node) -> Hash {
+        self.refs.create(node)
+    }
+
+    fn node(&mut self, hash: &Hash) -> Option<Node> {
+        self.refs.node(hash)
+    }
+
+    fn is_leaf(&mut self, hash: &Hash) -> bool {
+        matches!(self.node(hash), Some(Node::Leaf { .. }))
+    }
+}
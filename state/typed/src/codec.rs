@@ -0,0 +1,26 @@
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Converts values to and from the bytes stored in a resource.
+pub trait Codec<T> {
+    fn encode(value: &T) -> Vec<u8>;
+
+    /// Decodes a value, returning an error if the bytes are not a valid encoding.
+    fn decode(bytes: &[u8]) -> io::Result<T>;
+}
+
+/// Stores values in their Borsh serialization.
+///
+/// Decoding fails if the bytes are not consumed completely.
+pub struct BorshCodec;
+
+impl<T: BorshSerialize + BorshDeserialize> Codec<T> for BorshCodec {
+    fn encode(value: &T) -> Vec<u8> {
+        borsh::to_vec(value).expect("serializing into a vec does not fail")
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<T> {
+        borsh::from_slice(bytes)
+    }
+}
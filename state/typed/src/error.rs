@@ -0,0 +1,40 @@
+use std::{fmt, io};
+
+use vprogs_state_version::CorruptionError;
+
+/// Errors that can occur while reading typed state.
+#[derive(Debug)]
+pub enum TypedStateError {
+    /// The stored data does not match its checksum.
+    CorruptedState(CorruptionError),
+    /// The history of the batch with the given index has been pruned.
+    Pruned(u64),
+    /// The stored data is not a valid encoding of the value type.
+    Decode(io::Error),
+}
+
+impl From<CorruptionError> for TypedStateError {
+    fn from(err: CorruptionError) -> Self {
+        TypedStateError::CorruptedState(err)
+    }
+}
+
+impl From<io::Error> for TypedStateError {
+    fn from(err: io::Error) -> Self {
+        TypedStateError::Decode(err)
+    }
+}
+
+impl fmt::Display for TypedStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedStateError::CorruptedState(err) => write!(f, "can not read {err}"),
+            TypedStateError::Pruned(i) => write!(f, "batch {i} has been pruned"),
+            TypedStateError::Decode(err) => write!(f, "can not decode state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TypedStateError {}
+
+pub type TypedStateResult<T> = Result<T, TypedStateError>;
@@ -0,0 +1,7 @@
+mod codec;
+mod error;
+mod typed_state;
+
+pub use codec::{BorshCodec, Codec};
+pub use error::{TypedStateError, TypedStateResult};
+pub use typed_state::{TypedResource, TypedState};
@@ -0,0 +1,87 @@
+use std::marker::PhantomData;
+
+use vprogs_core_types::ResourceId;
+use vprogs_state_space::StateSpace;
+use vprogs_state_version::StateVersion;
+use vprogs_storage_types::{ReadStore, WriteBatch};
+
+use crate::{Codec, TypedStateError, TypedStateResult};
+
+/// Binds the resources identified by a [`ResourceId`] type to the type of value they hold and
+/// the codec that stores it.
+pub trait TypedResource {
+    type ResourceId: ResourceId;
+    type Value;
+    type Codec: Codec<Self::Value>;
+}
+
+/// Reads and writes the state of typed resources.
+///
+/// Resources that do not exist, because they were never created or were deleted, have no value.
+pub struct TypedState<T: TypedResource>(PhantomData<T>);
+
+impl<T: TypedResource> TypedState<T> {
+    /// Returns the latest value of a resource.
+    pub fn latest<S>(store: &S, resource_id: &T::ResourceId) -> TypedStateResult<Option<T::Value>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::value(&StateVersion::from_latest_data(store, resource_id.clone())?)
+    }
+
+    /// Returns the value of a resource as it was after the batch with the given index.
+    pub fn historical<S>(
+        store: &S,
+        resource_id: &T::ResourceId,
+        batch_index: u64,
+    ) -> TypedStateResult<Option<T::Value>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        match StateVersion::from_historical_data(store, resource_id.clone(), batch_index)? {
+            Some(state) => Self::value(&state),
+            None => Err(TypedStateError::Pruned(batch_index)),
+        }
+    }
+
+    /// Returns the value stored for a specific version of a resource.
+    pub fn get<S>(
+        store: &S,
+        version: u64,
+        resource_id: &T::ResourceId,
+    ) -> TypedStateResult<Option<T::Value>>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        if StateVersion::is_tombstone(store, version, resource_id) {
+            return Ok(None);
+        }
+        match StateVersion::get(store, version, resource_id)? {
+            Some(bytes) => Ok(Some(T::Codec::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the value for a specific version of a resource (see [`StateVersion::put`]).
+    pub fn put<W>(store: &mut W, version: u64, resource_id: &T::ResourceId, value: &T::Value)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        StateVersion::put(store, version, resource_id, &T::Codec::encode(value))
+    }
+
+    /// Decodes the value of a loaded version.
+    ///
+    /// # Panics
+    /// Panics if the data is chunked and not all chunks have been loaded (see
+    /// [`StateVersion::load`]).
+    pub fn value(state: &StateVersion<T::ResourceId>) -> TypedStateResult<Option<T::Value>> {
+        if let Some(err) = state.corruption() {
+            return Err(err.clone().into());
+        }
+        match state.version() == 0 || state.is_deleted() {
+            true => Ok(None),
+            false => Ok(Some(T::Codec::decode(state.data())?)),
+        }
+    }
+}
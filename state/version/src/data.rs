@@ -0,0 +1,180 @@
+use std::{
+    ops::Range,
+    sync::{Arc, OnceLock},
+};
+
+/// The size of the chunks that large values are split into.
+///
+/// Values up to this size are stored as a single value, larger ones as a manifest and chunks. This
+/// is the chunk size the state tree commits to data through, so that chunked values can be
+/// committed from their manifest.
+pub use vprogs_state_tree_proof::CHUNK_SIZE;
+
+/// Where a chunk of a large value is stored.
+///
+/// Chunks are keyed by the version that first stored them, so that later versions can reference
+/// unchanged chunks instead of storing them again.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ChunkRef {
+    /// The version that stored the chunk.
+    pub owner: u64,
+    /// The hash of the chunk's data.
+    pub hash: [u8; 32],
+}
+
+/// The data of a version.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum VersionData {
+    /// Small values and values that were modified through the byte API.
+    Bytes(Vec<u8>),
+    /// Large values, held as fixed-size chunks that are loaded on first access and shared with
+    /// other versions until they are modified.
+    Chunks(Chunks),
+    /// The tombstone of a deleted resource.
+    Deleted,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Chunks {
+    pub(crate) len: usize,
+    pub(crate) slots: Vec<Arc<Chunk>>,
+    /// A contiguous copy of all chunks, created on first use of the byte API.
+    pub(crate) bytes: OnceLock<Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Chunk {
+    /// Where the chunk is stored, once it is.
+    pub(crate) stored: OnceLock<ChunkRef>,
+    /// The chunk's data, once it is loaded.
+    pub(crate) data: OnceLock<Vec<u8>>,
+}
+
+impl VersionData {
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            VersionData::Bytes(bytes) => bytes.len(),
+            VersionData::Chunks(chunks) => chunks.len,
+            VersionData::Deleted => 0,
+        }
+    }
+
+    /// Returns whether both hold the same bytes.
+    ///
+    /// Chunks that are shared or stored at the same place are equal without comparing them, and
+    /// chunks that are not loaded are considered different.
+    pub(crate) fn same_content(&self, other: &Self) -> bool {
+        match (self, other) {
+            (VersionData::Bytes(a), VersionData::Bytes(b)) => a == b,
+            (VersionData::Deleted, _) | (_, VersionData::Deleted) => {
+                matches!((self, other), (VersionData::Deleted, VersionData::Deleted))
+            }
+            _ if self.len() != other.len() => false,
+            _ => (0..self.len().div_ceil(CHUNK_SIZE)).all(|index| {
+                if let (VersionData::Chunks(a), VersionData::Chunks(b)) = (self, other) {
+                    let (a, b) = (&a.slots[index], &b.slots[index]);
+                    if Arc::ptr_eq(a, b)
+                        || a.stored.get().is_some_and(|r| b.stored.get() == Some(r))
+                    {
+                        return true;
+                    }
+                }
+                matches!(
+                    (self.loaded_chunk(index), other.loaded_chunk(index)),
+                    (Some(a), Some(b)) if a == b
+                )
+            }),
+        }
+    }
+
+    /// Returns the chunk at `index` if it is loaded.
+    fn loaded_chunk(&self, index: usize) -> Option<&[u8]> {
+        match self {
+            VersionData::Bytes(bytes) => Some(&bytes[chunk_range(index, bytes.len())]),
+            VersionData::Chunks(chunks) => chunks.slots[index].data.get().map(Vec::as_slice),
+            VersionData::Deleted => None,
+        }
+    }
+}
+
+impl Chunks {
+    /// Creates the chunks of a stored value, none of which is loaded yet.
+    pub(crate) fn stored(len: usize, refs: Vec<ChunkRef>) -> Self {
+        let slots = refs
+            .into_iter()
+            .map(|chunk_ref| Arc::new(Chunk { stored: chunk_ref.into(), data: OnceLock::new() }))
+            .collect();
+        Self { len, slots, bytes: OnceLock::new() }
+    }
+
+    /// Returns the length of the chunk at `index`.
+    pub(crate) fn chunk_len(&self, index: usize) -> usize {
+        chunk_range(index, self.len).len()
+    }
+
+    /// Returns all chunks as contiguous bytes.
+    ///
+    /// # Panics
+    /// Panics if a chunk has not been loaded.
+    pub(crate) fn bytes(&self) -> &Vec<u8> {
+        self.bytes.get_or_init(|| {
+            let mut bytes = Vec::with_capacity(self.len);
+            for chunk in &self.slots {
+                bytes.extend_from_slice(chunk.data.get().expect("chunk must be loaded"));
+            }
+            bytes
+        })
+    }
+
+    /// Converts the chunks into contiguous bytes.
+    ///
+    /// # Panics
+    /// Panics if a chunk has not been loaded.
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes();
+        self.bytes.into_inner().expect("bytes were just created")
+    }
+
+    /// Returns the chunk at `index` for modification, detaching it from other versions.
+    ///
+    /// # Panics
+    /// Panics if the chunk has not been loaded.
+    pub(crate) fn chunk_mut(&mut self, index: usize) -> &mut Vec<u8> {
+        self.bytes = OnceLock::new();
+        let chunk = Arc::make_mut(&mut self.slots[index]);
+        chunk.stored = OnceLock::new();
+        chunk.data.get_mut().expect("chunk must be loaded")
+    }
+}
+
+/// Returns the range of the chunk at `index` within a value of `len` bytes.
+pub(crate) fn chunk_range(index: usize, len: usize) -> Range<usize> {
+    index * CHUNK_SIZE..((index + 1) * CHUNK_SIZE).min(len)
+}
+
+impl Clone for Chunks {
+    /// Shares all chunks with the clone, but not the contiguous copy.
+    fn clone(&self) -> Self {
+        Self { len: self.len, slots: self.slots.clone(), bytes: OnceLock::new() }
+    }
+}
+
+impl PartialEq for Chunks {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.slots.iter().zip(&other.slots).all(|(a, b)| {
+                Arc::ptr_eq(a, b)
+                    || a.stored.get().is_some_and(|r| b.stored.get() == Some(r))
+                    || a.data.get().is_some_and(|data| b.data.get() == Some(data))
+            })
+    }
+}
+
+impl Eq for Chunks {}
+
+impl Clone for Chunk {
+    /// Copies the data of the chunk, which is about to be modified and therefore not stored.
+    fn clone(&self) -> Self {
+        Self { stored: OnceLock::new(), data: self.data.clone() }
+    }
+}
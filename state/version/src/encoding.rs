@@ -0,0 +1,174 @@
+use vprogs_storage_manager::concat_bytes;
+
+use crate::ChunkRef;
+
+/// How new versions are stored by the scheduler.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum VersionEncoding {
+    /// Every version is stored in full.
+    #[default]
+    Full,
+    /// Versions are stored as deltas against the version they replaced.
+    ///
+    /// A version is stored in full whenever it starts a new interval of `full_interval` versions,
+    /// which bounds the number of deltas that have to be applied to load any version.
+    Delta { full_interval: u64 },
+    /// The data of versions is stored once per distinct content, keyed by its hash, and every
+    /// version holding it references it. Large values are stored as chunks regardless.
+    ContentAddressed,
+}
+
+const CHECKSUM_LEN: usize = blake3::OUT_LEN;
+const FULL: u8 = 0;
+const DELTA: u8 = 1;
+const CHUNKED: u8 = 2;
+const TOMBSTONE: u8 = 3;
+const CONTENT: u8 = 4;
+const DELTA_HEADER_LEN: usize = 3 * size_of::<u64>();
+const CHUNK_REF_LEN: usize = size_of::<u64>() + blake3::OUT_LEN;
+
+/// The decoded value of a stored version.
+///
+/// Value layout: `blake3(data) || tag || payload`, where the payload is either the data itself or
+/// `base_version.to_be_bytes() || prefix_len.to_be_bytes() || suffix_len.to_be_bytes() || middle`.
+///
+/// Large values are stored as a manifest of their chunks instead, which is checksummed itself:
+/// `blake3(manifest) || tag || manifest` with the manifest being
+/// `len.to_be_bytes() || (owner.to_be_bytes() || hash)*`.
+///
+/// The versions that delete a resource are stored as tombstones without a payload, and the
+/// versions whose data is stored by content hash have no payload either, as their checksum is the
+/// hash of the content.
+pub(crate) struct StoredValue<'a> {
+    pub(crate) checksum: &'a [u8],
+    pub(crate) data: StoredData<'a>,
+}
+
+pub(crate) enum StoredData<'a> {
+    Full(&'a [u8]),
+    Delta { base_version: u64, delta: Delta<'a> },
+    Chunked { len: usize, refs: Vec<ChunkRef> },
+    Tombstone,
+    Content,
+}
+
+/// Replaces everything between a common prefix and suffix of the base with `middle`.
+pub(crate) struct Delta<'a> {
+    prefix_len: usize,
+    suffix_len: usize,
+    middle: &'a [u8],
+}
+
+impl<'a> StoredValue<'a> {
+    /// Decodes a stored value, returning `None` if it is malformed or, for a manifest, does not
+    /// match its checksum.
+    pub(crate) fn decode(value: &'a [u8]) -> Option<Self> {
+        let (checksum, rest) = value.split_at_checked(CHECKSUM_LEN)?;
+        let (&tag, payload) = rest.split_first()?;
+        let data = match tag {
+            FULL => StoredData::Full(payload),
+            DELTA => {
+                let (header, middle) = payload.split_at_checked(DELTA_HEADER_LEN)?;
+                let read_u64 = |i: usize| u64::from_be_bytes(header[i..i + 8].try_into().unwrap());
+                StoredData::Delta {
+                    base_version: read_u64(0),
+                    delta: Delta {
+                        prefix_len: usize::try_from(read_u64(8)).ok()?,
+                        suffix_len: usize::try_from(read_u64(16)).ok()?,
+                        middle,
+                    },
+                }
+            }
+            CHUNKED => {
+                let (len, refs) = payload.split_at_checked(size_of::<u64>())?;
+                if blake3::hash(payload) != *checksum || refs.len() % CHUNK_REF_LEN != 0 {
+                    return None;
+                }
+                let refs = refs.chunks_exact(CHUNK_REF_LEN).map(|chunk_ref| {
+                    let (owner, hash) = chunk_ref.split_at(size_of::<u64>());
+                    ChunkRef {
+                        owner: u64::from_be_bytes(owner.try_into().unwrap()),
+                        hash: hash.try_into().unwrap(),
+                    }
+                });
+                StoredData::Chunked {
+                    len: usize::try_from(u64::from_be_bytes(len.try_into().unwrap())).ok()?,
+                    refs: refs.collect(),
+                }
+            }
+            TOMBSTONE if payload.is_empty() => StoredData::Tombstone,
+            CONTENT if payload.is_empty() => StoredData::Content,
+            _ => return None,
+        };
+        Some(Self { checksum, data })
+    }
+
+    /// Encodes data in full.
+    pub(crate) fn encode_full(data: &[u8]) -> Vec<u8> {
+        concat_bytes!(blake3::hash(data).as_bytes(), &[FULL], data)
+    }
+
+    /// Encodes data as a delta against the data of `base_version`.
+    ///
+    /// Returns `None` if the delta is not smaller than the data itself.
+    pub(crate) fn encode_delta(data: &[u8], base_version: u64, base: &[u8]) -> Option<Vec<u8>> {
+        let prefix_len = data.iter().zip(base).take_while(|(a, b)| a == b).count();
+        let max_suffix_len = data.len().min(base.len()) - prefix_len;
+        let suffix_len = data
+            .iter()
+            .rev()
+            .zip(base.iter().rev())
+            .take(max_suffix_len)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let middle = &data[prefix_len..data.len() - suffix_len];
+        (middle.len() + DELTA_HEADER_LEN < data.len()).then(|| {
+            concat_bytes!(
+                blake3::hash(data).as_bytes(),
+                &[DELTA],
+                &base_version.to_be_bytes(),
+                &(prefix_len as u64).to_be_bytes(),
+                &(suffix_len as u64).to_be_bytes(),
+                middle,
+            )
+        })
+    }
+
+    /// Encodes the manifest of a large value of `len` bytes, stored as the given chunks.
+    pub(crate) fn encode_chunked(len: usize, refs: &[ChunkRef]) -> Vec<u8> {
+        let mut manifest = Vec::with_capacity(size_of::<u64>() + refs.len() * CHUNK_REF_LEN);
+        manifest.extend_from_slice(&(len as u64).to_be_bytes());
+        for chunk_ref in refs {
+            manifest.extend_from_slice(&chunk_ref.owner.to_be_bytes());
+            manifest.extend_from_slice(&chunk_ref.hash);
+        }
+        concat_bytes!(blake3::hash(&manifest).as_bytes(), &[CHUNKED], &manifest)
+    }
+
+    /// Encodes the tombstone of a deleted resource.
+    pub(crate) fn encode_tombstone() -> Vec<u8> {
+        concat_bytes!(blake3::hash(&[]).as_bytes(), &[TOMBSTONE])
+    }
+
+    /// Encodes a reference to data that is stored by its hash.
+    pub(crate) fn encode_content(hash: &[u8; 32]) -> Vec<u8> {
+        concat_bytes!(hash, &[CONTENT])
+    }
+
+    /// Returns whether `data` matches the checksum of the value.
+    pub(crate) fn matches(&self, data: &[u8]) -> bool {
+        blake3::hash(data) == *self.checksum
+    }
+}
+
+impl Delta<'_> {
+    /// Applies the delta to the data of its base, returning `None` if it does not fit the base.
+    pub(crate) fn apply(&self, base: &[u8]) -> Option<Vec<u8>> {
+        let suffix_start = base.len().checked_sub(self.suffix_len)?;
+        if self.prefix_len > suffix_start {
+            return None;
+        }
+        Some(concat_bytes!(&base[..self.prefix_len], self.middle, &base[suffix_start..]))
+    }
+}
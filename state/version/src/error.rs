@@ -0,0 +1,19 @@
+use std::fmt;
+
+/// The stored data of a version does not match its checksum.
+///
+/// The resource ID is kept in its encoded form, so that the error can also be reported for
+/// versions whose resource ID is not known to be valid.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CorruptionError {
+    pub resource_id: Vec<u8>,
+    pub version: u64,
+}
+
+impl fmt::Display for CorruptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "corrupted data for resource_{:?}@v{}", self.resource_id, self.version)
+    }
+}
+
+impl std::error::Error for CorruptionError {}
@@ -1,62 +1,405 @@
-use std::sync::Arc;
+mod data;
+mod encoding;
+mod error;
+
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, OnceLock},
+};
 
-use tap::Tap;
 use vprogs_core_types::ResourceId;
+use vprogs_state_metadata::StateMetadata;
+use vprogs_state_ptr_history::StatePtrHistory;
 use vprogs_state_ptr_latest::StatePtrLatest;
 use vprogs_state_ptr_rollback::StatePtrRollback;
 use vprogs_state_space::StateSpace;
+use vprogs_state_tree_proof::{chunked_data_hash, data_hash};
 use vprogs_storage_manager::concat_bytes;
 use vprogs_storage_types::{ReadStore, WriteBatch};
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+pub use crate::{
+    data::{CHUNK_SIZE, ChunkRef},
+    encoding::VersionEncoding,
+    error::CorruptionError,
+};
+use crate::{
+    data::{Chunk, Chunks, VersionData, chunk_range},
+    encoding::{StoredData, StoredValue},
+};
+
+/// The data of deleted resources.
+static NO_DATA: Vec<u8> = Vec::new();
+
+/// A version of a resource's state.
+///
+/// Values larger than [`CHUNK_SIZE`] are stored as chunks, which are loaded on first access and
+/// shared with the version they were copied from until they are modified. Deleting a resource
+/// creates a tombstone version without data, after which the resource can be re-created.
+#[derive(Debug)]
 pub struct StateVersion<R: ResourceId> {
     resource_id: R,
     version: u64,
-    data: Vec<u8>,
+    data: VersionData,
+    corruption: Option<CorruptionError>,
+    /// Where [`Self::write_data`] stored the chunks of a large value held as bytes, so that the
+    /// next version can share them.
+    written_refs: OnceLock<Vec<ChunkRef>>,
+    /// The hash of the content [`Self::write_data`] stored the version as, whose reference is
+    /// counted once the version is committed.
+    written_content: OnceLock<[u8; 32]>,
 }
 
 impl<R: ResourceId> StateVersion<R> {
+    /// Suffix of the key under which the references to content are counted.
+    const CONTENT_REF_COUNT: &[u8] = b"refs";
+
     pub fn empty(id: R) -> Self {
-        Self { resource_id: id, version: 0, data: Vec::new() }
+        Self {
+            resource_id: id,
+            version: 0,
+            data: VersionData::Bytes(Vec::new()),
+            corruption: None,
+            written_refs: OnceLock::new(),
+            written_content: OnceLock::new(),
+        }
+    }
+
+    /// Creates a version of a resource holding the given data, e.g. to store state received from a
+    /// snapshot or a peer through [`Self::write_data`].
+    pub fn new(id: R, version: u64, data: Vec<u8>) -> Self {
+        Self {
+            resource_id: id,
+            version,
+            data: VersionData::Bytes(data),
+            corruption: None,
+            written_refs: OnceLock::new(),
+            written_content: OnceLock::new(),
+        }
+    }
+
+    /// Creates a placeholder for a version whose stored data failed verification.
+    ///
+    /// The placeholder holds no data and reports the error through [`Self::corruption`], so that
+    /// the reads depending on it can fail instead of executing on wrong data.
+    pub fn corrupted(id: R, error: CorruptionError) -> Self {
+        Self {
+            resource_id: id,
+            version: error.version,
+            data: VersionData::Bytes(Vec::new()),
+            corruption: Some(error),
+            written_refs: OnceLock::new(),
+            written_content: OnceLock::new(),
+        }
+    }
+
+    /// Loads the latest state of a resource.
+    ///
+    /// Returns an error if the stored data does not match its checksum.
+    pub fn from_latest_data<S>(store: &S, id: R) -> Result<Self, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let state = Self::from_latest_data_lazy(store, id)?;
+        state.load(store)?;
+        Ok(state)
     }
 
-    pub fn from_latest_data<S>(store: &S, id: R) -> Self
+    /// Loads the latest state of a resource without loading the chunks of a large value.
+    ///
+    /// The chunks are loaded on first access through [`Self::chunk`] or [`Self::load`].
+    pub fn from_latest_data_lazy<S>(store: &S, id: R) -> Result<Self, CorruptionError>
     where
         S: ReadStore<StateSpace = StateSpace>,
     {
-        match StatePtrLatest::get(store, &id) {
+        Ok(match StatePtrLatest::get(store, &id) {
             None => Self::empty(id),
-            Some(version) => match Self::get(store, version, &id) {
+            Some(version) => match Self::get_data(store, version, &id.to_bytes())? {
                 None => panic!("missing data for resource_{:?}@v{:?}", id, version),
-                Some(data) => Self { resource_id: id, version, data },
+                Some(data) => Self {
+                    resource_id: id,
+                    version,
+                    data,
+                    corruption: None,
+                    written_refs: OnceLock::new(),
+                    written_content: OnceLock::new(),
+                },
             },
+        })
+    }
+
+    /// Loads the state of a resource as it was after the batch with the given index.
+    ///
+    /// Returns `None` if the batch has been pruned, as its history is no longer complete, or has not
+    /// been committed, as its history pointers are written before the commit. Returns an error if
+    /// the stored data does not match its checksum.
+    pub fn from_historical_data<S>(
+        store: &S,
+        id: R,
+        batch_index: u64,
+    ) -> Result<Option<Self>, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        if batch_index < StateMetadata::pruned_index(store)
+            || batch_index > StateMetadata::last_committed_index(store)
+        {
+            return Ok(None);
         }
+
+        let state = match StatePtrHistory::find_at(store, &id, batch_index) {
+            None => Self::empty(id),
+            Some((_, version)) => match Self::get_data(store, version, &id.to_bytes())? {
+                None => panic!("missing data for resource_{:?}@v{:?}", id, version),
+                Some(data) => Self {
+                    resource_id: id,
+                    version,
+                    data,
+                    corruption: None,
+                    written_refs: OnceLock::new(),
+                    written_content: OnceLock::new(),
+                },
+            },
+        };
+        state.load(store)?;
+        Ok(Some(state))
     }
 
     pub fn version(&self) -> u64 {
         self.version
     }
 
+    /// Returns the data of the version.
+    ///
+    /// # Panics
+    /// Panics if the data is chunked and not all chunks have been loaded (see [`Self::load`]).
     pub fn data(&self) -> &Vec<u8> {
-        &self.data
+        match &self.data {
+            VersionData::Bytes(bytes) => bytes,
+            VersionData::Chunks(chunks) => chunks.bytes(),
+            VersionData::Deleted => &NO_DATA,
+        }
+    }
+
+    /// Returns whether the version is the tombstone of a deleted resource.
+    pub fn is_deleted(&self) -> bool {
+        matches!(self.data, VersionData::Deleted)
+    }
+
+    /// Returns whether two versions of a resource hold the same state, i.e. whether both exist
+    /// with the same data or neither exists.
+    ///
+    /// Corrupted versions never hold the same state as another version, and chunks that are not
+    /// loaded are considered different.
+    pub fn has_same_state(&self, other: &Self) -> bool {
+        let exists = |state: &Self| state.version != 0 && !state.is_deleted();
+        self.corruption.is_none()
+            && other.corruption.is_none()
+            && exists(self) == exists(other)
+            && (!exists(self) || self.data.same_content(&other.data))
+    }
+
+    /// Deletes the resource by turning this into a new tombstone version.
+    ///
+    /// Resources that do not exist are left unchanged. Modifying the data of a tombstone
+    /// re-creates the resource with empty data.
+    pub fn mark_deleted(self: &mut Arc<Self>) {
+        if self.version != 0 && !self.is_deleted() {
+            self.new_version().data = VersionData::Deleted;
+        }
+    }
+
+    /// Returns the hash the state tree commits to the data through (see
+    /// [`data_hash`](vprogs_state_tree_proof::data_hash)).
+    ///
+    /// Chunks of a large value are committed through the hashes recorded for them, so they are
+    /// neither loaded nor copied.
+    pub fn data_hash(&self) -> [u8; 32] {
+        match &self.data {
+            VersionData::Bytes(bytes) => data_hash(bytes),
+            VersionData::Chunks(chunks) => chunked_data_hash(
+                chunks.len,
+                chunks.slots.iter().map(|chunk| match chunk.stored.get() {
+                    Some(chunk_ref) => chunk_ref.hash,
+                    None => {
+                        blake3::hash(chunk.data.get().expect("modified chunks are loaded")).into()
+                    }
+                }),
+            ),
+            VersionData::Deleted => data_hash(&[]),
+        }
     }
 
+    /// Returns the error of a version whose stored data failed verification.
+    pub fn corruption(&self) -> Option<&CorruptionError> {
+        self.corruption.as_ref()
+    }
+
+    /// Returns the data of a new version, which replaces any corrupted data.
+    ///
+    /// # Panics
+    /// Panics if the data is chunked and not all chunks have been loaded (see [`Self::load`]).
     pub fn data_mut(self: &mut Arc<Self>) -> &mut Vec<u8> {
-        &mut Arc::make_mut(self).tap_mut(|s| s.version += 1).data
+        let state = self.new_version();
+        if let VersionData::Chunks(chunks) = &mut state.data {
+            state.data = VersionData::Bytes(mem::take(chunks).into_bytes());
+        }
+        match &mut state.data {
+            VersionData::Bytes(bytes) => bytes,
+            VersionData::Chunks(_) => unreachable!("chunks were converted to bytes"),
+            VersionData::Deleted => unreachable!("new versions are not deleted"),
+        }
+    }
+
+    /// Replaces the data of a new version without loading the current data.
+    pub fn set_data(self: &mut Arc<Self>, data: Vec<u8>) {
+        self.new_version().data = VersionData::Bytes(data);
+    }
+
+    /// Returns the length of the data.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns whether the data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of [`CHUNK_SIZE`] chunks the data consists of.
+    pub fn chunk_count(&self) -> usize {
+        self.len().div_ceil(CHUNK_SIZE)
+    }
+
+    /// Loads all chunks that have not been loaded yet.
+    ///
+    /// Returns an error if a chunk does not match the hash recorded for it.
+    pub fn load<S>(&self, store: &S) -> Result<(), CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        (0..self.chunk_count()).try_for_each(|index| self.chunk(store, index).map(drop))
+    }
+
+    /// Returns the chunk at `index`, loading it if necessary.
+    ///
+    /// Returns an error if the chunk does not match the hash recorded for it.
+    pub fn chunk<S>(&self, store: &S, index: usize) -> Result<&[u8], CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        match &self.data {
+            VersionData::Bytes(bytes) => Ok(&bytes[chunk_range(index, bytes.len())]),
+            VersionData::Deleted => Ok(&NO_DATA[chunk_range(index, 0)]),
+            VersionData::Chunks(chunks) => Self::load_chunk(
+                store,
+                self.version,
+                &self.resource_id.to_bytes(),
+                index,
+                &chunks.slots[index],
+                chunks.chunk_len(index),
+            ),
+        }
+    }
+
+    /// Returns the chunk at `index` of a new version, copying only that chunk if it is shared.
+    ///
+    /// Returns an error if the chunk does not match the hash recorded for it.
+    pub fn chunk_mut<S>(
+        self: &mut Arc<Self>,
+        store: &S,
+        index: usize,
+    ) -> Result<&mut [u8], CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        self.chunk(store, index)?;
+        Ok(match &mut self.new_version().data {
+            VersionData::Bytes(bytes) => {
+                let range = chunk_range(index, bytes.len());
+                &mut bytes[range]
+            }
+            VersionData::Chunks(chunks) => chunks.chunk_mut(index),
+            VersionData::Deleted => unreachable!("new versions are not deleted"),
+        })
     }
 
-    pub fn write_data<W>(&self, store: &mut W)
+    /// Persists the data of a new version, which replaced `base`.
+    ///
+    /// Large values are stored as chunks, of which those that are unchanged since `base` are
+    /// referenced instead of stored again. Other values are stored as a delta against `base` or
+    /// by content hash if `encoding` asks for it (see [`VersionEncoding`]). Deleted resources are
+    /// stored as a tombstone.
+    pub fn write_data<W>(&self, store: &mut W, base: &Self, encoding: VersionEncoding)
     where
         W: WriteBatch<StateSpace = StateSpace>,
     {
-        Self::put(store, self.version, &self.resource_id, &self.data);
+        let resource_id = self.resource_id.to_bytes();
+        let value = match &self.data {
+            VersionData::Bytes(data) if data.len() > CHUNK_SIZE => {
+                let base_refs: Vec<_> = match &base.data {
+                    VersionData::Chunks(chunks) => {
+                        chunks.slots.iter().map(|chunk| chunk.stored.get().copied()).collect()
+                    }
+                    VersionData::Bytes(_) | VersionData::Deleted => {
+                        base.written_refs.get().into_iter().flatten().copied().map(Some).collect()
+                    }
+                };
+                let refs = Self::put_chunks(store, self.version, &resource_id, data, &base_refs);
+                StoredValue::encode_chunked(data.len(), self.written_refs.get_or_init(|| refs))
+            }
+            VersionData::Bytes(data) => match encoding {
+                VersionEncoding::Full => StoredValue::encode_full(data),
+                VersionEncoding::Delta { full_interval } => {
+                    self.encode_delta(data, base, full_interval)
+                }
+                VersionEncoding::ContentAddressed => {
+                    let hash = Self::put_content(store, self.version, &resource_id, data);
+                    StoredValue::encode_content(self.written_content.get_or_init(|| hash))
+                }
+            },
+            VersionData::Chunks(chunks) => {
+                let refs: Vec<_> = (chunks.slots.iter().enumerate())
+                    .map(|(index, chunk)| match chunk.stored.get() {
+                        Some(chunk_ref) => *chunk_ref,
+                        None => {
+                            let data = chunk.data.get().expect("modified chunks are loaded");
+                            let chunk_ref =
+                                Self::put_chunk(store, self.version, &resource_id, index, data);
+                            *chunk.stored.get_or_init(|| chunk_ref)
+                        }
+                    })
+                    .collect();
+                StoredValue::encode_chunked(chunks.len, &refs)
+            }
+            VersionData::Deleted => StoredValue::encode_tombstone(),
+        };
+
+        let key = concat_bytes!(&self.version.to_be_bytes(), &resource_id);
+        store.put(StateSpace::StateVersion, &key, &value);
     }
 
     pub fn write_latest_ptr<W>(&self, store: &mut W)
     where
         W: WriteBatch<StateSpace = StateSpace>,
     {
-        StatePtrLatest::put(store, &self.resource_id, self.version);
+        match self.is_deleted() {
+            true => StatePtrLatest::put_tombstone(store, &self.resource_id, self.version),
+            false => StatePtrLatest::put(store, &self.resource_id, self.version),
+        }
+    }
+
+    /// Points the latest pointer of a resource back to a stored version, e.g. when reverting the
+    /// batch that replaced it, keeping it a tombstone if the version is one.
+    pub fn restore_latest_ptr<S, W>(store: &S, write_batch: &mut W, version: u64, resource_id: &R)
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        match Self::is_tombstone(store, version, resource_id) {
+            true => StatePtrLatest::put_tombstone(write_batch, resource_id, version),
+            false => StatePtrLatest::put(write_batch, resource_id, version),
+        }
     }
 
     pub fn write_rollback_ptr<W>(&self, store: &mut W, batch_index: u64)
@@ -66,30 +409,151 @@ impl<R: ResourceId> StateVersion<R> {
         StatePtrRollback::put(store, batch_index, &self.resource_id, self.version);
     }
 
-    /// Gets the data for a specific version of a resource.
+    pub fn write_history_ptr<W>(&self, store: &mut W, batch_index: u64)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        StatePtrHistory::put(store, &self.resource_id, batch_index, self.version);
+    }
+
+    /// Gets the data for a specific version of a resource, applying deltas and loading chunks as
+    /// needed. The data of a tombstone is empty (see [`Self::is_tombstone`]).
+    ///
+    /// Returns an error if the stored data does not match its checksum or a delta can not be
+    /// applied.
     ///
     /// Key layout: `version.to_be_bytes() || resource_id.to_bytes()`
-    pub fn get<S>(store: &S, version: u64, resource_id: &R) -> Option<Vec<u8>>
+    /// Value layout: `blake3(data) || tag || payload` (see [`VersionEncoding`])
+    pub fn get<S>(
+        store: &S,
+        version: u64,
+        resource_id: &R,
+    ) -> Result<Option<Vec<u8>>, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::get_encoded(store, version, &resource_id.to_bytes())
+    }
+
+    /// Returns whether a stored version is the tombstone of a deleted resource.
+    pub fn is_tombstone<S>(store: &S, version: u64, resource_id: &R) -> bool
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
+        store.get(StateSpace::StateVersion, &key).is_some_and(|value| {
+            matches!(
+                StoredValue::decode(&value),
+                Some(StoredValue { data: StoredData::Tombstone, .. })
+            )
+        })
+    }
+
+    /// Returns the version a stored version is a delta against, if it is stored as a delta.
+    pub fn delta_base<S>(store: &S, version: u64, resource_id: &R) -> Option<u64>
     where
         S: ReadStore<StateSpace = StateSpace>,
     {
         let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
-        store.get(StateSpace::StateVersion, &key)
+        let value = store.get(StateSpace::StateVersion, &key)?;
+        match StoredValue::decode(&value)?.data {
+            StoredData::Delta { base_version, .. } => Some(base_version),
+            StoredData::Full(_)
+            | StoredData::Chunked { .. }
+            | StoredData::Tombstone
+            | StoredData::Content => None,
+        }
     }
 
-    /// Stores data for a specific version of a resource.
+    /// Returns the hash of the content a stored version references, if its data is stored by
+    /// content hash.
+    pub fn content_hash<S>(store: &S, version: u64, resource_id: &R) -> Option<[u8; 32]>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
+        let value = store.get(StateSpace::StateVersion, &key)?;
+        match StoredValue::decode(&value)? {
+            StoredValue { checksum, data: StoredData::Content } => checksum.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns where the chunks of a stored version are, indexed by their position in the value.
+    ///
+    /// Returns an empty list if the version is not stored as chunks.
+    pub fn chunk_refs<S>(store: &S, version: u64, resource_id: &R) -> Vec<ChunkRef>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
+        let value = store.get(StateSpace::StateVersion, &key).unwrap_or_default();
+        match StoredValue::decode(&value).map(|value| value.data) {
+            Some(StoredData::Chunked { refs, .. }) => refs,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Stores the full data for a specific version of a resource, prefixed with its checksum.
+    ///
+    /// Large values are stored as chunks that are all owned by the version.
     ///
     /// Key layout: `version.to_be_bytes() || resource_id.to_bytes()`
+    /// Value layout: `blake3(data) || tag || data`
     pub fn put<W>(store: &mut W, version: u64, resource_id: &R, data: &[u8])
     where
         W: WriteBatch<StateSpace = StateSpace>,
     {
-        let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
-        store.put(StateSpace::StateVersion, &key, data);
+        let resource_id = resource_id.to_bytes();
+        let value = match data.len() > CHUNK_SIZE {
+            true => {
+                let refs = Self::put_chunks(store, version, &resource_id, data, &[]);
+                StoredValue::encode_chunked(data.len(), &refs)
+            }
+            false => StoredValue::encode_full(data),
+        };
+        let key = concat_bytes!(&version.to_be_bytes(), &resource_id);
+        store.put(StateSpace::StateVersion, &key, &value);
+    }
+
+    /// Iterates the data of all versions of all resources.
+    ///
+    /// Returns an iterator yielding `(version, resource_id_bytes, data)` triples, where `data` is
+    /// an error if it can not be loaded.
+    pub fn iter<S>(
+        store: &S,
+    ) -> impl Iterator<Item = (u64, Vec<u8>, Result<Vec<u8>, CorruptionError>)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StateVersion, &[]).map(|(key, value)| {
+            let version = u64::from_be_bytes(key[..8].try_into().unwrap());
+            let resource_id = key[8..].to_vec();
+            let data = Self::decode(store, version, &resource_id, &value)
+                .and_then(|data| Self::into_bytes(store, version, &resource_id, data));
+            (version, resource_id, data)
+        })
+    }
+
+    /// Iterates the keys of all stored chunks.
+    ///
+    /// Returns an iterator yielding `(owner, resource_id_bytes, index)` triples.
+    pub fn iter_chunks<S>(store: &S) -> impl Iterator<Item = (u64, Vec<u8>, usize)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StateVersionChunk, &[]).map(|(key, _)| {
+            let (resource_id, index) = key.split_at(key.len() - size_of::<u32>());
+            let owner = u64::from_be_bytes(resource_id[..8].try_into().unwrap());
+            let index = u32::from_be_bytes(index.try_into().unwrap()) as usize;
+            (owner, resource_id[8..].to_vec(), index)
+        })
     }
 
     /// Deletes data for a specific version of a resource.
     ///
+    /// Chunks are left in place, as later versions may still reference them.
+    ///
     /// Key layout: `version.to_be_bytes() || resource_id.to_bytes()`
     pub fn delete<W>(store: &mut W, version: u64, resource_id: &R)
     where
@@ -98,14 +562,399 @@ impl<R: ResourceId> StateVersion<R> {
         let key = concat_bytes!(&version.to_be_bytes(), &resource_id.to_bytes());
         store.delete(StateSpace::StateVersion, &key);
     }
+
+    /// Deletes data for a specific version of a resource along with the chunks it stored itself
+    /// and its reference to the content it is stored as.
+    ///
+    /// Only valid for the latest version of a resource, as no later version references its chunks.
+    /// The content itself is left in place (see [`Self::update_content_refs`]).
+    pub fn delete_with_chunks<S, W>(store: &S, write_batch: &mut W, version: u64, resource_id: &R)
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        for (index, chunk_ref) in Self::chunk_refs(store, version, resource_id).iter().enumerate() {
+            if chunk_ref.owner == version {
+                Self::delete_chunk(write_batch, version, resource_id, index);
+            }
+        }
+        if let Some(hash) = Self::content_hash(store, version, resource_id) {
+            Self::delete_content_ref(write_batch, &hash, version, resource_id);
+        }
+        Self::delete(write_batch, version, resource_id);
+    }
+
+    /// Deletes the reference of a version to the content it is stored as.
+    ///
+    /// Key layout: `hash || version.to_be_bytes() || resource_id.to_bytes()`
+    pub fn delete_content_ref<W>(store: &mut W, hash: &[u8; 32], version: u64, resource_id: &R)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(hash, &version.to_be_bytes(), &resource_id.to_bytes());
+        store.delete(StateSpace::StateContent, &key);
+    }
+
+    /// Adds the given changes to the reference counts of content and deletes the content that no
+    /// version references anymore.
+    ///
+    /// Changes are given as `(hash, delta)` pairs and may repeat a hash. A delta of 0 deletes
+    /// content that was stored without a counted reference, e.g. by a batch that is reverted
+    /// before it was committed. The counts are read from `store`, so the changes of earlier write
+    /// batches must have been committed.
+    ///
+    /// # Panics
+    /// Panics if more references are removed than the content has.
+    pub fn update_content_refs<S, W>(
+        store: &S,
+        write_batch: &mut W,
+        changes: impl IntoIterator<Item = ([u8; 32], i64)>,
+    ) where
+        S: ReadStore<StateSpace = StateSpace>,
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let mut deltas = HashMap::new();
+        for (hash, delta) in changes {
+            *deltas.entry(hash).or_insert(0) += delta;
+        }
+
+        for (hash, delta) in deltas {
+            let ref_count = Self::content_refs(store, &hash) as i64 + delta;
+            assert!(ref_count >= 0, "released unreferenced content {hash:?}");
+            match ref_count {
+                0 => Self::delete_content(write_batch, &hash),
+                ref_count => {
+                    let key = concat_bytes!(&hash, Self::CONTENT_REF_COUNT);
+                    write_batch.put(
+                        StateSpace::StateContent,
+                        &key,
+                        &(ref_count as u64).to_be_bytes(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Deletes content and its reference count regardless of the versions that still reference
+    /// it.
+    ///
+    /// Key layout: `hash` for the content and `hash || b"refs"` for its reference count
+    pub fn delete_content<W>(store: &mut W, hash: &[u8; 32])
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        store.delete(StateSpace::StateContent, hash);
+        store.delete(StateSpace::StateContent, &concat_bytes!(hash, Self::CONTENT_REF_COUNT));
+    }
+
+    /// Returns the number of committed versions that reference the content with the given hash.
+    ///
+    /// Key layout: `hash || b"refs"`
+    /// Value layout: `ref_count.to_be_bytes()`
+    pub fn content_refs<S>(store: &S, hash: &[u8; 32]) -> u64
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(hash, Self::CONTENT_REF_COUNT);
+        store
+            .get(StateSpace::StateContent, &key)
+            .map(|bytes| match bytes[..].try_into() {
+                Ok(bytes) => u64::from_be_bytes(bytes),
+                Err(_) => panic!("malformed reference count of content {hash:?}: {bytes:?}"),
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the hash of the content [`Self::write_data`] stored this version as, if any.
+    ///
+    /// The reference is counted when the version is committed (see
+    /// [`Self::update_content_refs`]).
+    pub fn written_content(&self) -> Option<[u8; 32]> {
+        self.written_content.get().copied()
+    }
+
+    /// Iterates the stored content and the references to it, skipping the reference counts.
+    ///
+    /// Returns an iterator yielding `(hash, reference)` pairs, where `reference` is `None` for the
+    /// content itself and the `(version, resource_id_bytes)` of the referencing version otherwise.
+    pub fn iter_content<S>(
+        store: &S,
+    ) -> impl Iterator<Item = ([u8; 32], Option<(u64, Vec<u8>)>)> + '_
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        store.iter_from(StateSpace::StateContent, &[]).filter_map(|(key, _)| {
+            let (hash, reference) = key.split_at(32);
+            if reference == Self::CONTENT_REF_COUNT {
+                return None;
+            }
+            let reference = (!reference.is_empty()).then(|| {
+                let (version, resource_id) = reference.split_at(size_of::<u64>());
+                (u64::from_be_bytes(version.try_into().unwrap()), resource_id.to_vec())
+            });
+            Some((hash.try_into().unwrap(), reference))
+        })
+    }
+
+    /// Deletes a single chunk.
+    ///
+    /// Key layout: `owner.to_be_bytes() || resource_id.to_bytes() || index.to_be_bytes()`
+    pub fn delete_chunk<W>(store: &mut W, owner: u64, resource_id: &R, index: usize)
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let key = Self::chunk_key(owner, &resource_id.to_bytes(), index);
+        store.delete(StateSpace::StateVersionChunk, &key);
+    }
+
+    /// Returns the data of a new version, which re-creates a deleted resource.
+    fn new_version(self: &mut Arc<Self>) -> &mut Self {
+        let state = Arc::make_mut(self);
+        state.version += 1;
+        state.corruption = None;
+        if state.is_deleted() {
+            state.data = VersionData::Bytes(Vec::new());
+        }
+        state
+    }
+
+    /// Encodes small data as a delta against `base`, falling back to the full data if it starts a
+    /// new interval of `full_interval` versions, if `base` holds no valid small value or if the
+    /// delta would not be smaller.
+    fn encode_delta(&self, data: &[u8], base: &Self, full_interval: u64) -> Vec<u8> {
+        let full_interval = full_interval.max(1);
+        let base_data = match &base.data {
+            VersionData::Bytes(base_data)
+                if base.version != 0
+                    && base.corruption.is_none()
+                    && base.version / full_interval == self.version / full_interval =>
+            {
+                Some(base_data)
+            }
+            _ => None,
+        };
+        base_data
+            .and_then(|base_data| StoredValue::encode_delta(data, base.version, base_data))
+            .unwrap_or_else(|| StoredValue::encode_full(data))
+    }
+
+    /// Stores data once by its hash, references it from `version` and returns the hash. Writing
+    /// content that is already stored only adds the reference, which is counted separately (see
+    /// [`Self::update_content_refs`]).
+    ///
+    /// Key layout: `hash` for the content and `hash || version.to_be_bytes() ||
+    /// resource_id.to_bytes()` for each reference
+    /// Value layout: `data` for the content, verified against its hash, and empty for references
+    fn put_content<W>(store: &mut W, version: u64, resource_id: &[u8], data: &[u8]) -> [u8; 32]
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let hash = *blake3::hash(data).as_bytes();
+        store.put(StateSpace::StateContent, &hash, data);
+        let key = concat_bytes!(&hash, &version.to_be_bytes(), resource_id);
+        store.put(StateSpace::StateContent, &key, &[]);
+        hash
+    }
+
+    /// Stores a large value as chunks and returns where they are stored.
+    ///
+    /// Chunks whose hash matches the stored chunk at the same position of `base` reference it
+    /// instead of being stored again.
+    fn put_chunks<W>(
+        store: &mut W,
+        version: u64,
+        resource_id: &[u8],
+        data: &[u8],
+        base: &[Option<ChunkRef>],
+    ) -> Vec<ChunkRef>
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        (data.chunks(CHUNK_SIZE).enumerate())
+            .map(|(index, chunk)| {
+                let hash = *blake3::hash(chunk).as_bytes();
+                match base.get(index).copied().flatten() {
+                    Some(base_ref) if base_ref.hash == hash => base_ref,
+                    _ => Self::put_chunk(store, version, resource_id, index, chunk),
+                }
+            })
+            .collect()
+    }
+
+    /// Stores a single chunk owned by `version`.
+    ///
+    /// Key layout: `owner.to_be_bytes() || resource_id.to_bytes() || index.to_be_bytes()`
+    /// Value layout: `data`, verified against the hash in the manifest
+    fn put_chunk<W>(
+        store: &mut W,
+        version: u64,
+        resource_id: &[u8],
+        index: usize,
+        data: &[u8],
+    ) -> ChunkRef
+    where
+        W: WriteBatch<StateSpace = StateSpace>,
+    {
+        let key = Self::chunk_key(version, resource_id, index);
+        store.put(StateSpace::StateVersionChunk, &key, data);
+        ChunkRef { owner: version, hash: *blake3::hash(data).as_bytes() }
+    }
+
+    fn chunk_key(owner: u64, resource_id: &[u8], index: usize) -> Vec<u8> {
+        concat_bytes!(&owner.to_be_bytes(), resource_id, &(index as u32).to_be_bytes())
+    }
+
+    /// Loads a chunk of `version` if it has not been loaded yet and verifies it.
+    fn load_chunk<'a, S>(
+        store: &S,
+        version: u64,
+        resource_id: &[u8],
+        index: usize,
+        chunk: &'a Chunk,
+        len: usize,
+    ) -> Result<&'a [u8], CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        if let Some(data) = chunk.data.get() {
+            return Ok(data);
+        }
+
+        let chunk_ref = chunk.stored.get().expect("chunks that are not loaded are stored");
+        let data = store
+            .get(
+                StateSpace::StateVersionChunk,
+                &Self::chunk_key(chunk_ref.owner, resource_id, index),
+            )
+            .filter(|data| data.len() == len && blake3::hash(data) == chunk_ref.hash)
+            .ok_or_else(|| CorruptionError { resource_id: resource_id.to_vec(), version })?;
+        Ok(chunk.data.get_or_init(|| data))
+    }
+
+    /// Gets the data for a specific version of an encoded resource ID, loading all chunks.
+    fn get_encoded<S>(
+        store: &S,
+        version: u64,
+        resource_id: &[u8],
+    ) -> Result<Option<Vec<u8>>, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        Self::get_data(store, version, resource_id)?
+            .map(|data| Self::into_bytes(store, version, resource_id, data))
+            .transpose()
+    }
+
+    /// Gets the data for a specific version of an encoded resource ID, without loading chunks.
+    fn get_data<S>(
+        store: &S,
+        version: u64,
+        resource_id: &[u8],
+    ) -> Result<Option<VersionData>, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let key = concat_bytes!(&version.to_be_bytes(), resource_id);
+        store
+            .get(StateSpace::StateVersion, &key)
+            .map(|value| Self::decode(store, version, resource_id, &value))
+            .transpose()
+    }
+
+    /// Loads all chunks of the data and converts it into contiguous bytes.
+    fn into_bytes<S>(
+        store: &S,
+        version: u64,
+        resource_id: &[u8],
+        data: VersionData,
+    ) -> Result<Vec<u8>, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        match data {
+            VersionData::Bytes(bytes) => Ok(bytes),
+            VersionData::Deleted => Ok(Vec::new()),
+            VersionData::Chunks(chunks) => {
+                for (index, chunk) in chunks.slots.iter().enumerate() {
+                    Self::load_chunk(
+                        store,
+                        version,
+                        resource_id,
+                        index,
+                        chunk,
+                        chunks.chunk_len(index),
+                    )?;
+                }
+                Ok(chunks.into_bytes())
+            }
+        }
+    }
+
+    /// Decodes a stored value, loading the base of a delta, and verifies the data.
+    ///
+    /// The chunks of a large value are verified once they are loaded.
+    fn decode<S>(
+        store: &S,
+        version: u64,
+        resource_id: &[u8],
+        value: &[u8],
+    ) -> Result<VersionData, CorruptionError>
+    where
+        S: ReadStore<StateSpace = StateSpace>,
+    {
+        let corrupted = || CorruptionError { resource_id: resource_id.to_vec(), version };
+
+        let value = StoredValue::decode(value).ok_or_else(corrupted)?;
+        let data = match &value.data {
+            StoredData::Full(data) => data.to_vec(),
+            StoredData::Delta { base_version, delta } => {
+                let base = Self::get_encoded(store, *base_version, resource_id)?;
+                base.and_then(|base| delta.apply(&base)).ok_or_else(corrupted)?
+            }
+            StoredData::Chunked { len, refs } => {
+                return match refs.len() == len.div_ceil(CHUNK_SIZE) {
+                    true => Ok(VersionData::Chunks(Chunks::stored(*len, refs.clone()))),
+                    false => Err(corrupted()),
+                };
+            }
+            StoredData::Content => {
+                store.get(StateSpace::StateContent, value.checksum).ok_or_else(corrupted)?
+            }
+            StoredData::Tombstone => {
+                return match value.matches(&[]) {
+                    true => Ok(VersionData::Deleted),
+                    false => Err(corrupted()),
+                };
+            }
+        };
+
+        match value.matches(&data) {
+            true => Ok(VersionData::Bytes(data)),
+            false => Err(corrupted()),
+        }
+    }
 }
 
+impl<R: ResourceId> PartialEq for StateVersion<R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.resource_id == other.resource_id
+            && self.version == other.version
+            && self.data == other.data
+            && self.corruption == other.corruption
+    }
+}
+
+impl<R: ResourceId> Eq for StateVersion<R> {}
+
 impl<R: ResourceId> Clone for StateVersion<R> {
     fn clone(&self) -> Self {
         Self {
             resource_id: self.resource_id.clone(),
             version: self.version,
             data: self.data.clone(),
+            corruption: self.corruption.clone(),
+            written_refs: OnceLock::new(),
+            written_content: OnceLock::new(),
         }
     }
 }
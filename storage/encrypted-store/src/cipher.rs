@@ -0,0 +1,138 @@
+use chacha20poly1305::{
+    AeadCore, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, Payload},
+};
+
+use crate::{
+    EncryptionConfig, EncryptionError, EncryptionLayout, EncryptionResult, KeyLayout, Keyring,
+};
+
+/// The length of a key hash.
+pub(crate) const KEY_HASH_LEN: usize = blake3::OUT_LEN;
+
+const KEY_ID_LEN: usize = size_of::<u32>();
+const NONCE_LEN: usize = 24;
+
+/// Encrypts values into envelopes of the form `key_id || nonce || ciphertext` and, if enabled,
+/// hashes keys.
+///
+/// Ciphertexts are authenticated against the state space and the key they are stored under, so
+/// values can not be moved to a different location unnoticed. With key hashing, the original key
+/// is prepended to the value before encryption so that iteration can recover it.
+pub(crate) struct Cipher {
+    keyring: Keyring,
+    key_hashing_key: Option<[u8; 32]>,
+}
+
+impl Cipher {
+    pub(crate) fn new(config: EncryptionConfig) -> Self {
+        Self {
+            keyring: config.keyring().clone(),
+            key_hashing_key: config.key_hashing_key().copied(),
+        }
+    }
+
+    pub(crate) fn hashes_keys(&self) -> bool {
+        self.key_hashing_key.is_some()
+    }
+
+    pub(crate) fn active_key_id(&self) -> u32 {
+        self.keyring.active_key_id()
+    }
+
+    /// Returns the key under which the entry for `key` is stored in the inner store.
+    pub(crate) fn stored_key<L: EncryptionLayout>(&self, ns: &L, key: &[u8]) -> Vec<u8> {
+        if !self.hashes_keys() {
+            return key.to_vec();
+        }
+
+        match ns.key_layout() {
+            KeyLayout::Plaintext => key.to_vec(),
+            KeyLayout::PlaintextPrefix(len) => {
+                [&key[..len.min(key.len())], &self.key_hash(ns.domain(), key)].concat()
+            }
+            KeyLayout::HashedPrefix(len) => {
+                let (hashed, suffix) = key.split_at(key.len().saturating_sub(len));
+                [&self.key_hash(ns.domain(), hashed), suffix].concat()
+            }
+        }
+    }
+
+    /// Hashes `bytes` with the key hashing key, bound to the given domain.
+    ///
+    /// # Panics
+    /// Panics if key hashing is disabled.
+    pub(crate) fn key_hash(&self, domain: u8, bytes: &[u8]) -> [u8; KEY_HASH_LEN] {
+        let key_hashing_key = self.key_hashing_key.as_ref().expect("key hashing is disabled");
+        *blake3::Hasher::new_keyed(key_hashing_key)
+            .update(&[domain])
+            .update(bytes)
+            .finalize()
+            .as_bytes()
+    }
+
+    pub(crate) fn encrypt(
+        &self,
+        domain: u8,
+        stored_key: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) -> Vec<u8> {
+        let payload = match self.hashes_keys() {
+            true => [&(key.len() as u32).to_be_bytes(), key, value].concat(),
+            false => value.to_vec(),
+        };
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = [&[domain], stored_key].concat();
+        let ciphertext =
+            match self.keyring.active_key().encrypt(&nonce, Payload { msg: &payload, aad: &aad }) {
+                Ok(ciphertext) => ciphertext,
+                Err(e) => panic!("value encryption failed: {e}"),
+            };
+
+        [&self.active_key_id().to_be_bytes(), nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Decrypts an envelope, returning the original key and the value.
+    pub(crate) fn decrypt(
+        &self,
+        domain: u8,
+        stored_key: &[u8],
+        envelope: &[u8],
+    ) -> EncryptionResult<(Vec<u8>, Vec<u8>)> {
+        if envelope.len() < KEY_ID_LEN + NONCE_LEN {
+            return Err(EncryptionError::Truncated);
+        }
+        let (nonce, ciphertext) = envelope[KEY_ID_LEN..].split_at(NONCE_LEN);
+
+        let key_id = Self::key_id(envelope)?;
+        let key = self.keyring.key(key_id).ok_or(EncryptionError::UnknownKey(key_id))?;
+
+        let aad = [&[domain], stored_key].concat();
+        let payload = key
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| EncryptionError::Unauthenticated)?;
+
+        match self.hashes_keys() {
+            true => {
+                let (key_len, rest) = payload
+                    .split_first_chunk::<{ size_of::<u32>() }>()
+                    .ok_or(EncryptionError::MalformedPayload)?;
+                let key_len = u32::from_be_bytes(*key_len) as usize;
+                if rest.len() < key_len {
+                    return Err(EncryptionError::MalformedPayload);
+                }
+                let (key, value) = rest.split_at(key_len);
+                Ok((key.to_vec(), value.to_vec()))
+            }
+            false => Ok((stored_key.to_vec(), payload)),
+        }
+    }
+
+    /// Returns the id of the key that an envelope was encrypted with.
+    pub(crate) fn key_id(envelope: &[u8]) -> EncryptionResult<u32> {
+        let key_id = envelope.first_chunk::<KEY_ID_LEN>().ok_or(EncryptionError::Truncated)?;
+        Ok(u32::from_be_bytes(*key_id))
+    }
+}
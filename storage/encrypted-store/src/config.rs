@@ -0,0 +1,31 @@
+use crate::Keyring;
+
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    keyring: Keyring,
+    key_hashing_key: Option<[u8; 32]>,
+}
+
+impl EncryptionConfig {
+    pub fn new(keyring: Keyring) -> Self {
+        Self { keyring, key_hashing_key: None }
+    }
+
+    /// Enables deterministic hashing of keys with the given secret.
+    ///
+    /// Only the plaintext prefix defined by the [`EncryptionLayout`](crate::EncryptionLayout) of
+    /// a state space stays readable on disk; the original key is stored inside the encrypted
+    /// value. The secret can not be rotated, as it determines where entries are stored.
+    pub fn with_key_hashing(mut self, key_hashing_key: [u8; 32]) -> Self {
+        self.key_hashing_key = Some(key_hashing_key);
+        self
+    }
+
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+
+    pub fn key_hashing_key(&self) -> Option<&[u8; 32]> {
+        self.key_hashing_key.as_ref()
+    }
+}
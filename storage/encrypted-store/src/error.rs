@@ -0,0 +1,31 @@
+use std::fmt;
+
+/// Errors that can occur while decrypting a stored value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EncryptionError {
+    /// The stored value is too short to hold an envelope.
+    Truncated,
+    /// The value is encrypted with a key that is not part of the keyring.
+    UnknownKey(u32),
+    /// The value fails authentication, e.g. because it was modified or moved to a different key.
+    Unauthenticated,
+    /// The decrypted value does not hold the original key.
+    MalformedPayload,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::Truncated => write!(f, "encrypted value is truncated"),
+            EncryptionError::UnknownKey(key_id) => {
+                write!(f, "value is encrypted with unknown key {key_id}")
+            }
+            EncryptionError::Unauthenticated => write!(f, "value fails authentication"),
+            EncryptionError::MalformedPayload => write!(f, "decrypted value is malformed"),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+pub type EncryptionResult<T> = Result<T, EncryptionError>;
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+
+/// The set of value encryption keys known to an [`EncryptedStore`](crate::EncryptedStore).
+///
+/// New values are always encrypted with the active key. Retired keys are only used to decrypt
+/// values that were written before a rotation, until they have been re-encrypted.
+#[derive(Clone)]
+pub struct Keyring {
+    active_key_id: u32,
+    keys: HashMap<u32, XChaCha20Poly1305>,
+}
+
+impl Keyring {
+    pub fn new(active_key_id: u32, active_key: [u8; 32]) -> Self {
+        Self {
+            active_key_id,
+            keys: HashMap::from([(active_key_id, XChaCha20Poly1305::new(&active_key.into()))]),
+        }
+    }
+
+    pub fn with_retired_key(mut self, key_id: u32, key: [u8; 32]) -> Self {
+        assert!(!self.keys.contains_key(&key_id), "duplicate encryption key id {key_id}");
+        self.keys.insert(key_id, XChaCha20Poly1305::new(&key.into()));
+        self
+    }
+
+    pub fn active_key_id(&self) -> u32 {
+        self.active_key_id
+    }
+
+    pub(crate) fn active_key(&self) -> &XChaCha20Poly1305 {
+        &self.keys[&self.active_key_id]
+    }
+
+    pub(crate) fn key(&self, key_id: u32) -> Option<&XChaCha20Poly1305> {
+        self.keys.get(&key_id)
+    }
+}
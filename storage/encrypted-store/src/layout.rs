@@ -0,0 +1,77 @@
+use vprogs_state_space::StateSpace;
+
+/// Describes which parts of the keys of a state space stay readable on disk when key hashing is
+/// enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyLayout {
+    /// Keys are stored in plaintext.
+    Plaintext,
+    /// The first `n` key bytes stay in plaintext and are followed by a keyed hash of the key.
+    ///
+    /// Entries that share the plaintext prefix are sorted in memory when iterated. Range deletion
+    /// is only supported on bounds that are at most `n` bytes long.
+    PlaintextPrefix(usize),
+    /// All but the last `n` key bytes are replaced by a keyed hash, followed by the last `n` bytes
+    /// in plaintext.
+    ///
+    /// Prefix iteration expects prefixes that consist of exactly the hashed part of the keys and
+    /// yields the entries that share it in order of their plaintext suffix. Iterating the whole
+    /// state space sorts it in memory. Range deletion is not supported.
+    HashedPrefix(usize),
+}
+
+/// Describes how the keys of a state space are treated by an
+/// [`EncryptedStore`](crate::EncryptedStore).
+pub trait EncryptionLayout {
+    /// A tag that is unique per state space, binding ciphertexts and key hashes to the state
+    /// space they were written to.
+    fn domain(&self) -> u8;
+
+    /// The layout of the keys when key hashing is enabled.
+    fn key_layout(&self) -> KeyLayout;
+}
+
+impl EncryptionLayout for StateSpace {
+    fn domain(&self) -> u8 {
+        match self {
+            StateSpace::StateVersion => 0,
+            StateSpace::StatePtrLatest => 1,
+            StateSpace::StatePtrRollback => 2,
+            StateSpace::Metadata => 3,
+            StateSpace::StatePtrHistory => 4,
+            StateSpace::StateTreeNode => 5,
+            StateSpace::StateTreeRoot => 6,
+            StateSpace::StateTreeKey => 7,
+            StateSpace::StateVersionChunk => 8,
+            StateSpace::StateIndex => 9,
+            StateSpace::StateContent => 10,
+        }
+    }
+
+    fn key_layout(&self) -> KeyLayout {
+        match self {
+            // Keyed by `version || resource_id`, iterated by version.
+            StateSpace::StateVersion => KeyLayout::PlaintextPrefix(size_of::<u64>()),
+            // Keyed by `owner_version || resource_id || chunk_index`, iterated by owner version.
+            StateSpace::StateVersionChunk => KeyLayout::PlaintextPrefix(size_of::<u64>()),
+            // Keyed by `hash` and `hash || version || resource_id`, iterated by hash. Content
+            // hashes therefore stay visible, which reveals which versions hold equal data.
+            StateSpace::StateContent => KeyLayout::PlaintextPrefix(32),
+            // Keyed by `batch_index || resource_id`, iterated and deleted by batch index.
+            StateSpace::StatePtrRollback => KeyLayout::PlaintextPrefix(size_of::<u64>()),
+            // Keyed by `index_id || key_len || key || resource_id`, iterated by index key and
+            // deleted by index ID.
+            StateSpace::StateIndex => KeyLayout::PlaintextPrefix(size_of::<u16>()),
+            // Keyed by `resource_id || inverted batch_index` and searched per resource from a
+            // batch index on, which only needs the batch index to stay ordered.
+            StateSpace::StatePtrHistory => KeyLayout::HashedPrefix(size_of::<u64>()),
+            // Keyed by fixed names and `b"batch_header" || batch_index`, which hold no state. They
+            // stay in plaintext so that batch headers are iterated in batch order.
+            StateSpace::Metadata => KeyLayout::Plaintext,
+            StateSpace::StatePtrLatest
+            | StateSpace::StateTreeNode
+            | StateSpace::StateTreeRoot
+            | StateSpace::StateTreeKey => KeyLayout::PlaintextPrefix(0),
+        }
+    }
+}
@@ -0,0 +1,14 @@
+mod cipher;
+mod config;
+mod error;
+mod keyring;
+mod layout;
+mod store;
+mod write_batch;
+
+pub use config::EncryptionConfig;
+pub use error::{EncryptionError, EncryptionResult};
+pub use keyring::Keyring;
+pub use layout::{EncryptionLayout, KeyLayout};
+pub use store::EncryptedStore;
+pub use write_batch::EncryptedWriteBatch;
@@ -0,0 +1,241 @@
+use std::{iter, sync::Arc};
+
+use vprogs_storage_types::{PrefixIterator, Store, WriteBatch};
+
+use crate::{
+    EncryptedWriteBatch, EncryptionConfig, EncryptionLayout, EncryptionResult, KeyLayout,
+    cipher::{Cipher, KEY_HASH_LEN},
+};
+
+/// A stored key together with the result of decrypting its entry.
+type Entry = (Vec<u8>, EncryptionResult<(Vec<u8>, Vec<u8>)>);
+
+/// A [`Store`] wrapper that transparently encrypts values at rest.
+///
+/// Values are encrypted with XChaCha20-Poly1305 under the active key of the configured
+/// [`Keyring`](crate::Keyring). Keys are stored as-is unless key hashing is enabled, in which
+/// case only the parts defined by their [`KeyLayout`] remain readable on disk.
+///
+/// Iteration yields keys in lexicographic order with key hashing as well. Entries whose order is
+/// lost on disk are sorted in memory, so iterating state spaces whose keys are hashed entirely
+/// buffers them.
+///
+/// The [`Store`] methods panic on values that can not be decrypted, as they were corrupted or
+/// tampered with. The `try_` methods return the [`EncryptionError`](crate::EncryptionError)
+/// instead.
+pub struct EncryptedStore<S: Store> {
+    inner: S,
+    cipher: Arc<Cipher>,
+}
+
+impl<S: Store<StateSpace: EncryptionLayout>> EncryptedStore<S> {
+    pub fn new(inner: S, config: EncryptionConfig) -> Self {
+        Self { inner, cipher: Arc::new(Cipher::new(config)) }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the id of the key that the value stored under `key` is encrypted with.
+    pub fn key_id(&self, ns: S::StateSpace, key: &[u8]) -> EncryptionResult<Option<u32>> {
+        let stored_key = self.cipher.stored_key(&ns, key);
+        self.inner.get(ns, &stored_key).map(|envelope| Cipher::key_id(&envelope)).transpose()
+    }
+
+    /// Gets the value stored under `key`, failing if it can not be decrypted.
+    pub fn try_get(&self, ns: S::StateSpace, key: &[u8]) -> EncryptionResult<Option<Vec<u8>>> {
+        let domain = ns.domain();
+        let stored_key = self.cipher.stored_key(&ns, key);
+        self.inner
+            .get(ns, &stored_key)
+            .map(|envelope| Ok(self.cipher.decrypt(domain, &stored_key, &envelope)?.1))
+            .transpose()
+    }
+
+    /// Iterates the entries whose keys start with `prefix` like
+    /// [`prefix_iter`](Store::prefix_iter), yielding an error for every value that can not be
+    /// decrypted.
+    pub fn try_prefix_iter(
+        &self,
+        ns: S::StateSpace,
+        prefix: &[u8],
+    ) -> impl Iterator<Item = EncryptionResult<(Vec<u8>, Vec<u8>)>> + '_ {
+        self.search(ns, prefix, prefix).map(|(_, entry)| entry)
+    }
+
+    /// Re-encrypts all values under `prefix` that are not yet encrypted with the active key and
+    /// returns their number.
+    ///
+    /// Once all state spaces have been re-encrypted, retired keys can be removed from the keyring.
+    /// This writes to the inner store directly and must not run concurrently with other writers.
+    /// Nothing is written if a value can not be decrypted.
+    pub fn reencrypt(&self, ns: S::StateSpace, prefix: &[u8]) -> EncryptionResult<usize>
+    where
+        S::StateSpace: Copy,
+    {
+        let domain = ns.domain();
+        let active_key_id = self.cipher.active_key_id();
+
+        let mut write_batch = self.inner.write_batch();
+        let mut count = 0;
+        for (stored_key, envelope) in self.search_inner(ns, prefix, prefix) {
+            if Cipher::key_id(&envelope)? == active_key_id {
+                continue;
+            }
+
+            let (key, value) = self.cipher.decrypt(domain, &stored_key, &envelope)?;
+            if key.starts_with(prefix) {
+                write_batch.put(
+                    ns,
+                    &stored_key,
+                    &self.cipher.encrypt(domain, &stored_key, &key, &value),
+                );
+                count += 1;
+            }
+        }
+        self.inner.commit(write_batch);
+
+        Ok(count)
+    }
+
+    /// Decrypts the entries whose keys start with `prefix` and are greater than or equal to
+    /// `from`, in lexicographic order of their keys.
+    fn search(
+        &self,
+        ns: S::StateSpace,
+        prefix: &[u8],
+        from: &[u8],
+    ) -> Box<dyn Iterator<Item = Entry> + '_> {
+        let domain = ns.domain();
+        let layout = self.cipher.hashes_keys().then(|| ns.key_layout());
+        let entries = self.search_inner(ns, prefix, from).map(move |(stored_key, envelope)| {
+            let entry = self.cipher.decrypt(domain, &stored_key, &envelope);
+            (stored_key, entry)
+        });
+
+        // Restore the order of the keys where the inner store only orders their plaintext parts.
+        let entries: Box<dyn Iterator<Item = Entry>> = match layout {
+            Some(KeyLayout::PlaintextPrefix(len)) => {
+                Box::new(sort_runs(entries, move |stored_key| {
+                    len.min(stored_key.len().saturating_sub(KEY_HASH_LEN))
+                }))
+            }
+            Some(KeyLayout::HashedPrefix(_)) if prefix.is_empty() => {
+                Box::new(sort_runs(entries, |_| 0))
+            }
+            _ => Box::new(entries),
+        };
+
+        let (prefix, from) = (prefix.to_vec(), from.to_vec());
+        Box::new(entries.filter(move |(_, entry)| match entry {
+            Ok((key, _)) => key.starts_with(&prefix) && *key >= from,
+            Err(_) => true,
+        }))
+    }
+
+    /// Returns the entries of the inner store that can hold the keys searched by
+    /// [`search`](Self::search).
+    fn search_inner(&self, ns: S::StateSpace, prefix: &[u8], from: &[u8]) -> PrefixIterator<'_> {
+        if !self.cipher.hashes_keys() {
+            return self.inner.prefix_iter_from(ns, prefix, from);
+        }
+
+        match ns.key_layout() {
+            KeyLayout::Plaintext => self.inner.prefix_iter_from(ns, prefix, from),
+            KeyLayout::PlaintextPrefix(len) => self.inner.prefix_iter_from(
+                ns,
+                &prefix[..len.min(prefix.len())],
+                &from[..len.min(from.len())],
+            ),
+            KeyLayout::HashedPrefix(_) if prefix.is_empty() => self.inner.iter_from(ns, &[]),
+            KeyLayout::HashedPrefix(_) => {
+                let suffix_from = match from.strip_prefix(prefix) {
+                    Some(suffix_from) => suffix_from,
+                    None if from < prefix => &[],
+                    None => return Box::new(iter::empty()),
+                };
+                let hash = self.cipher.key_hash(ns.domain(), prefix);
+                self.inner.prefix_iter_from(ns, &hash, &[&hash, suffix_from].concat())
+            }
+        }
+    }
+}
+
+impl<S: Store<StateSpace: EncryptionLayout>> Store for EncryptedStore<S> {
+    type StateSpace = S::StateSpace;
+    type WriteBatch = EncryptedWriteBatch<S::WriteBatch>;
+
+    fn get(&self, ns: Self::StateSpace, key: &[u8]) -> Option<Vec<u8>> {
+        let domain = ns.domain();
+        let stored_key = self.cipher.stored_key(&ns, key);
+        self.inner.get(ns, &stored_key).map(|envelope| {
+            let entry = self.cipher.decrypt(domain, &stored_key, &envelope);
+            valid((stored_key, entry)).1
+        })
+    }
+
+    fn write_batch(&self) -> Self::WriteBatch {
+        EncryptedWriteBatch::new(self.inner.write_batch(), self.cipher.clone())
+    }
+
+    fn commit(&self, write_batch: Self::WriteBatch) {
+        self.inner.commit(write_batch.into_inner());
+    }
+
+    fn prefix_iter(&self, ns: Self::StateSpace, prefix: &[u8]) -> PrefixIterator<'_> {
+        Box::new(self.search(ns, prefix, prefix).map(valid))
+    }
+
+    fn iter_from(&self, ns: Self::StateSpace, from: &[u8]) -> PrefixIterator<'_> {
+        Box::new(self.search(ns, &[], from).map(valid))
+    }
+
+    fn prefix_iter_from(
+        &self,
+        ns: Self::StateSpace,
+        prefix: &[u8],
+        from: &[u8],
+    ) -> PrefixIterator<'_> {
+        Box::new(self.search(ns, prefix, from).map(valid))
+    }
+}
+
+impl<S: Store + Clone> Clone for EncryptedStore<S> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), cipher: self.cipher.clone() }
+    }
+}
+
+/// Unwraps a decrypted entry.
+///
+/// # Panics
+/// Panics if the value could not be decrypted, as it was corrupted or tampered with.
+fn valid((stored_key, entry): Entry) -> (Vec<u8>, Vec<u8>) {
+    entry.unwrap_or_else(|err| panic!("invalid value stored under {stored_key:?}: {err}"))
+}
+
+/// Sorts the runs of consecutive entries whose stored keys share their first `run_len` bytes by
+/// key. Values that can not be decrypted lead their run.
+fn sort_runs<'a>(
+    entries: impl Iterator<Item = Entry> + 'a,
+    run_len: impl Fn(&[u8]) -> usize + 'a,
+) -> impl Iterator<Item = Entry> + 'a {
+    let mut entries = entries.peekable();
+    iter::from_fn(move || {
+        let first = entries.next()?;
+        let run_prefix = first.0[..run_len(&first.0)].to_vec();
+
+        let mut run = vec![first];
+        while let Some(entry) =
+            entries.next_if(|(stored_key, _)| stored_key.starts_with(&run_prefix))
+        {
+            run.push(entry);
+        }
+        run.sort_by(|(_, a), (_, b)| {
+            a.as_ref().ok().map(|(key, _)| key).cmp(&b.as_ref().ok().map(|(key, _)| key))
+        });
+        Some(run)
+    })
+    .flatten()
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use vprogs_storage_types::WriteBatch;
+
+use crate::{EncryptionLayout, KeyLayout, cipher::Cipher};
+
+/// A write batch that encrypts values (and hashes keys, if enabled) before handing them to the
+/// write batch of the inner store.
+pub struct EncryptedWriteBatch<W> {
+    inner: W,
+    cipher: Arc<Cipher>,
+}
+
+impl<W> EncryptedWriteBatch<W> {
+    pub(crate) fn new(inner: W, cipher: Arc<Cipher>) -> Self {
+        Self { inner, cipher }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: WriteBatch<StateSpace: EncryptionLayout>> WriteBatch for EncryptedWriteBatch<W> {
+    type StateSpace = W::StateSpace;
+
+    fn put(&mut self, ns: Self::StateSpace, key: &[u8], value: &[u8]) {
+        let stored_key = self.cipher.stored_key(&ns, key);
+        let envelope = self.cipher.encrypt(ns.domain(), &stored_key, key, value);
+        self.inner.put(ns, &stored_key, &envelope);
+    }
+
+    fn delete(&mut self, ns: Self::StateSpace, key: &[u8]) {
+        let stored_key = self.cipher.stored_key(&ns, key);
+        self.inner.delete(ns, &stored_key);
+    }
+
+    /// Deletes all keys in the half-open range `[from, to)`.
+    ///
+    /// # Panics
+    /// With key hashing enabled, panics if a bound is longer than the plaintext prefix of the
+    /// state space (see [`KeyLayout`]), as the order of the remaining key bytes is not preserved on
+    /// disk.
+    fn delete_range(&mut self, ns: Self::StateSpace, from: &[u8], to: &[u8]) {
+        if self.cipher.hashes_keys() {
+            check_range_bounds(&ns, &[from, to]);
+        }
+        self.inner.delete_range(ns, from, to);
+    }
+
+    /// Deletes all keys that are greater than or equal to `from`.
+    ///
+    /// # Panics
+    /// With key hashing enabled, panics if `from` is longer than the plaintext prefix of the state
+    /// space (see [`KeyLayout`]).
+    fn delete_from(&mut self, ns: Self::StateSpace, from: &[u8]) {
+        if self.cipher.hashes_keys() {
+            check_range_bounds(&ns, &[from]);
+        }
+        self.inner.delete_from(ns, from);
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
+    }
+}
+
+/// Checks that range deletion with the given bounds only depends on key bytes that stay in
+/// plaintext on disk.
+fn check_range_bounds<L: EncryptionLayout>(ns: &L, bounds: &[&[u8]]) {
+    match ns.key_layout() {
+        KeyLayout::Plaintext => {}
+        KeyLayout::PlaintextPrefix(len) => assert!(
+            bounds.iter().all(|bound| bound.len() <= len),
+            "range bounds exceed the plaintext key prefix of {len} bytes"
+        ),
+        KeyLayout::HashedPrefix(_) => panic!("range deletion is not supported on hashed keys"),
+    }
+}
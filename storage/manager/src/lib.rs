@@ -25,11 +25,13 @@ pub(crate) mod read {
 pub(crate) mod write {
     mod cmd;
     mod config;
+    mod limits;
     mod manager;
     mod worker;
 
     pub use cmd::WriteCmd;
     pub use config::WriteConfig;
+    pub use limits::WriteLimits;
     pub use manager::WriteManager;
     pub use worker::WriteWorker;
 }
@@ -37,4 +39,4 @@ pub(crate) mod write {
 pub use config::StorageConfig;
 pub use manager::StorageManager;
 pub use read::ReadCmd;
-pub use write::WriteCmd;
+pub use write::{WriteCmd, WriteConfig, WriteLimits};
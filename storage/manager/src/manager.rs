@@ -6,7 +6,9 @@ use std::sync::{
 use vprogs_core_macros::smart_pointer;
 use vprogs_storage_types::Store;
 
-use crate::{ReadCmd, StorageConfig, WriteCmd, read::ReadManager, write::WriteManager};
+use crate::{
+    ReadCmd, StorageConfig, WriteCmd, WriteLimits, read::ReadManager, write::WriteManager,
+};
 
 #[smart_pointer]
 pub struct StorageManager<S: Store, R: ReadCmd<S::StateSpace>, W: WriteCmd<S::StateSpace>> {
@@ -39,6 +41,11 @@ impl<S: Store, R: ReadCmd<S::StateSpace>, W: WriteCmd<S::StateSpace>> StorageMan
         self.writer.submit(cmd);
     }
 
+    /// Returns the batch limits the write worker currently applies.
+    pub fn write_limits(&self) -> &WriteLimits {
+        self.writer.limits()
+    }
+
     pub fn store(&self) -> &S {
         &self.store
     }
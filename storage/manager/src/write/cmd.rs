@@ -3,5 +3,9 @@ use vprogs_storage_types::Store;
 pub trait WriteCmd<T>: Send + Sync + 'static {
     fn exec<S: Store<StateSpace = T>>(&self, store: &S, batch: S::WriteBatch) -> S::WriteBatch;
 
+    /// Returns an estimate of the bytes the command adds to a write batch, which lets submitters
+    /// wake the worker once the pending commands reach the byte limit.
+    fn size_hint(&self) -> usize;
+
     fn done(self);
 }
@@ -3,7 +3,9 @@ use std::time::Duration;
 #[derive(Clone, Debug)]
 pub struct WriteConfig {
     max_batch_size: usize,
+    max_batch_bytes: usize,
     max_batch_duration: Duration,
+    target_commit_latency: Option<Duration>,
 }
 
 impl WriteConfig {
@@ -16,22 +18,49 @@ impl WriteConfig {
         self
     }
 
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: usize) -> Self {
+        self.max_batch_bytes = max_batch_bytes;
+        self
+    }
+
     pub fn with_max_batch_duration(mut self, max_batch_duration: Duration) -> Self {
         self.max_batch_duration = max_batch_duration;
         self
     }
 
+    /// Enables adaptive mode, in which the writer tunes its batch limits after every commit so
+    /// that commits take roughly `target_commit_latency`.
+    ///
+    /// The configured maximums remain upper bounds for the tuned values.
+    pub fn with_target_commit_latency(mut self, target_commit_latency: Duration) -> Self {
+        self.target_commit_latency = Some(target_commit_latency);
+        self
+    }
+
     pub fn max_batch_size(&self) -> usize {
         self.max_batch_size
     }
 
+    pub fn max_batch_bytes(&self) -> usize {
+        self.max_batch_bytes
+    }
+
     pub fn max_batch_duration(&self) -> Duration {
         self.max_batch_duration
     }
+
+    pub fn target_commit_latency(&self) -> Option<Duration> {
+        self.target_commit_latency
+    }
 }
 
 impl Default for WriteConfig {
     fn default() -> Self {
-        Self { max_batch_size: 1000, max_batch_duration: Duration::from_millis(10) }
+        Self {
+            max_batch_size: 1000,
+            max_batch_bytes: 64 << 20,
+            max_batch_duration: Duration::from_millis(10),
+            target_commit_latency: None,
+        }
     }
 }
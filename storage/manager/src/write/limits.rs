@@ -0,0 +1,107 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use vprogs_core_macros::smart_pointer;
+
+use crate::write::WriteConfig;
+
+/// Lower bound for the tuned batch duration, so that an overloaded disk can not make the writer
+/// spin on empty batches.
+const MIN_BATCH_DURATION: Duration = Duration::from_micros(100);
+
+/// Lower bound for the tuned byte limit (capped by the configured maximum).
+const MIN_BATCH_BYTES: usize = 64 << 10;
+
+/// Shrink factor applied at most once per commit, to dampen the reaction to latency spikes.
+const MAX_SHRINK: f64 = 0.5;
+
+/// Growth factor applied after commits that were considerably faster than the target.
+const GROWTH: f64 = 1.25;
+
+/// The batch limits the write worker currently applies.
+///
+/// Without a target commit latency these are the values of the [`WriteConfig`]. In adaptive mode
+/// the worker retunes them after every commit, and they reflect the currently chosen cadence.
+#[smart_pointer]
+pub struct WriteLimits {
+    batch_size: AtomicUsize,
+    batch_bytes: AtomicUsize,
+    batch_duration_nanos: AtomicU64,
+    last_commit_latency_nanos: AtomicU64,
+}
+
+impl WriteLimits {
+    pub(crate) fn new(config: &WriteConfig) -> Self {
+        Self(Arc::new(WriteLimitsData {
+            batch_size: AtomicUsize::new(config.max_batch_size()),
+            batch_bytes: AtomicUsize::new(config.max_batch_bytes()),
+            batch_duration_nanos: AtomicU64::new(config.max_batch_duration().as_nanos() as u64),
+            last_commit_latency_nanos: AtomicU64::new(0),
+        }))
+    }
+
+    /// Returns the number of commands after which a batch is committed.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes after which a batch is committed.
+    pub fn batch_bytes(&self) -> usize {
+        self.batch_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time after which a non-empty batch is committed.
+    pub fn batch_duration(&self) -> Duration {
+        Duration::from_nanos(self.batch_duration_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Returns how long the most recent commit took.
+    pub fn last_commit_latency(&self) -> Duration {
+        Duration::from_nanos(self.last_commit_latency_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Records the latency of a commit and, in adaptive mode, retunes the limits towards the
+    /// target commit latency.
+    ///
+    /// Slow commits shrink all limits proportionally to how far they overshot the target. Commits
+    /// that took less than half the target grow them again, up to the configured maximums.
+    pub(crate) fn record_commit(&self, config: &WriteConfig, latency: Duration) {
+        self.last_commit_latency_nanos.store(latency.as_nanos() as u64, Ordering::Relaxed);
+
+        let Some(target) = config.target_commit_latency() else {
+            return;
+        };
+
+        let factor = if latency > target {
+            (target.as_secs_f64() / latency.as_secs_f64()).max(MAX_SHRINK)
+        } else if latency < target / 2 {
+            GROWTH
+        } else {
+            return;
+        };
+
+        let scale = |value: usize, min: usize, max: usize| {
+            ((value as f64 * factor).ceil() as usize).clamp(min.min(max), max)
+        };
+
+        self.batch_size
+            .store(scale(self.batch_size(), 1, config.max_batch_size()), Ordering::Relaxed);
+        self.batch_bytes.store(
+            scale(self.batch_bytes(), MIN_BATCH_BYTES, config.max_batch_bytes()),
+            Ordering::Relaxed,
+        );
+        self.batch_duration_nanos.store(
+            scale(
+                self.batch_duration().as_nanos() as usize,
+                MIN_BATCH_DURATION.as_nanos() as usize,
+                config.max_batch_duration().as_nanos() as usize,
+            ) as u64,
+            Ordering::Relaxed,
+        );
+    }
+}
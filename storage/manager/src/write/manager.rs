@@ -1,19 +1,24 @@
 use std::{
     marker::PhantomData,
-    sync::{Arc, atomic::AtomicBool},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
 };
 
+use crossbeam_utils::CachePadded;
 use vprogs_storage_types::Store;
 
 use crate::{
     WriteCmd,
     utils::{CmdQueue, WorkerHandle},
-    write::{WriteConfig, WriteWorker},
+    write::{WriteConfig, WriteLimits, WriteWorker},
 };
 
 pub struct WriteManager<K: Store, W: WriteCmd<K::StateSpace>> {
-    config: WriteConfig,
-    queue: CmdQueue<W>,
+    limits: WriteLimits,
+    queue: CmdQueue<(W, usize)>,
+    pending_bytes: Arc<CachePadded<AtomicUsize>>,
     worker: WorkerHandle,
     _marker: PhantomData<K>,
 }
@@ -21,20 +26,39 @@ pub struct WriteManager<K: Store, W: WriteCmd<K::StateSpace>> {
 impl<K: Store, W: WriteCmd<K::StateSpace>> WriteManager<K, W> {
     pub fn new(config: WriteConfig, store: &Arc<K>, is_shutdown: &Arc<AtomicBool>) -> Self {
         let queue = CmdQueue::new();
+        let limits = WriteLimits::new(&config);
+        let pending_bytes = Arc::new(CachePadded::new(AtomicUsize::new(0)));
         Self {
-            worker: WriteWorker::spawn(&config, &queue, store, is_shutdown),
+            worker: WriteWorker::spawn(
+                &config,
+                &limits,
+                &queue,
+                &pending_bytes,
+                store,
+                is_shutdown,
+            ),
             queue,
-            config,
+            pending_bytes,
+            limits,
             _marker: PhantomData,
         }
     }
 
     pub fn submit(&self, write: W) {
-        if self.queue.push(write) >= self.config.max_batch_size() && self.worker.is_parked() {
+        let size_hint = write.size_hint();
+        let pending_bytes = self.pending_bytes.fetch_add(size_hint, Ordering::Relaxed) + size_hint;
+        let queue_len = self.queue.push((write, size_hint));
+        if (queue_len >= self.limits.batch_size() || pending_bytes >= self.limits.batch_bytes())
+            && self.worker.is_parked()
+        {
             self.worker.wake();
         }
     }
 
+    pub fn limits(&self) -> &WriteLimits {
+        &self.limits
+    }
+
     pub fn shutdown(&self) {
         self.worker.wake();
 
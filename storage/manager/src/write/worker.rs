@@ -1,25 +1,28 @@
 use std::{
     sync::{
         Arc,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
     thread,
     time::Instant,
 };
 
 use crossbeam_utils::{CachePadded, sync::Parker};
-use vprogs_storage_types::Store;
+use vprogs_storage_types::{Store, WriteBatch};
 
 use crate::{
     WriteCmd,
     utils::{CmdQueue, WorkerHandle},
-    write::WriteConfig,
+    write::{WriteConfig, WriteLimits},
 };
 
 pub struct WriteWorker<K: Store, W: WriteCmd<K::StateSpace>> {
     config: WriteConfig,
+    limits: WriteLimits,
     store: Arc<K>,
-    queue: CmdQueue<W>,
+    queue: CmdQueue<(W, usize)>,
+    /// The size hints of the commands that were submitted but not committed yet.
+    pending_bytes: Arc<CachePadded<AtomicUsize>>,
     is_parked: Arc<CachePadded<AtomicBool>>,
     parker: Parker,
     is_shutdown: Arc<AtomicBool>,
@@ -28,13 +31,17 @@ pub struct WriteWorker<K: Store, W: WriteCmd<K::StateSpace>> {
 impl<K: Store, W: WriteCmd<K::StateSpace>> WriteWorker<K, W> {
     pub(crate) fn spawn(
         config: &WriteConfig,
-        queue: &CmdQueue<W>,
+        limits: &WriteLimits,
+        queue: &CmdQueue<(W, usize)>,
+        pending_bytes: &Arc<CachePadded<AtomicUsize>>,
         store: &Arc<K>,
         is_shutdown: &Arc<AtomicBool>,
     ) -> WorkerHandle {
         let this = Self {
             config: config.clone(),
+            limits: limits.clone(),
             queue: queue.clone(),
+            pending_bytes: pending_bytes.clone(),
             store: store.clone(),
             is_shutdown: is_shutdown.clone(),
             parker: Parker::new(),
@@ -51,19 +58,26 @@ impl<K: Store, W: WriteCmd<K::StateSpace>> WriteWorker<K, W> {
     fn run(self) {
         let mut batch_cmds = Vec::with_capacity(self.config.max_batch_size());
         let mut write_batch = self.store.write_batch();
+        let mut batch_size_hint = 0;
         let mut created = Instant::now();
 
         while !self.is_shutdown() {
-            if !batch_cmds.is_empty() && self.should_commit(batch_cmds.len(), created) {
+            if !batch_cmds.is_empty() && self.should_commit(batch_cmds.len(), &write_batch, created)
+            {
+                let commit_start = Instant::now();
                 self.store.commit(write_batch);
+                self.limits.record_commit(&self.config, commit_start.elapsed());
                 batch_cmds.drain(..).for_each(W::done);
+                self.pending_bytes.fetch_sub(batch_size_hint, Ordering::Relaxed);
 
                 write_batch = self.store.write_batch();
+                batch_size_hint = 0;
                 created = Instant::now();
             }
 
             match self.queue.pop() {
-                (Some(cmd), _) => {
+                (Some((cmd, size_hint)), _) => {
+                    batch_size_hint += size_hint;
                     write_batch = cmd.exec(&*self.store, write_batch);
                     batch_cmds.push(cmd);
                 }
@@ -72,13 +86,20 @@ impl<K: Store, W: WriteCmd<K::StateSpace>> WriteWorker<K, W> {
         }
     }
 
-    fn should_commit(&self, batch_size: usize, created: Instant) -> bool {
-        self.batch_is_full(batch_size) || created.elapsed() >= self.config.max_batch_duration()
+    fn should_commit(
+        &self,
+        batch_size: usize,
+        write_batch: &K::WriteBatch,
+        created: Instant,
+    ) -> bool {
+        self.batch_is_full(batch_size, write_batch)
+            || created.elapsed() >= self.limits.batch_duration()
     }
 
     #[inline(always)]
-    fn batch_is_full(&self, batch_size: usize) -> bool {
-        batch_size >= self.config.max_batch_size()
+    fn batch_is_full(&self, batch_size: usize, write_batch: &K::WriteBatch) -> bool {
+        batch_size >= self.limits.batch_size()
+            || write_batch.size_in_bytes() >= self.limits.batch_bytes()
     }
 
     #[inline(always)]
@@ -89,7 +110,7 @@ impl<K: Store, W: WriteCmd<K::StateSpace>> WriteWorker<K, W> {
     fn park(&self) {
         self.is_parked.store(true, Ordering::Relaxed);
         if !self.is_shutdown() && self.queue.is_empty() {
-            self.parker.park_timeout(self.config.max_batch_duration());
+            self.parker.park_timeout(self.limits.batch_duration());
         }
         self.is_parked.store(false, Ordering::Relaxed);
     }
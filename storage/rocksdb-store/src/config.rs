@@ -2,7 +2,9 @@ use rocksdb::{Options, SliceTransform, WriteOptions};
 use tap::Tap;
 
 /// Prefix length for keys that start with a u64 (batch_index or version).
-const U64_PREFIX_LEN: usize = size_of::<u64>();
+pub(crate) const U64_PREFIX_LEN: usize = size_of::<u64>();
+/// Prefix length for keys that start with a blake3 hash.
+pub(crate) const HASH_PREFIX_LEN: usize = 32;
 
 pub trait Config: Send + Sync + 'static {
     fn db_opts() -> Options {
@@ -58,6 +60,23 @@ pub trait Config: Send + Sync + 'static {
         })
     }
 
+    fn cf_data_chunk_opts() -> Options {
+        Options::default().tap_mut(|o| {
+            // Data chunk keys are: owner version (u64 big-endian) || resource_id || chunk index
+            // Enable prefix iteration by owner version.
+            o.set_prefix_extractor(SliceTransform::create_fixed_prefix(U64_PREFIX_LEN));
+        })
+    }
+
+    fn cf_data_content_opts() -> Options {
+        Options::default().tap_mut(|o| {
+            // Content keys are: hash (32 bytes), followed by version (u64 big-endian) ||
+            // resource_id for the references to it.
+            // Enable prefix iteration by hash.
+            o.set_prefix_extractor(SliceTransform::create_fixed_prefix(HASH_PREFIX_LEN));
+        })
+    }
+
     fn cf_latest_ptr_opts() -> Options {
         Options::default()
     }
@@ -70,6 +89,31 @@ pub trait Config: Send + Sync + 'static {
         })
     }
 
+    fn cf_history_ptr_opts() -> Options {
+        // HistoryPtr keys are: resource_id || inverted batch_index (u64 big-endian)
+        // Resource ids vary in length, so prefix iteration relies on an upper bound instead.
+        Options::default()
+    }
+
+    fn cf_tree_node_opts() -> Options {
+        // TreeNode keys are: node_hash (32 bytes), only ever read by point lookups.
+        Options::default()
+    }
+
+    fn cf_tree_root_opts() -> Options {
+        Options::default()
+    }
+
+    fn cf_tree_key_opts() -> Options {
+        Options::default()
+    }
+
+    fn cf_index_opts() -> Options {
+        // Index keys are: index_id (u16 big-endian) || key_len (u32 big-endian) || key || resource_id
+        // Keys vary in length, so prefix iteration relies on an upper bound instead.
+        Options::default()
+    }
+
     fn cf_metas_opts() -> Options {
         Options::default()
     }
@@ -1,8 +1,14 @@
 mod config;
+mod read;
+mod read_only_store;
 mod state_space_ext;
 mod store;
 mod write_batch;
 
 pub use config::{Config, DefaultConfig};
+pub use read_only_store::RocksDbReadOnlyStore;
+// Re-exported so that custom state spaces can build column family options.
+pub use rocksdb;
+pub use state_space_ext::StateSpaceExt;
 pub use store::RocksDbStore;
 pub use write_batch::WriteBatch;
@@ -0,0 +1,74 @@
+use rocksdb::{DB, DBIteratorWithThreadMode, Direction, IteratorMode};
+use vprogs_storage_types::{PrefixIterator, prefix_upper_bound};
+
+use crate::{
+    config::Config,
+    state_space_ext::{StateSpaceExt, cf_handle},
+};
+
+/// Point lookup shared by the read-write and the read-only store.
+pub(crate) fn get<C: Config, T: StateSpaceExt<C>>(db: &DB, ns: &T, key: &[u8]) -> Option<Vec<u8>> {
+    match db.get_cf(cf_handle::<C, T>(db, ns), key) {
+        Ok(res) => res,
+        Err(e) => panic!("rocksdb get failed: {e}"),
+    }
+}
+
+/// Prefix iteration shared by the read-write and the read-only store.
+pub(crate) fn prefix_iter<'a, C: Config, T: StateSpaceExt<C>>(
+    db: &'a DB,
+    ns: &T,
+    prefix: &[u8],
+) -> PrefixIterator<'a> {
+    let cf = cf_handle::<C, T>(db, ns);
+
+    let mut read_opts = rocksdb::ReadOptions::default();
+    match ns.prefix_len() {
+        // Ensure iteration stops when keys no longer share the extracted prefix.
+        Some(prefix_len) if prefix.len() >= prefix_len => read_opts.set_prefix_same_as_start(true),
+        // The prefix extractor must not see keys shorter than its prefix, so seek in total order.
+        _ => read_opts.set_total_order_seek(true),
+    }
+    // Bound the iteration explicitly, as the extracted prefix may be shorter than `prefix`.
+    if let Some(upper_bound) = prefix_upper_bound(prefix) {
+        read_opts.set_iterate_upper_bound(upper_bound);
+    }
+
+    let mode = IteratorMode::From(prefix, Direction::Forward);
+    let iter = db.iterator_cf_opt(cf, read_opts, mode);
+    Box::new(RocksDbPrefixIter { inner: iter })
+}
+
+/// Iteration from a key to the end of a column family, shared by the read-write and the read-only
+/// store.
+pub(crate) fn iter_from<'a, C: Config, T: StateSpaceExt<C>>(
+    db: &'a DB,
+    ns: &T,
+    from: &[u8],
+) -> PrefixIterator<'a> {
+    let cf = cf_handle::<C, T>(db, ns);
+
+    let mut read_opts = rocksdb::ReadOptions::default();
+    // Iterate across prefixes, ignoring the prefix extractor.
+    read_opts.set_total_order_seek(true);
+
+    let mode = IteratorMode::From(from, Direction::Forward);
+    let iter = db.iterator_cf_opt(cf, read_opts, mode);
+    Box::new(RocksDbPrefixIter { inner: iter })
+}
+
+/// Wrapper around RocksDB's prefix iterator that unwraps Results into panics.
+struct RocksDbPrefixIter<'a> {
+    inner: DBIteratorWithThreadMode<'a, DB>,
+}
+
+impl Iterator for RocksDbPrefixIter<'_> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|res| match res {
+            Ok((k, v)) => (k.to_vec(), v.to_vec()),
+            Err(e) => panic!("rocksdb prefix iteration failed: {e}"),
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::{marker::PhantomData, path::Path, sync::Arc};
+
+use rocksdb::DB;
+use vprogs_state_space::StateSpace;
+use vprogs_storage_types::{PrefixIterator, ReadStore};
+
+use crate::{
+    config::{Config, DefaultConfig},
+    read,
+    state_space_ext::StateSpaceExt,
+};
+
+/// A read-only view of a database that is owned by another process, intended for external tools
+/// such as inspectors, exporters or monitoring.
+///
+/// It only implements [`ReadStore`], so it can be handed to anything that reads state, but can
+/// never be used to write. Two modes are supported:
+///
+/// - [`open_read_only`](Self::open_read_only) opens a static snapshot of the database as of the
+///   time of opening.
+/// - [`open_secondary`](Self::open_secondary) opens a secondary instance that can follow the
+///   primary via [`try_catch_up_with_primary`](Self::try_catch_up_with_primary).
+pub struct RocksDbReadOnlyStore<C: Config = DefaultConfig, T: StateSpaceExt<C> = StateSpace> {
+    db: Arc<DB>,
+    is_secondary: bool,
+    _marker: PhantomData<(C, T)>,
+}
+
+impl<C: Config, T: StateSpaceExt<C>> RocksDbReadOnlyStore<C, T> {
+    /// Opens the database at `path` in read-only mode.
+    ///
+    /// The view does not observe writes that the primary performs after opening.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Self {
+        let db = match DB::open_cf_descriptors_read_only(
+            &C::db_opts(),
+            path,
+            T::all_descriptors(),
+            false,
+        ) {
+            Ok(db) => db,
+            Err(e) => panic!("failed to open RocksDB in read-only mode: {e}"),
+        };
+
+        Self { db: Arc::new(db), is_secondary: false, _marker: PhantomData }
+    }
+
+    /// Opens a secondary instance of the database at `primary_path`, keeping its own info logs
+    /// at `secondary_path`.
+    pub fn open_secondary<P: AsRef<Path>, S: AsRef<Path>>(
+        primary_path: P,
+        secondary_path: S,
+    ) -> Self {
+        let mut db_opts = C::db_opts();
+        // Secondary instances must be able to keep all table files of the primary open.
+        db_opts.set_max_open_files(-1);
+
+        let db = match DB::open_cf_descriptors_as_secondary(
+            &db_opts,
+            primary_path.as_ref(),
+            secondary_path.as_ref(),
+            T::all_descriptors(),
+        ) {
+            Ok(db) => db,
+            Err(e) => panic!("failed to open RocksDB as secondary: {e}"),
+        };
+
+        Self { db: Arc::new(db), is_secondary: true, _marker: PhantomData }
+    }
+
+    /// Replays the writes the primary performed since opening (or the previous catch-up).
+    ///
+    /// Panics if the store was not opened with [`open_secondary`](Self::open_secondary).
+    pub fn try_catch_up_with_primary(&self) {
+        assert!(self.is_secondary, "only secondary instances can catch up with the primary");
+
+        if let Err(e) = self.db.try_catch_up_with_primary() {
+            panic!("rocksdb catch-up with primary failed: {e}");
+        }
+    }
+
+    pub fn is_secondary(&self) -> bool {
+        self.is_secondary
+    }
+}
+
+impl<C: Config, T: StateSpaceExt<C>> ReadStore for RocksDbReadOnlyStore<C, T> {
+    type StateSpace = T;
+
+    fn get(&self, ns: T, key: &[u8]) -> Option<Vec<u8>> {
+        read::get::<C, T>(&self.db, &ns, key)
+    }
+
+    fn prefix_iter(&self, ns: T, prefix: &[u8]) -> PrefixIterator<'_> {
+        read::prefix_iter::<C, T>(&self.db, &ns, prefix)
+    }
+
+    fn iter_from(&self, ns: T, from: &[u8]) -> PrefixIterator<'_> {
+        read::iter_from::<C, T>(&self.db, &ns, from)
+    }
+}
+
+impl<C: Config, T: StateSpaceExt<C>> Clone for RocksDbReadOnlyStore<C, T> {
+    fn clone(&self) -> Self {
+        Self { db: self.db.clone(), is_secondary: self.is_secondary, _marker: PhantomData }
+    }
+}
@@ -1,31 +1,115 @@
-use rocksdb::ColumnFamilyDescriptor;
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, DB, Options};
 use vprogs_state_space::StateSpace;
 
-use crate::config::{Config, DefaultConfig};
+use crate::config::{Config, DefaultConfig, HASH_PREFIX_LEN, U64_PREFIX_LEN};
 
-pub trait StateSpaceExt<C: Config = DefaultConfig> {
+/// Describes how a state-space type maps to RocksDB column families.
+///
+/// Every state space is backed by its own column family. Implement this trait for an
+/// application-defined type to use it as the state space of a
+/// [`RocksDbStore`](crate::RocksDbStore), e.g. to add column families for indexes or application
+/// metadata.
+pub trait StateSpaceExt<C: Config = DefaultConfig>: Sized + Send + Sync + 'static {
+    /// Returns all state spaces of this type.
+    fn all() -> Vec<Self>;
+
+    /// Returns the name of the column family backing this state space.
     fn cf_name(&self) -> &'static str;
-    fn all_descriptors() -> Vec<ColumnFamilyDescriptor>;
+
+    /// Returns the options of the column family backing this state space.
+    fn cf_opts(&self) -> Options;
+
+    /// Returns the length of the fixed prefix extractor configured in [`Self::cf_opts`], if any.
+    ///
+    /// Prefix iteration only relies on the extractor for prefixes that are at least this long and
+    /// seeks in total order otherwise.
+    fn prefix_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the descriptors of all column families, used when opening the database.
+    fn all_descriptors() -> Vec<ColumnFamilyDescriptor> {
+        Self::all()
+            .iter()
+            .map(|ns| ColumnFamilyDescriptor::new(ns.cf_name(), ns.cf_opts()))
+            .collect()
+    }
 }
 
 impl<C: Config> StateSpaceExt<C> for StateSpace {
+    fn all() -> Vec<Self> {
+        use StateSpace::*;
+        vec![
+            StateVersion,
+            StateVersionChunk,
+            StateContent,
+            StatePtrLatest,
+            StatePtrRollback,
+            StatePtrHistory,
+            StateTreeNode,
+            StateTreeRoot,
+            StateTreeKey,
+            StateIndex,
+            Metadata,
+        ]
+    }
+
     fn cf_name(&self) -> &'static str {
         match self {
             StateSpace::StateVersion => "data",
+            StateSpace::StateVersionChunk => "data_chunks",
+            StateSpace::StateContent => "data_content",
             StateSpace::StatePtrLatest => "latest_ptr",
             StateSpace::StatePtrRollback => "rollback_ptr",
+            StateSpace::StatePtrHistory => "history_ptr",
+            StateSpace::StateTreeNode => "tree_node",
+            StateSpace::StateTreeRoot => "tree_root",
+            StateSpace::StateTreeKey => "tree_key",
+            StateSpace::StateIndex => "index",
             StateSpace::Metadata => "metas",
         }
     }
 
-    fn all_descriptors() -> Vec<ColumnFamilyDescriptor> {
-        use StateSpace::*;
-        let cf_name = <StateSpace as StateSpaceExt<C>>::cf_name;
-        vec![
-            ColumnFamilyDescriptor::new(cf_name(&StateVersion), C::cf_data_opts()),
-            ColumnFamilyDescriptor::new(cf_name(&StatePtrLatest), C::cf_latest_ptr_opts()),
-            ColumnFamilyDescriptor::new(cf_name(&StatePtrRollback), C::cf_rollback_ptr_opts()),
-            ColumnFamilyDescriptor::new(cf_name(&Metadata), C::cf_metas_opts()),
-        ]
+    fn cf_opts(&self) -> Options {
+        match self {
+            StateSpace::StateVersion => C::cf_data_opts(),
+            StateSpace::StateVersionChunk => C::cf_data_chunk_opts(),
+            StateSpace::StateContent => C::cf_data_content_opts(),
+            StateSpace::StatePtrLatest => C::cf_latest_ptr_opts(),
+            StateSpace::StatePtrRollback => C::cf_rollback_ptr_opts(),
+            StateSpace::StatePtrHistory => C::cf_history_ptr_opts(),
+            StateSpace::StateTreeNode => C::cf_tree_node_opts(),
+            StateSpace::StateTreeRoot => C::cf_tree_root_opts(),
+            StateSpace::StateTreeKey => C::cf_tree_key_opts(),
+            StateSpace::StateIndex => C::cf_index_opts(),
+            StateSpace::Metadata => C::cf_metas_opts(),
+        }
+    }
+
+    fn prefix_len(&self) -> Option<usize> {
+        match self {
+            StateSpace::StateVersion
+            | StateSpace::StateVersionChunk
+            | StateSpace::StatePtrRollback => Some(U64_PREFIX_LEN),
+            StateSpace::StateContent => Some(HASH_PREFIX_LEN),
+            StateSpace::StatePtrLatest
+            | StateSpace::StatePtrHistory
+            | StateSpace::StateTreeNode
+            | StateSpace::StateTreeRoot
+            | StateSpace::StateTreeKey
+            | StateSpace::StateIndex
+            | StateSpace::Metadata => None,
+        }
+    }
+}
+
+/// Resolves the column family backing `ns`.
+pub(crate) fn cf_handle<'a, C: Config, T: StateSpaceExt<C>>(
+    db: &'a DB,
+    ns: &T,
+) -> &'a ColumnFamily {
+    match db.cf_handle(ns.cf_name()) {
+        Some(cf) => cf,
+        None => panic!("missing column family '{}'", ns.cf_name()),
     }
 }
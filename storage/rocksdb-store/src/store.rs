@@ -1,87 +1,67 @@
 use std::{marker::PhantomData, path::Path, sync::Arc};
 
-use rocksdb::{DB, DBIteratorWithThreadMode, Direction, IteratorMode};
+use rocksdb::DB;
 use vprogs_state_space::StateSpace;
 use vprogs_storage_types::{PrefixIterator, Store};
 
 use crate::{
     config::{Config, DefaultConfig},
+    read,
     state_space_ext::StateSpaceExt,
     write_batch::WriteBatch,
 };
 
-pub struct RocksDbStore<C: Config = DefaultConfig> {
+pub struct RocksDbStore<C: Config = DefaultConfig, T: StateSpaceExt<C> = StateSpace> {
     db: Arc<DB>,
     write_opts: Arc<rocksdb::WriteOptions>,
-    _marker: PhantomData<C>,
+    _marker: PhantomData<(C, T)>,
 }
 
-impl<C: Config> RocksDbStore<C> {
+impl<C: Config, T: StateSpaceExt<C>> RocksDbStore<C, T> {
     pub fn open<P: AsRef<Path>>(path: P) -> Self {
         let mut db_opts = C::db_opts();
         db_opts.create_if_missing(true);
         db_opts.create_missing_column_families(true);
 
         Self {
-            db: Arc::new(
-                match DB::open_cf_descriptors(
-                    &db_opts,
-                    path,
-                    <StateSpace as StateSpaceExt<C>>::all_descriptors(),
-                ) {
-                    Ok(db) => db,
-                    Err(e) => panic!("failed to open RocksDB: {e}"),
-                },
-            ),
+            db: Arc::new(match DB::open_cf_descriptors(&db_opts, path, T::all_descriptors()) {
+                Ok(db) => db,
+                Err(e) => panic!("failed to open RocksDB: {e}"),
+            }),
             write_opts: Arc::new(C::write_opts()),
             _marker: PhantomData,
         }
     }
-
-    fn cf(&self, ns: &StateSpace) -> &rocksdb::ColumnFamily {
-        let cf_name = <StateSpace as StateSpaceExt<C>>::cf_name;
-        match self.db.cf_handle(cf_name(ns)) {
-            Some(cf) => cf,
-            None => panic!("missing column family '{}'", cf_name(ns)),
-        }
-    }
 }
 
-impl<C: Config> Store for RocksDbStore<C> {
-    type StateSpace = StateSpace;
-    type WriteBatch = WriteBatch<C>;
+impl<C: Config, T: StateSpaceExt<C>> Store for RocksDbStore<C, T> {
+    type StateSpace = T;
+    type WriteBatch = WriteBatch<C, T>;
 
-    fn get(&self, state_space: StateSpace, key: &[u8]) -> Option<Vec<u8>> {
-        match self.db.get_cf(self.cf(&state_space), key) {
-            Ok(res) => res,
-            Err(e) => panic!("rocksdb get failed: {e}"),
-        }
+    fn get(&self, state_space: T, key: &[u8]) -> Option<Vec<u8>> {
+        read::get::<C, T>(&self.db, &state_space, key)
     }
 
-    fn write_batch(&self) -> WriteBatch<C> {
+    fn write_batch(&self) -> WriteBatch<C, T> {
         WriteBatch::new(self.db.clone())
     }
 
-    fn commit(&self, write_batch: WriteBatch<C>) {
+    fn commit(&self, write_batch: WriteBatch<C, T>) {
         if let Err(err) = self.db.write_opt(write_batch.into(), &self.write_opts) {
             panic!("rocksdb write-batch commit failed: {err}");
         }
     }
 
-    fn prefix_iter(&self, state_space: StateSpace, prefix: &[u8]) -> PrefixIterator<'_> {
-        let cf = self.cf(&state_space);
-
-        let mut read_opts = rocksdb::ReadOptions::default();
-        // Ensure iteration stops when keys no longer share the prefix.
-        read_opts.set_prefix_same_as_start(true);
+    fn prefix_iter(&self, state_space: T, prefix: &[u8]) -> PrefixIterator<'_> {
+        read::prefix_iter::<C, T>(&self.db, &state_space, prefix)
+    }
 
-        let mode = IteratorMode::From(prefix, Direction::Forward);
-        let iter = self.db.iterator_cf_opt(cf, read_opts, mode);
-        Box::new(RocksDbPrefixIter { inner: iter })
+    fn iter_from(&self, state_space: T, from: &[u8]) -> PrefixIterator<'_> {
+        read::iter_from::<C, T>(&self.db, &state_space, from)
     }
 }
 
-impl<C: Config> Clone for RocksDbStore<C> {
+impl<C: Config, T: StateSpaceExt<C>> Clone for RocksDbStore<C, T> {
     fn clone(&self) -> Self {
         RocksDbStore {
             db: self.db.clone(),
@@ -90,19 +70,3 @@ impl<C: Config> Clone for RocksDbStore<C> {
         }
     }
 }
-
-/// Wrapper around RocksDB's prefix iterator that unwraps Results into panics.
-struct RocksDbPrefixIter<'a> {
-    inner: DBIteratorWithThreadMode<'a, DB>,
-}
-
-impl Iterator for RocksDbPrefixIter<'_> {
-    type Item = (Vec<u8>, Vec<u8>);
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|res| match res {
-            Ok((k, v)) => (k.to_vec(), v.to_vec()),
-            Err(e) => panic!("rocksdb prefix iteration failed: {e}"),
-        })
-    }
-}
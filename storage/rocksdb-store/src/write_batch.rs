@@ -5,43 +5,59 @@ use vprogs_state_space::StateSpace;
 
 use crate::{
     config::{Config, DefaultConfig},
-    state_space_ext::StateSpaceExt,
+    state_space_ext::{StateSpaceExt, cf_handle},
 };
 
-pub struct WriteBatch<C: Config = DefaultConfig> {
+/// The minimum length of the exclusive end key of [`delete_from`](WriteBatch::delete_from), which
+/// consists of `0xff` bytes only.
+///
+/// The end key sorts after every key that does not start with this many `0xff` bytes, which no key
+/// of a state space does in practice.
+const END_KEY_LEN: usize = 1024;
+
+pub struct WriteBatch<C: Config = DefaultConfig, T: StateSpaceExt<C> = StateSpace> {
     db: Arc<DB>,
     inner: rocksdb::WriteBatch,
-    _marker: PhantomData<C>,
+    _marker: PhantomData<(C, T)>,
 }
 
-impl<C: Config> WriteBatch<C> {
+impl<C: Config, T: StateSpaceExt<C>> WriteBatch<C, T> {
     pub(crate) fn new(db: Arc<DB>) -> Self {
         Self { db, inner: rocksdb::WriteBatch::default(), _marker: PhantomData }
     }
 }
 
-impl<C: Config> vprogs_storage_types::WriteBatch for WriteBatch<C> {
-    type StateSpace = StateSpace;
+impl<C: Config, T: StateSpaceExt<C>> vprogs_storage_types::WriteBatch for WriteBatch<C, T> {
+    type StateSpace = T;
+
+    fn put(&mut self, ns: T, key: &[u8], value: &[u8]) {
+        self.inner.put_cf(cf_handle::<C, T>(&self.db, &ns), key, value)
+    }
+
+    fn delete(&mut self, ns: T, key: &[u8]) {
+        self.inner.delete_cf(cf_handle::<C, T>(&self.db, &ns), key)
+    }
+
+    fn delete_range(&mut self, ns: T, from: &[u8], to: &[u8]) {
+        self.inner.delete_range_cf(cf_handle::<C, T>(&self.db, &ns), from, to)
+    }
 
-    fn put(&mut self, ns: StateSpace, key: &[u8], value: &[u8]) {
-        let cf_handle = <StateSpace as StateSpaceExt<C>>::cf_name(&ns);
-        let Some(cf) = self.db.cf_handle(cf_handle) else {
-            panic!("missing column family '{}'", cf_handle)
-        };
-        self.inner.put_cf(cf, key, value)
+    /// Deletes the range from `from` up to a fixed end key of `0xff` bytes (see [`END_KEY_LEN`]),
+    /// as RocksDB only deletes ranges with an end key. Being independent of the stored keys, the
+    /// range also covers keys that are put earlier in the same batch or committed by others before
+    /// the batch.
+    fn delete_from(&mut self, ns: T, from: &[u8]) {
+        let to = vec![0xff; END_KEY_LEN.max(from.len() + 1)];
+        self.inner.delete_range_cf(cf_handle::<C, T>(&self.db, &ns), from, &to)
     }
 
-    fn delete(&mut self, ns: StateSpace, key: &[u8]) {
-        let cf_handle = <StateSpace as StateSpaceExt<C>>::cf_name(&ns);
-        let Some(cf) = self.db.cf_handle(cf_handle) else {
-            panic!("missing column family '{}'", cf_handle)
-        };
-        self.inner.delete_cf(cf, key)
+    fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
     }
 }
 
-impl<C: Config> From<WriteBatch<C>> for rocksdb::WriteBatch {
-    fn from(value: WriteBatch<C>) -> Self {
+impl<C: Config, T: StateSpaceExt<C>> From<WriteBatch<C, T>> for rocksdb::WriteBatch {
+    fn from(value: WriteBatch<C, T>) -> Self {
         value.inner
     }
 }
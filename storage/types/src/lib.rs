@@ -1,7 +1,9 @@
+mod prefix;
 mod read_store;
 mod store;
 mod write_batch;
 
+pub use prefix::prefix_upper_bound;
 pub use read_store::ReadStore;
 pub use store::{PrefixIterator, Store};
 pub use write_batch::WriteBatch;
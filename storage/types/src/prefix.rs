@@ -0,0 +1,8 @@
+/// Returns the smallest key that is greater than every key starting with `prefix`, or `None` if no
+/// such key exists (the prefix is empty or consists of `0xff` bytes only).
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|&byte| byte != u8::MAX)?;
+    let mut upper_bound = prefix[..=end].to_vec();
+    upper_bound[end] += 1;
+    Some(upper_bound)
+}
@@ -1,8 +1,32 @@
-use crate::Store;
+use crate::{PrefixIterator, Store};
 
 pub trait ReadStore {
     type StateSpace;
     fn get(&self, ns: Self::StateSpace, key: &[u8]) -> Option<Vec<u8>>;
+
+    /// Iterate over all key-value pairs in the given state space whose keys start with the
+    /// specified prefix (see [`Store::prefix_iter`]).
+    fn prefix_iter(&self, ns: Self::StateSpace, prefix: &[u8]) -> PrefixIterator<'_>;
+
+    /// Iterate over all key-value pairs in the given state space whose keys are greater than or
+    /// equal to `from` (see [`Store::iter_from`]).
+    fn iter_from(&self, ns: Self::StateSpace, from: &[u8]) -> PrefixIterator<'_>;
+
+    /// Iterate over all key-value pairs in the given state space whose keys start with the
+    /// specified prefix and are greater than or equal to `from` (see [`Store::prefix_iter_from`]).
+    fn prefix_iter_from(
+        &self,
+        ns: Self::StateSpace,
+        prefix: &[u8],
+        from: &[u8],
+    ) -> PrefixIterator<'_> {
+        if from <= prefix {
+            return self.prefix_iter(ns, prefix);
+        }
+
+        let prefix = prefix.to_vec();
+        Box::new(self.iter_from(ns, from).take_while(move |(key, _)| key.starts_with(&prefix)))
+    }
 }
 
 impl<T: Store> ReadStore for T {
@@ -11,4 +35,21 @@ impl<T: Store> ReadStore for T {
     fn get(&self, ns: Self::StateSpace, key: &[u8]) -> Option<Vec<u8>> {
         Store::get(self, ns, key)
     }
+
+    fn prefix_iter(&self, ns: Self::StateSpace, prefix: &[u8]) -> PrefixIterator<'_> {
+        Store::prefix_iter(self, ns, prefix)
+    }
+
+    fn iter_from(&self, ns: Self::StateSpace, from: &[u8]) -> PrefixIterator<'_> {
+        Store::iter_from(self, ns, from)
+    }
+
+    fn prefix_iter_from(
+        &self,
+        ns: Self::StateSpace,
+        prefix: &[u8],
+        from: &[u8],
+    ) -> PrefixIterator<'_> {
+        Store::prefix_iter_from(self, ns, prefix, from)
+    }
 }
@@ -20,4 +20,38 @@ pub trait Store: Send + Sync + 'static {
     /// # Panics
     /// Panics if the underlying storage operation fails.
     fn prefix_iter(&self, state_space: Self::StateSpace, prefix: &[u8]) -> PrefixIterator<'_>;
+
+    /// Iterate over all key-value pairs in the given state space whose keys are greater than or
+    /// equal to `from`.
+    ///
+    /// The iterator yields `(key, value)` pairs in lexicographic order of keys until the end of
+    /// the state space. An empty `from` iterates the whole state space.
+    ///
+    /// # Panics
+    /// Panics if the underlying storage operation fails.
+    fn iter_from(&self, state_space: Self::StateSpace, from: &[u8]) -> PrefixIterator<'_>;
+
+    /// Iterate over all key-value pairs in the given state space whose keys start with the
+    /// specified prefix and are greater than or equal to `from`.
+    ///
+    /// The iterator yields `(key, value)` pairs in lexicographic order of keys. By default, this
+    /// seeks to `from` and stops at the first key that does not match the prefix.
+    ///
+    /// # Panics
+    /// Panics if the underlying storage operation fails.
+    fn prefix_iter_from(
+        &self,
+        state_space: Self::StateSpace,
+        prefix: &[u8],
+        from: &[u8],
+    ) -> PrefixIterator<'_> {
+        if from <= prefix {
+            return self.prefix_iter(state_space, prefix);
+        }
+
+        let prefix = prefix.to_vec();
+        Box::new(
+            self.iter_from(state_space, from).take_while(move |(key, _)| key.starts_with(&prefix)),
+        )
+    }
 }
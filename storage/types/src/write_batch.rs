@@ -1,5 +1,28 @@
+use crate::prefix_upper_bound;
+
 pub trait WriteBatch {
     type StateSpace;
     fn put(&mut self, ns: Self::StateSpace, key: &[u8], value: &[u8]);
     fn delete(&mut self, ns: Self::StateSpace, key: &[u8]);
+
+    /// Deletes all keys in the half-open range `[from, to)`.
+    fn delete_range(&mut self, ns: Self::StateSpace, from: &[u8], to: &[u8]);
+
+    /// Deletes all keys that are greater than or equal to `from`, up to the end of the state
+    /// space.
+    fn delete_from(&mut self, ns: Self::StateSpace, from: &[u8]);
+
+    /// Deletes all keys that start with `prefix`.
+    ///
+    /// Prefixes without an upper bound (see [`prefix_upper_bound`]) cover the end of the state
+    /// space and are deleted through [`delete_from`](Self::delete_from).
+    fn delete_prefix(&mut self, ns: Self::StateSpace, prefix: &[u8]) {
+        match prefix_upper_bound(prefix) {
+            Some(upper_bound) => self.delete_range(ns, prefix, &upper_bound),
+            None => self.delete_from(ns, prefix),
+        }
+    }
+
+    /// Returns the approximate number of bytes the batch will write when committed.
+    fn size_in_bytes(&self) -> usize;
 }
@@ -1,5 +1,7 @@
 use std::io::Error;
 
+use vprogs_state_typed::TypedStateError;
+use vprogs_state_version::CorruptionError;
 use vprogs_transaction_runtime_address::Address;
 
 use crate::VmError::SerializationError;
@@ -9,6 +11,7 @@ pub enum VmError {
     DataNotFound(Address),
     MissingMutCapability(Address),
     SerializationError(Error),
+    CorruptedState(CorruptionError),
 }
 
 impl From<Error> for VmError {
@@ -17,4 +20,14 @@ impl From<Error> for VmError {
     }
 }
 
+impl From<TypedStateError> for VmError {
+    fn from(err: TypedStateError) -> Self {
+        match err {
+            TypedStateError::CorruptedState(err) => VmError::CorruptedState(err),
+            TypedStateError::Decode(err) => SerializationError(err),
+            TypedStateError::Pruned(_) => VmError::Generic,
+        }
+    }
+}
+
 pub type VmResult<T> = Result<T, VmError>;
@@ -1,9 +1,9 @@
 use std::collections::{HashMap, HashSet};
 
-use borsh::BorshDeserialize;
 use vprogs_core_types::AccessMetadata;
 use vprogs_scheduling_scheduler::{AccessHandle, VmInterface};
 use vprogs_state_space::StateSpace;
+use vprogs_state_typed::{BorshCodec, TypedResource};
 use vprogs_storage_types::Store;
 use vprogs_transaction_runtime_address::Address;
 use vprogs_transaction_runtime_authenticated_data::AuthenticatedData;
@@ -56,16 +56,14 @@ where
             match handle.access_metadata().id() {
                 // TODO: VALIDATE PROGRAM WITH VM?
                 ObjectId::Program(address) => {
-                    let program = Program::deserialize(&mut handle.data().as_slice())?;
+                    let program =
+                        handle.value::<ProgramState>()?.ok_or(VmError::DataNotFound(address))?;
 
                     self.loaded_programs.insert(address, program);
                 }
-                // TODO: VERIFY CHECKSUMS, ETC.
                 ObjectId::Data(address) => {
-                    // Storage format: Lock | Data (serialized sequentially with Borsh)
-                    let mut reader = handle.data().as_slice();
-                    let lock = Lock::deserialize(&mut reader)?;
-                    let data = Data::deserialize(&mut reader)?;
+                    let (lock, data) =
+                        handle.value::<DataState>()?.ok_or(VmError::DataNotFound(address))?;
                     let mut_cap = lock.unlock(self);
 
                     self.loaded_data.insert(address, AuthenticatedData::new(data, mut_cap));
@@ -101,5 +99,23 @@ where
     }
 }
 
+/// The state of program objects.
+struct ProgramState;
+
+impl TypedResource for ProgramState {
+    type ResourceId = ObjectId;
+    type Value = Program;
+    type Codec = BorshCodec;
+}
+
+/// The state of data objects, stored as `Lock | Data` (serialized sequentially with Borsh).
+struct DataState;
+
+impl TypedResource for DataState {
+    type ResourceId = ObjectId;
+    type Value = (Lock, Data);
+    type Codec = BorshCodec;
+}
+
 mod auth_context;
 mod data_context;